@@ -0,0 +1,222 @@
+//! Generates the `memory.x` linker script for the selected board
+//!
+//! The board is selected by a cargo feature (`stm32f401re` if none given):
+//! - `stm32f401re`, Nucleo STM32F401RE, 512K flash, 96K RAM
+//! - `stm32f411re`, Nucleo STM32F411RE, 512K flash, 128K RAM
+//! - `qemu`, the QEMU `netduinoplus2` board (STM32F405RG), 1M flash, 128K RAM
+//!
+//! Optional regions, each by a cargo feature:
+//! - `bootloader`, flash sectors 0 and 1 (32K) are left to a bootloader, the
+//!   application starts after it (the bootloader must set VTOR accordingly)
+//! - `config-sector`, a flash sector kept for persistent configuration, sector 2
+//!   (16K) with a bootloader, else the last sector
+//! - `noinit`, a RAM region at the end of RAM holding the `.noinit` section,
+//!   which is not touched by the startup code (and thus survives a reset)
+//!
+//! With the `trace-deferred` feature, the `.trace` section (the interned format
+//! strings of `trace!`) is placed at address 1 (not 0, the first string would be
+//! a null reference), and not loaded.
+//!
+//! The application regions `FLASH` and `RAM` exclude the optional regions,
+//! so the linker fails the build if the image overflows into them (or overflows
+//! the part), with "region `FLASH' overflowed by N bytes".
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+const K: u32 = 1024;
+
+const FLASH_ORIGIN: u32 = 0x0800_0000;
+const RAM_ORIGIN: u32 = 0x2000_0000;
+
+// flash sectors 0 and 1
+const BOOTLOADER_SECTORS: usize = 2;
+// size of the `.noinit` region
+const NOINIT_SIZE: u32 = 1024;
+
+struct Board {
+    feature: &'static str,
+    name: &'static str,
+    ram: u32,
+    // flash sector sizes, RM0368 3.3
+    sectors: &'static [u32],
+}
+
+impl Board {
+    fn flash(&self) -> u32 {
+        self.sectors.iter().sum()
+    }
+
+    fn sector_start(&self, n: usize) -> u32 {
+        FLASH_ORIGIN + self.sectors[..n].iter().sum::<u32>()
+    }
+}
+
+// STM32F401xE/STM32F411xE, 512K
+const SECTORS_512K: &[u32] = &[
+    16 * K,
+    16 * K,
+    16 * K,
+    16 * K,
+    64 * K,
+    128 * K,
+    128 * K,
+    128 * K,
+];
+
+// STM32F405xG, 1M
+const SECTORS_1M: &[u32] = &[
+    16 * K,
+    16 * K,
+    16 * K,
+    16 * K,
+    64 * K,
+    128 * K,
+    128 * K,
+    128 * K,
+    128 * K,
+    128 * K,
+    128 * K,
+    128 * K,
+];
+
+const BOARDS: &[Board] = &[
+    Board {
+        feature: "STM32F401RE",
+        name: "STM32F401RE",
+        ram: 96 * K,
+        sectors: SECTORS_512K,
+    },
+    Board {
+        feature: "STM32F411RE",
+        name: "STM32F411RE",
+        ram: 128 * K,
+        sectors: SECTORS_512K,
+    },
+    Board {
+        feature: "QEMU",
+        name: "QEMU netduinoplus2 (STM32F405RG)",
+        ram: 128 * K,
+        sectors: SECTORS_1M,
+    },
+];
+
+fn feature(name: &str) -> bool {
+    env::var_os(format!("CARGO_FEATURE_{}", name)).is_some()
+}
+
+fn main() {
+    let selected: Vec<&Board> = BOARDS.iter().filter(|b| feature(b.feature)).collect();
+
+    let board = match selected.as_slice() {
+        [] => &BOARDS[0],
+        [board] => *board,
+        _ => panic!("select at most one of the `stm32f401re`, `stm32f411re` and `qemu` features"),
+    };
+
+    let bootloader = feature("BOOTLOADER");
+    let config_sector = feature("CONFIG_SECTOR");
+    let noinit = feature("NOINIT");
+    let trace = feature("TRACE_DEFERRED");
+
+    let mut flash_start = FLASH_ORIGIN;
+    let mut flash_end = FLASH_ORIGIN + board.flash();
+    let mut ram_end = RAM_ORIGIN + board.ram;
+
+    let mut regions = String::new();
+    let mut symbols = String::new();
+
+    if bootloader {
+        flash_start = board.sector_start(BOOTLOADER_SECTORS);
+        regions += &region("BOOTLOADER", FLASH_ORIGIN, flash_start - FLASH_ORIGIN);
+    }
+
+    if config_sector {
+        // the sector following the bootloader, or else the last sector
+        let n = if bootloader {
+            BOOTLOADER_SECTORS
+        } else {
+            board.sectors.len() - 1
+        };
+        let start = board.sector_start(n);
+        regions += &region("CONFIG", start, board.sectors[n]);
+        symbols += "_config_start = ORIGIN(CONFIG);\n";
+        symbols += "_config_end = ORIGIN(CONFIG) + LENGTH(CONFIG);\n";
+        if bootloader {
+            flash_start += board.sectors[n];
+        } else {
+            flash_end = start;
+        }
+    }
+
+    if noinit {
+        ram_end -= NOINIT_SIZE;
+        regions += &region("NOINIT", ram_end, NOINIT_SIZE);
+    }
+
+    let mut memory_x = format!(
+        "/* Linker script for the {} (generated by build.rs) */\n",
+        board.name
+    );
+    memory_x += "MEMORY\n{\n";
+    memory_x += &region("FLASH", flash_start, flash_end - flash_start);
+    memory_x += &region("RAM", RAM_ORIGIN, ram_end - RAM_ORIGIN);
+    memory_x += &regions;
+    memory_x += "}\n";
+
+    if !symbols.is_empty() {
+        memory_x += "\n";
+        memory_x += &symbols;
+    }
+
+    if noinit {
+        memory_x += "
+/* not initialized by the startup code (preserved over reset) */
+SECTIONS
+{
+  .noinit (NOLOAD) : ALIGN(4)
+  {
+    _snoinit = .;
+    *(.noinit .noinit.*);
+    . = ALIGN(4);
+    _enoinit = .;
+  } > NOINIT
+} INSERT AFTER .bss;
+";
+    }
+
+    if trace {
+        memory_x += "
+/* interned format strings, the address of a string is its id (see src/trace.rs),
+   from 1, a static at address 0 would be a null reference */
+SECTIONS
+{
+  .trace 1 (INFO) :
+  {
+    *(.trace .trace.*);
+  }
+}
+";
+    }
+
+    // put `memory.x` in our output directory and make sure it's on the linker search path
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    File::create(out.join("memory.x"))
+        .unwrap()
+        .write_all(memory_x.as_bytes())
+        .unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+fn region(name: &str, origin: u32, length: u32) -> String {
+    format!(
+        "  {:<10} : ORIGIN = 0x{:08X}, LENGTH = {}K\n",
+        name,
+        origin,
+        length / K
+    )
+}
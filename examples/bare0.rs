@@ -15,57 +15,36 @@
 // no standard main, we declare main using [entry]
 #![no_main]
 
-// Panic handler, for textual output using semihosting
-use panic_semihosting as _;
+// Panic handler, for textual output using semihosting (`panic-semihosting` feature)
+use app as _;
 
 // import entry point
 use cortex_m_rt::entry;
 
+use app::global::VolatileGlobal;
+
 // a constant (cannot be changed at run-time)
 const X_INIT: u32 = 10;
 // const X_INIT: u32 = core::u32::MAX;
 
-// global mutable variables (changed using unsafe code)
-static mut X: u32 = X_INIT;
-static mut Y: u32 = 0;
-
-#[inline(always)]
-fn read_x() -> u32 {
-    unsafe { core::ptr::read_volatile(X as *const _) }
-}
-
-#[inline(always)]
-fn write_x(val: u32) {
-    unsafe { core::ptr::write_volatile(X as *mut _, val); }
-}
-
-#[inline(always)]
-fn read_y() -> u32 {
-    unsafe { core::ptr::read_volatile(Y as *const _) }
-}
-
-#[inline(always)]
-fn write_y(val: u32) {
-    unsafe { core::ptr::write_volatile(Y as *mut _, val); }
-}
+// global variables, changed in safe code (atomically, see `src/global.rs`)
+// (previously `static mut X: u32`, read and written by `unsafe` code)
+static X: VolatileGlobal<u32> = VolatileGlobal::new(X_INIT);
+static Y: VolatileGlobal<u32> = VolatileGlobal::new(0);
 
 #[entry]
 fn main() -> ! {
     // local mutable variable (changed in safe code)
-    let mut x = unsafe { X };
+    let mut x = X.get();
 
     loop {
         x = x.wrapping_add(1);
-        unsafe {
-            write_x(read_x().wrapping_add(1));
-            write_y(read_x());
-            //X += 1;
-            //Y = X;
+        X.set(X.get().wrapping_add(1));
+        Y.set(X.get());
+        //X += 1;
+        //Y = X;
 
-            let _ = core::ptr::read_volatile(&X);
-            let _ = core::ptr::read_volatile(&Y);
-            assert!(x == X && X == Y);
-        }
+        assert!(x == X.get() && X.get() == Y.get());
     }
 }
 
@@ -73,7 +52,7 @@ fn main() -> ! {
 //
 // 0. Compile/build and run the example in debug (dev) mode.
 //
-//    > cargo run --example bare0
+//    > cargo run --example bare0 --features panic-semihosting
 //    (or use vscode)
 //
 // 1. Run the program in the debugger, let the program run for a while and
@@ -142,3 +121,6 @@ fn main() -> ! {
 //
 //    Commit your solution (bare0_6)
 //
+//    (`app::global::VolatileGlobal`, as now used for X and Y, is such an
+//    abstraction. Why is it safe, where `static mut` is not?)
+//
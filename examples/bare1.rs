@@ -10,7 +10,7 @@
 #![no_main]
 #![no_std]
 
-use panic_itm as _;
+use app as _;
 
 use cortex_m_rt::entry;
 
@@ -49,7 +49,7 @@ fn main() -> ! {
 //
 // 1. Build and run the application
 //
-//    > cargo run --example bare1
+//    > cargo run --example bare1 --features panic-itm
 //    (or use the `itm fifo (debug)` or the `itm internal (debug)` launch configuration.)
 //
 //    Make sure you have followed the instructions for fifo `ITM` tracing accordingly.
@@ -3,13 +3,17 @@
 //! What it covers:
 //! - Priority based scheduling
 //! - Message passing
+//! - Deferred formatting trace
+//! - Command line interpreter
 
 #![no_main]
 #![no_std]
 
-extern crate panic_halt;
+use app as _;
 
-use cortex_m::{asm, iprintln};
+use app::cli::{Blink, Cli, BLINK};
+use app::trace;
+use cortex_m::{asm, iprintln, peripheral::DWT};
 
 extern crate stm32f4xx_hal as hal;
 use crate::hal::prelude::*;
@@ -25,6 +29,7 @@ pub enum Error {
     RingBufferOverflow,
     UsartSendOverflow,
     UsartReceiveOverflow,
+    CliOverflow,
 }
 
 #[app(device = hal::stm32, peripherals = true)]
@@ -34,6 +39,7 @@ const APP: () = {
         TX: Tx<hal::stm32::USART2>,
         RX: Rx<hal::stm32::USART2>,
         ITM: ITM,
+        CLI: Cli<Blink>,
     }
     // init runs in an interrupt free section>
     #[init]
@@ -44,6 +50,10 @@ const APP: () = {
         let stim = &mut core.ITM.stim[0];
         iprintln!(stim, "bare10");
 
+        // the cycle counter, for measuring the cost of tracing
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
         let rcc = device.RCC.constrain();
 
         // 16 MHz (default, all clocks)
@@ -75,6 +85,9 @@ const APP: () = {
 
             // For debugging
             ITM: core.ITM,
+
+            // Command line interpreter
+            CLI: Cli::new(BLINK),
         }
     }
 
@@ -88,8 +101,12 @@ const APP: () = {
 
     #[task(priority = 1, resources = [ITM])]
     fn trace_data(cx: trace_data::Context, byte: u8) {
-        let stim = &mut cx.resources.ITM.stim[0];
-        iprintln!(stim, "data {}", byte);
+        // cycles spent tracing the previous byte
+        static mut COST: u32 = 0;
+
+        let start = DWT::get_cycle_count();
+        trace!(cx.resources.ITM, "data {} ({} cycles)", byte, *COST);
+        *COST = DWT::get_cycle_count().wrapping_sub(start);
         // for _ in 0..10000 {
         //     asm::nop();
         // }
@@ -101,6 +118,17 @@ const APP: () = {
         iprintln!(stim, "{:?}", error);
     }
 
+    // command line interpreter, a command is parsed at the end of each line
+    #[task(priority = 1, capacity = 10, resources = [CLI, ITM])]
+    fn cli(cx: cli::Context, byte: u8) {
+        let stim = &mut cx.resources.ITM.stim[0];
+        match cx.resources.CLI.push(byte) {
+            Some(Ok(command)) => iprintln!(stim, "{:?}", command),
+            Some(Err(err)) => iprintln!(stim, "cli {:?}", err),
+            None => {}
+        }
+    }
+
     #[task(priority = 2, resources = [TX], spawn = [trace_error])]
     fn echo(cx: echo::Context, byte: u8) {
         let tx = cx.resources.TX;
@@ -110,13 +138,16 @@ const APP: () = {
         }
     }
 
-    #[task(binds = USART2, priority = 3, resources = [RX], spawn = [trace_data, trace_error, echo])]
+    #[task(binds = USART2, priority = 3, resources = [RX], spawn = [trace_data, trace_error, echo, cli])]
     fn usart2(cx: usart2::Context) {
         let rx = cx.resources.RX;
 
         match rx.read() {
             Ok(byte) => {
                 let _ = cx.spawn.echo(byte);
+                if cx.spawn.cli(byte).is_err() {
+                    let _ = cx.spawn.trace_error(Error::CliOverflow);
+                }
                 if cx.spawn.trace_data(byte).is_err() {
                     let _ = cx.spawn.trace_error(Error::RingBufferOverflow);
                 }
@@ -179,6 +210,9 @@ const APP: () = {
 //    Commit your answers (bare10_2)
 //
 // 3. Implement a command line interpreter as a new task.
+//    (The interpreter is given, see the `cli` task and `src/cli.rs`,
+//    the commands are traced, the blinking is up to you.)
+//
 //    It should:
 //    - have priority 1.
 //    - take a byte as an argument (passed from the USART2 interrupt).
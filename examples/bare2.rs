@@ -13,7 +13,7 @@
 #![no_main]
 #![no_std]
 
-use panic_halt as _;
+use app as _;
 
 use cortex_m::{iprintln, peripheral::DWT, Peripherals};
 use cortex_m_rt::entry;
@@ -45,12 +45,15 @@ fn main() -> ! {
     // pub fn get_cycle_count() -> u32
     //
     // Notice the difference in the function signature!
+    //
+    // (`app::time::Instant` holds a reading, converted to time at SYSCLK)
 
     let start = DWT::get_cycle_count();
     wait(1_000_000);
     let end = DWT::get_cycle_count();
 
     // notice all printing outside of the section to measure!
+    // (`app::bench!` runs it a number of times, see `bench.rs`)
     iprintln!(stim, "Start {:?}", start);
     iprintln!(stim, "End {:?}", end);
     iprintln!(stim, "Diff {:?}", end - start);
@@ -11,42 +11,21 @@
 #![no_std]
 #![no_main]
 
-extern crate panic_halt;
+use app as _;
 
 extern crate cortex_m;
 use cortex_m_rt::entry;
 
-// Peripheral addresses as constants
-#[rustfmt::skip]
-mod address {
-    pub const PERIPH_BASE: u32      = 0x40000000;
-    pub const AHB1PERIPH_BASE: u32  = PERIPH_BASE + 0x00020000;
-    pub const RCC_BASE: u32         = AHB1PERIPH_BASE + 0x3800;
-    pub const RCC_AHB1ENR: u32      = RCC_BASE + 0x30;
-    pub const GBPIA_BASE: u32       = AHB1PERIPH_BASE + 0x0000;
-    pub const GPIOA_MODER: u32      = GBPIA_BASE + 0x00;
-    pub const GPIOA_BSRR: u32       = GBPIA_BASE + 0x18;
-}
-
-use address::*;
+// The register level code lives in the library (`src/blinky.rs`), generic over
+// a `RegisterBus`. Here we use memory mapped IO, while the host tests run the
+// very same code on an in-memory bus and check the accesses.
+use app::blinky;
+use app::bus::Mmio;
 
 // see the Reference Manual RM0368 (www.st.com/resource/en/reference_manual/dm00096844.pdf)
 // rcc,     chapter 6
 // gpio,    chapter 8
 
-#[inline(always)]
-fn read_u32(addr: u32) -> u32 {
-     unsafe { core::ptr::read_volatile(addr as *const _) }
-    //core::ptr::read_volatile(addr as *const _)
-}
-
-#[inline(always)]
-fn write_u32(addr: u32, val: u32) {
-    unsafe {
-        core::ptr::write_volatile(addr as *mut _, val);
-    }
-}
-
 fn wait(i: u32) {
     for _ in 0..i {
         cortex_m::asm::nop(); // no operation (cannot be optimized out)
@@ -55,24 +34,22 @@ fn wait(i: u32) {
 
 #[entry]
 fn main() -> ! {
-    // power on GPIOA
-    let r = read_u32(RCC_AHB1ENR); // read
-    write_u32(RCC_AHB1ENR, r | 1); // set enable
+    // the RCC and GPIOA addresses used by `blinky` are valid on the STM32F401
+    let bus = unsafe { Mmio::new() };
 
-    // configure PA5 as output
-    let r = read_u32(GPIOA_MODER) & !(0b11 << (5 * 2)); // read and mask
-    write_u32(GPIOA_MODER, r | 0b01 << (5 * 2)); // set output mode
+    // power on GPIOA, and configure PA5 as output
+    blinky::init(&bus);
 
     // and alter the data output through the BSRR register
     // this is more efficient as the read register is not needed.
 
     loop {
         // set PA5 high
-        write_u32(GPIOA_BSRR, 1 << 5); // set bit, output hight (turn on led)
+        blinky::led_on(&bus); // set bit, output hight (turn on led)
         wait(10_000);
 
         // set PA5 low
-        write_u32(GPIOA_BSRR, 1 << (5 + 16)); // clear bit, output low (turn off led)
+        blinky::led_off(&bus); // clear bit, output low (turn off led)
         wait(10_000);
     }
 }
@@ -94,10 +71,11 @@ fn main() -> ! {
 //
 //    Commit your answers (bare4_1)
 //
-// 2. Comment out line 40 and uncomment line 41 (essentially omitting the `unsafe`)
+// 2. In `src/bus.rs`, replace the body of `Mmio::read_u32` (essentially omitting the `unsafe`)
 //
-//    //unsafe { core::ptr::read_volatile(addr as *const _) }
-//    core::ptr::read_volatile(addr as *const _)
+//    unsafe { ptr::read_volatile(addr as *const _) }
+//    by
+//    ptr::read_volatile(addr as *const _)
 //
 //    What was the error message and explain why.
 //
@@ -122,10 +100,10 @@ fn main() -> ! {
 //
 //    --> It allow to avoid memory conflict, to avoid two command to access the same register in case of a read_volatile failure
 //
-//    Give an example in the above code, where reordering might make things go horribly wrong
+//    Give an example in `blinky::init`, where reordering might make things go horribly wrong
 //    (hint, accessing a peripheral not being powered...)
 //
-//    --> Between lines 59 and 60, it could make a read and a write on the same time on the same register if the read_volatile fail
+//    --> Between the AHB1ENR and MODER accesses, it could make a read and a write on the same time on the same register if the read_volatile fail
 //
 //    Without the non-reordering property of `write_volatile/read_volatile` could that happen in theory
 //    (argue from the point of data dependencies).
@@ -10,7 +10,7 @@
 #![no_main]
 #![no_std]
 
-extern crate panic_halt;
+use app as _;
 
 use cortex_m::{iprintln, peripheral::itm::Stim};
 use cortex_m_rt::entry;
@@ -67,6 +67,7 @@ fn idle(stim: &mut Stim, rcc: RCC, gpioa: GPIOA) -> ! {
 // uses the DWT.CYCNT
 // doc: ARM trm_100166_0001_00_en.pdf, chapter 9.2
 // we use the `cortex-m` abstraction, as re-exported by the stm32f40x
+// (`app::time::delay` waits a `Duration` instead, at the current SYSCLK)
 fn wait_cycles(nr_cycles: u32) {
     let t = DWT::get_cycle_count().wrapping_add(nr_cycles);
     while (DWT::get_cycle_count().wrapping_sub(t) as i32) < 0 {}
@@ -75,6 +76,7 @@ fn wait_cycles(nr_cycles: u32) {
 // see the Reference Manual RM0368 (www.st.com/resource/en/reference_manual/dm00096844.pdf)
 // rcc,     chapter 6** your answer here **
 // gpio,    chapter 8
+// (`app::clocks::mco` does the same over a `RegisterBus`, see `clocks.rs`)
 fn clock_out(rcc: &RCC, gpioc: &GPIOC) {
     // output MCO2 to pin PC9
 
@@ -144,6 +146,9 @@ fn clock_out(rcc: &RCC, gpioc: &GPIOC) {
 // 2. Now connect an oscilloscope to PC9, which is set to
 //    output the MCO2.
 //
+//    (No oscilloscope? Wire PC9 to PA0 and measure it by `capture.rs`, a
+//    frequency counter referenced to the LSE crystal.)
+//
 //    What is the frequency of MCO2 read by the oscilloscope?
 //
 //    --> I don't have any oscilloscope in my flat, I guess 4Mhz according to the code
@@ -12,7 +12,7 @@
 #![no_main]
 #![no_std]
 
-extern crate panic_halt;
+use app as _;
 
 use cortex_m::iprintln;
 use cortex_m_rt::entry;
@@ -130,7 +130,8 @@ fn main() -> ! {
 //
 //    Commit your answers (bare7_1)
 //
-//    Tip: You may use `stm32cubemx` to get a graphical view for experimentation.
+//    Tip: You may use `stm32cubemx` to get a graphical view for experimentation,
+//    or the planner of `examples/clocks.rs` (`app::clocks`).
 //
 // 2. Now give the system with a valid clock, sysclk of 84 MHz.
 //
@@ -139,6 +140,8 @@ fn main() -> ! {
 //    Repeat the experiment bare6_2.
 //
 //    What is the frequency of MCO2 read by the oscilloscope.
+//    (No oscilloscope? `capture.rs` measures PC9 wired to PA0, and checks
+//    SYSCLK against the plan.)
 //
 //    ** your answer here **
 //
@@ -8,13 +8,15 @@
 //! - owned resources
 //! - peripheral access in RTFM
 //! - polling in `idle`
+//! - deferred formatting trace, and its cost in cycles
 
 #![no_main]
 #![no_std]
 
-extern crate panic_halt;
+use app as _;
 
-use cortex_m::iprintln;
+use app::trace;
+use cortex_m::{iprintln, peripheral::DWT};
 use nb::block;
 
 extern crate stm32f4xx_hal as hal;
@@ -42,6 +44,10 @@ const APP: () = {
         let stim = &mut core.ITM.stim[0];
         iprintln!(stim, "bare8");
 
+        // the cycle counter, for measuring the cost of tracing
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
         let rcc = device.RCC.constrain();
 
         // 16 MHz (default, all clocks)
@@ -76,16 +82,20 @@ const APP: () = {
     fn idle(cx: idle::Context) -> ! {
         let rx = cx.resources.RX;
         let tx = cx.resources.TX;
-        let stim = &mut cx.resources.ITM.stim[0];
+        let itm = cx.resources.ITM;
+        // cycles spent tracing the previous byte
+        let mut cost: u32 = 0;
 
         loop {
             match block!(rx.read()) {
                 Ok(byte) => {
-                    iprintln!(stim, "Ok {:?}", byte);
+                    let start = DWT::get_cycle_count();
+                    trace!(itm, "Ok {:?} ({} cycles)", byte, cost);
+                    cost = DWT::get_cycle_count().wrapping_sub(start);
                     tx.write(byte).unwrap();
                 }
                 Err(err) => {
-                    iprintln!(stim, "Error {:?}", err);
+                    iprintln!(&mut itm.stim[0], "Error {:?}", err);
                 }
             }
         }
@@ -128,3 +138,21 @@ const APP: () = {
 //    ** your answer here **
 //
 //    Commit your answer (bare8_4)
+//
+// 5. *Optional
+//    The "Ok" trace reports the cycles spent tracing the previous byte.
+//    Now trace using deferred formatting (the format string is interned, and
+//    only its id and the arguments are sent over the ITM, stimulus port 1).
+//
+//    > cargo build --example bare8 --features "rtfm trace-deferred"
+//    > itm-trace target/thumbv7em-none-eabihf/debug/examples/bare8 -f /tmp/itm.fifo
+//
+//    How many cycles does a trace take, formatted vs. deferred?
+//
+//    ** your answer here **
+//
+//    Are you still loosing data?
+//
+//    ** your answer here **
+//
+//    Commit your answer (bare8_5)
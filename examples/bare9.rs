@@ -6,14 +6,16 @@
 //! - Heapless Ringbuffer
 //! - Heapless Producer/Consumer lockfree data access
 //! - Interrupt driven I/O
+//! - Deferred formatting trace
 //!
 
 #![no_main]
 #![no_std]
 
-extern crate panic_halt;
+use app as _;
 
-use cortex_m::{asm, iprintln};
+use app::trace;
+use cortex_m::{asm, iprintln, peripheral::DWT};
 
 extern crate stm32f4xx_hal as hal;
 use crate::hal::prelude::*;
@@ -53,6 +55,10 @@ const APP: () = {
         let stim = &mut core.ITM.stim[0];
         iprintln!(stim, "bare9");
 
+        // the cycle counter, for measuring the cost of tracing
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
         let rcc = device.RCC.constrain();
 
         // 16 MHz (default, all clocks)
@@ -94,17 +100,21 @@ const APP: () = {
     // idle may be interrupted by other interrupt/tasks in the system
     #[idle(resources = [ITM, CONSUMER])]
     fn idle(cx: idle::Context) -> ! {
-        let stim = &mut cx.resources.ITM.stim[0];
+        let itm = cx.resources.ITM;
+        // cycles spent tracing the previous byte
+        let mut cost: u32 = 0;
 
         loop {
             while let Some(byte) = cx.resources.CONSUMER.dequeue() {
-                iprintln!(stim, "data {}", byte);
+                let start = DWT::get_cycle_count();
+                trace!(itm, "data {} ({} cycles)", byte, cost);
+                cost = DWT::get_cycle_count().wrapping_sub(start);
             }
 
-            iprintln!(stim, "goto sleep");
+            trace!(itm, "goto sleep");
             asm::wfi();
 
-            iprintln!(stim, "woken..");
+            trace!(itm, "woken..");
         }
     }
 
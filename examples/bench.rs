@@ -0,0 +1,94 @@
+//! bench.rs
+//!
+//! Micro benchmarks
+//!
+//! What it covers:
+//! - timing code by `bench!` (`app::bench`), `n` runs with the measurement
+//!   overhead subtracted, in place of the single `end - start` of `bare2.rs`
+//! - min, max, mean and jitter, in cycles and nanoseconds
+//! - machine readable reports over ITM, compared by `bench-compare` (host)
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::bench::black_box;
+use core::fmt::Write;
+use cortex_m::{asm, interrupt, iprintln};
+use cortex_m_rt::entry;
+use heapless::{consts::U16, String};
+
+// burns CPU cycles by just looping `i` times (as in `bare2.rs`)
+#[inline(never)]
+fn wait(i: u32) {
+    for _ in 0..i {
+        asm::nop();
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let mut c = cortex_m::Peripherals::take().unwrap();
+    c.DCB.enable_trace();
+    c.DWT.enable_cycle_counter();
+    let stim = &mut c.ITM.stim[0];
+
+    iprintln!(stim, "bench");
+
+    // interrupts would be counted in the runs
+    let reports = interrupt::free(|_| {
+        [
+            app::bench!("nop", 100, asm::nop()),
+            app::bench!("wait", 4, wait(1_000_000)),
+            // `black_box`, else the sum is computed at compile time
+            app::bench!("sum", 100, (0..black_box(1_000u32)).sum::<u32>()),
+            app::bench!("fmt", 100, {
+                let mut s: String<U16> = String::new();
+                write!(s, "{}", black_box(1_234_567u32)).ok();
+                s
+            }),
+        ]
+    });
+
+    // machine readable, for `bench-compare`
+    for report in &reports {
+        iprintln!(stim, "{}", report);
+    }
+    for report in &reports {
+        iprintln!(stim, "{:#}", report);
+    }
+
+    loop {
+        asm::wfi();
+    }
+}
+
+// Assignments
+// 0. Compile and run the example, in dev and release, keeping the reports.
+//
+//    > cargo run --example bench
+//    > itm-decode -p 0 /tmp/itm.fifo > dev.txt
+//    > cargo run --example bench --release
+//    > itm-decode -p 0 /tmp/itm.fifo > release.txt
+//    > bench-compare dev.txt release.txt
+//
+//    Compare `wait` to the numbers of `bare2.rs`. Which benchmark gains the
+//    most by the release build, and why?
+//
+//    ** your answer here **
+//
+// 1. What is the overhead in dev and in release? Why does `nop` take more
+//    than one cycle in dev?
+//
+//    ** your answer here **
+//
+// 2. Remove the `black_box` of `sum` and run in release. What happens to the
+//    reported cycles? Explain.
+//
+//    ** your answer here **
+//
+// 3. Move the `bench!` calls out of `interrupt::free`, and enable the SysTick
+//    interrupt at 1 kHz. Which of the statistics shows it?
+//
+//    ** your answer here **
@@ -0,0 +1,131 @@
+//! capture.rs
+//!
+//! Measuring clocks and signals by timer input capture, without an oscilloscope
+//!
+//! What it covers:
+//! - a frequency counter on TIM5, referenced to the LSE crystal
+//!   (`app::capture::Counter`)
+//! - a self test of the clock configuration: SYSCLK / 5 on MCO2 (PC9), looped
+//!   back to PA0 (A0), measured and checked against the planned SYSCLK
+//! - the PWM input mode, the period and duty cycle of the LED PWM (PA5, D13)
+//!   looped back to TIM3_CH1 (PA6, D12) (`app::capture::Capture`)
+//!
+//! Wiring:
+//! - PC9 (CN10 pin 1) to PA0 (A0)
+//! - PA5 (D13) to PA6 (D12)
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::bus::Mmio;
+use app::capture::{Capture, Counter};
+use app::clocks::{self, Mco, Mco2, Request};
+use app::exti::{Pin, Port};
+use app::pwm::{Channel, Polarity, Pwm, Timer, PA5};
+use app::regs::{AF_TIM1_2, AF_TIM3_5};
+use app::time::{self, DurationExt as _, Hertz};
+use cortex_m::{interrupt, iprintln};
+use cortex_m_rt::entry;
+
+const PA6: Pin = Pin::new(Port::A, 6);
+
+const MHZ: u32 = 1_000_000;
+
+// MCO2 prescaler, the counter input must be below half of the timer clock
+const MCO_DIV: u8 = 5;
+
+// the HSI is trimmed to 1% at 25 C
+const TOLERANCE_PPM: u32 = 20_000;
+
+#[entry]
+fn main() -> ! {
+    let mut c = cortex_m::Peripherals::take().unwrap();
+    c.DCB.enable_trace();
+    c.DWT.enable_cycle_counter();
+    let stim = &mut c.ITM.stim[0];
+    let bus = unsafe { Mmio::new() };
+
+    // the ITM (SWO) is clocked by SYSCLK, from here on at 84 MHz
+    let plan = Request::hsi(84 * MHZ).pclk1(42 * MHZ).plan().unwrap();
+    plan.apply(&bus);
+    time::set_sysclk(Hertz(plan.sysclk));
+    iprintln!(stim, "capture");
+
+    clocks::mco(&bus, Mco::Mco2(Mco2::Sysclk), MCO_DIV).unwrap();
+    let counter = Counter::new(unsafe { Mmio::new() });
+    counter.input();
+
+    // the reference of the counter, no self test without it
+    match clocks::lse_on(&bus) {
+        // no interrupts while polling the captures
+        Ok(()) => match interrupt::free(|_| counter.self_test(plan.sysclk, MCO_DIV)) {
+            Ok(report) => {
+                let verdict = if report.passed(TOLERANCE_PPM) {
+                    "PASS"
+                } else {
+                    "FAIL"
+                };
+                iprintln!(stim, "{} {}", report, verdict);
+            }
+            Err(e) => iprintln!(stim, "self test {:?}", e),
+        },
+        Err(e) => iprintln!(stim, "self test {:?} (LSE not ready, no X2 crystal?)", e),
+    }
+
+    // 1 kHz at 25%, measured in 1 us ticks
+    let timclk = Hertz(plan.timclk1());
+    let led = Pwm::new(unsafe { Mmio::new() }, Timer::Tim2, timclk, Hertz::khz(1)).unwrap();
+    led.output(PA5, AF_TIM1_2);
+    led.enable(Channel::C1, Polarity::ActiveHigh);
+    led.set_duty_percent(Channel::C1, 25).unwrap();
+
+    let capture = Capture::new(unsafe { Mmio::new() }, Timer::Tim3, timclk, 84).unwrap();
+    capture.input(PA6, AF_TIM3_5);
+
+    loop {
+        time::delay(500.millis());
+        match capture.poll() {
+            Some(pulse) => iprintln!(
+                stim,
+                "PA6 {} Hz, {}% ({:?})",
+                pulse.frequency(),
+                pulse.duty(),
+                pulse
+            ),
+            None => iprintln!(stim, "PA6 no signal"),
+        }
+    }
+}
+
+// Assignments
+// 0. Compile and run the example, with PC9 wired to PA0.
+//
+//    > cargo run --example capture
+//
+//    What is the measured SYSCLK, and its error in ppm? Is it within the 1% of
+//    the HSI?
+//
+//    ** your answer here **
+//
+// 1. Remove the wire. What is reported, and why?
+//
+//    ** your answer here **
+//
+// 2. Switch to the 8 MHz clock of the ST-LINK, `Request::hse_bypass(8 * MHZ,
+//    84 * MHZ)`, and set `TOLERANCE_PPM` to 100. Does it still pass? Why is the
+//    error smaller than for the HSI?
+//
+//    ** your answer here **
+//
+// 3. Set `MCO_DIV` to 2. What is measured now, and why (look at the module
+//    documentation of `app::capture`)?
+//
+//    ** your answer here **
+//
+// 4. Change the duty of the LED to 10% and its frequency to 20 Hz. What does
+//    the PWM input report? Why does it fail below about 15 Hz (at the 1 us
+//    ticks of TIM3)?
+//
+//    ** your answer here **
@@ -0,0 +1,75 @@
+//! clocks.rs
+//!
+//! Planning the clock tree
+//!
+//! What it covers:
+//! - the clocks wanted (SYSCLK, HCLK, PCLK1, PCLK2) checked against the
+//!   limits of the STM32F401, and the PLL computed (`app::clocks`)
+//! - switching to the planned clocks
+//! - routing SYSCLK to MCO2 (PC9), as the `clock_out` of `bare6.rs`
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::bus::Mmio;
+use app::clocks::{self, Mco, Mco2, Request};
+use app::time::{self, Hertz};
+use cortex_m::{asm, iprintln};
+use cortex_m_rt::entry;
+
+const MHZ: u32 = 1_000_000;
+
+#[entry]
+fn main() -> ! {
+    let mut c = cortex_m::Peripherals::take().unwrap();
+    let stim = &mut c.ITM.stim[0];
+    let bus = unsafe { Mmio::new() };
+
+    // `bare7.rs`, APB1 runs at most at 42 MHz
+    let wrong = Request::hsi(64 * MHZ).pclk1(64 * MHZ);
+    iprintln!(stim, "{:?}", wrong.plan());
+
+    let plan = Request::hsi(84 * MHZ).pclk1(42 * MHZ).plan().unwrap();
+    iprintln!(stim, "{}", plan);
+    iprintln!(
+        stim,
+        "timers APB1 {} Hz, APB2 {} Hz",
+        plan.timclk1(),
+        plan.timclk2()
+    );
+    iprintln!(
+        stim,
+        "MCO2 {:?} Hz",
+        plan.mco_hz(Mco::Mco2(Mco2::Sysclk), 4)
+    );
+
+    // the ITM (SWO) is clocked by SYSCLK, from here on at 84 MHz
+    plan.apply(&bus);
+    time::set_sysclk(Hertz(plan.sysclk));
+    clocks::mco(&bus, Mco::Mco2(Mco2::Sysclk), 4).unwrap();
+
+    loop {
+        asm::wfi();
+    }
+}
+
+// Assignments
+// 0. Compile and run the example.
+//
+//    > cargo run --example clocks
+//
+//    Measure the frequency on PC9 with an oscilloscope, does it match MCO2?
+//
+//    ** your answer here **
+//
+// 1. Try `Request::hse_bypass(8 * MHZ, 84 * MHZ)`, (the 8 MHz clock of the
+//    ST-LINK on the Nucleo). Which PLL is planned?
+//
+//    ** your answer here **
+//
+// 2. Add `.usb()` to the request of 84 MHz, and to one of 50 MHz. Why is the
+//    latter rejected?
+//
+//    ** your answer here **
@@ -5,11 +5,12 @@
 #![no_main]
 #![no_std]
 
-//use panic_halt as _;
-use panic_semihosting as _;
+use app as _;
 
 use core::ptr;
 
+use app::bus::Mmio;
+use app::fault::{Registers, Report};
 use cortex_m_rt::{entry, exception};
 
 #[entry]
@@ -28,5 +29,7 @@ fn main() -> ! {
 #[exception]
 #[inline(never)]
 fn HardFault(ef: &cortex_m_rt::ExceptionFrame) -> ! {
-    panic!("Exception frame {:?}", ef);
+    // the fault status and address registers, decoded
+    let regs = Registers::read(unsafe { &Mmio::new() });
+    panic!("{}\nException frame {:?}", Report::new(regs, ef.into()), ef);
 }
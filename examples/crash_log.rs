@@ -0,0 +1,79 @@
+//! crash_log.rs
+//!
+//! Crash log, kept over a reset in `.noinit` RAM
+//!
+//! What it covers:
+//! - the panic handler (`panic-log` and `panic-reset` features, see
+//!   `src/panic.rs`) and a `HardFault` handler recording the crash, and
+//!   resetting the MCU
+//! - reporting the crash of the previous run (over ITM), on the next boot
+//! - the reset cause (`RCC_CSR`)
+//!
+//! The first boot panics, the second one faults (as in `crash.rs`), each time
+//! reported by the following boot. Press the reset button for more boots.
+
+#![no_main]
+#![no_std]
+
+use core::ptr;
+
+use app::bus::Mmio;
+use app::fault::{Registers, Report};
+use app::panic::{log, stack};
+use app::reset;
+use cortex_m::{iprintln, peripheral::SCB};
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+
+#[entry]
+fn main() -> ! {
+    let mut p = cortex_m::Peripherals::take().unwrap();
+    let stim = &mut p.ITM.stim[0];
+
+    let flags = reset::Flags::take(unsafe { &Mmio::new() });
+    // the handlers never return, so `main` is the only user of the log
+    let boot = unsafe { log() }.boot();
+    iprintln!(
+        stim,
+        "crash_log, boot {}, reset by {:?}",
+        boot.count,
+        flags.cause()
+    );
+
+    if let Some(crash) = boot.crash {
+        iprintln!(stim, "previous crash:\n{}", crash);
+    }
+
+    match boot.count {
+        1 => panic!("boot {}", boot.count),
+        2 => unsafe {
+            // read an address outside of the RAM region to cause a HardFault exception
+            ptr::read_volatile(0x2FFF_FFFF as *const u32);
+        },
+        _ => {}
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+#[exception]
+fn HardFault(ef: &ExceptionFrame) -> ! {
+    let regs = Registers::read(unsafe { &Mmio::new() });
+    unsafe { log() }.record_fault(&Report::new(regs, ef.into()), stack());
+    SCB::sys_reset();
+}
+
+// Assignments
+// 0. Compile and run the example.
+//
+//    > cargo run --example crash_log --features "panic-log panic-reset"
+//
+//    Which reset cause is reported for the first three boots, and why?
+//
+//    ** your answer here **
+//
+// 1. Power cycle the board (unplug the USB cable). Is the boot count kept?
+//    Why, or why not?
+//
+//    ** your answer here **
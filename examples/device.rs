@@ -5,7 +5,7 @@
 #![no_std]
 
 #[allow(unused_extern_crates)]
-use panic_halt as _;
+use app as _;
 
 use cortex_m::{iprint, peripheral::syst::SystClkSource};
 use cortex_m_rt::entry;
@@ -11,7 +11,7 @@
 #![no_main]
 #![no_std]
 
-use panic_halt as _;
+use app as _;
 
 use cortex_m::peripheral::syst::SystClkSource;
 use cortex_m::Peripherals;
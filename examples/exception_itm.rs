@@ -9,12 +9,16 @@
 #![no_main]
 #![no_std]
 
-use panic_halt as _;
+use app as _;
 
-use cortex_m::peripheral::syst::SystClkSource;
+use app::global::Handoff;
+use cortex_m::peripheral::{syst::SystClkSource, ITM};
 use cortex_m::{iprint, iprintln, Peripherals};
 use cortex_m_rt::{entry, exception};
 
+// the `ITM`, handed from `main` to `SysTick`
+static HANDOFF: Handoff<ITM> = Handoff::new();
+
 #[entry]
 fn main() -> ! {
     let mut p = Peripherals::take().unwrap();
@@ -22,6 +26,10 @@ fn main() -> ! {
     let stim = &mut p.ITM.stim[0];
     iprintln!(stim, "exception_itm");
 
+    // from here on `main` has no access to the `ITM`
+    // (given before the exception is enabled, else `take` panics)
+    HANDOFF.give(p.ITM);
+
     // configures the system timer to trigger a SysTick exception every second
     syst.set_clock_source(SystClkSource::Core);
     syst.set_reload(16_000_000); // period = 1s
@@ -35,12 +43,13 @@ fn main() -> ! {
 
 #[exception]
 fn SysTick() {
-    // Here we steal all the peripherals.
-    //
-    // This is unsafe, as some other task/tasks may access the peripherals
+    // Previously we stole all the peripherals here (`Peripherals::steal`),
+    // which is unsafe, as some other task/tasks may access the peripherals
     // simultaneously, causing a conflict/race.
     //
-    let mut p = unsafe { cortex_m::Peripherals::steal() };
-    let stim = &mut p.ITM.stim[0];
-    iprint!(stim, ".");
+    // Instead `SysTick` takes the `ITM` (once), and keeps it in a local
+    // `static mut`, only accessible to this handler.
+    static mut OWNED: Option<ITM> = None;
+    let itm = OWNED.get_or_insert_with(|| HANDOFF.take());
+    iprint!(&mut itm.stim[0], ".");
 }
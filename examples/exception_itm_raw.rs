@@ -4,22 +4,31 @@
 //!
 //! [1]: https://rust-embedded.github.io/cortex-m-rt/0.6.1/cortex_m_rt_macros/fn.exception.html
 //!
+//! The `ITM` is shared by `main` and `SysTick` (previously accessed by a *raw*
+//! pointer in `SysTick`), each borrowing it in a critical section.
+//!
 
 #![no_main]
 #![no_std]
 
-use panic_halt as _;
+use app as _;
 
+use app::global::Mutex;
 use cortex_m::peripheral::{syst::SystClkSource, ITM};
-use cortex_m::{iprint, iprintln, Peripherals};
+use cortex_m::{asm, iprint, iprintln, Peripherals};
 use cortex_m_rt::{entry, exception};
 
+// `None` until `main` puts the `ITM` in
+static SHARED: Mutex<Option<ITM>> = Mutex::new(None);
+
 #[entry]
 fn main() -> ! {
-    let mut p = Peripherals::take().unwrap();
+    let p = Peripherals::take().unwrap();
     let mut syst = p.SYST;
-    let stim = &mut p.ITM.stim[0];
-    iprintln!(stim, "exception_itm_raw");
+    let mut itm = p.ITM;
+    iprintln!(&mut itm.stim[0], "exception_itm_raw");
+    // moved out of `p` first, the closure would capture all of `p`
+    SHARED.lock(|shared| *shared = Some(itm));
 
     // configures the system timer to trigger a SysTick exception every second
     syst.set_clock_source(SystClkSource::Core);
@@ -28,16 +37,23 @@ fn main() -> ! {
     syst.enable_interrupt();
 
     loop {
-        continue;
+        asm::wfi();
+        SHARED.lock(|shared| {
+            if let Some(itm) = shared {
+                iprint!(&mut itm.stim[0], "+");
+            }
+        });
     }
 }
 
 #[exception]
 fn SysTick() {
-    // here we access `ITM` using a *raw* pointer
-    // this is unsafe, as there may be other tasks accessing the peripheral
-    // simultaneously (and that might cause a conflict/race)
-    let itm = unsafe { &mut *ITM::ptr() };
-    let stim = &mut itm.stim[0];
-    iprint!(stim, ".");
+    // accessing `ITM` using a *raw* pointer (`ITM::ptr()`) is unsafe, as
+    // `main` may be in the middle of writing to the same stimulus port (and
+    // that would cause a conflict/race), here `main` cannot be
+    SHARED.lock(|shared| {
+        if let Some(itm) = shared {
+            iprint!(&mut itm.stim[0], ".");
+        }
+    });
 }
@@ -0,0 +1,98 @@
+//! global.rs
+//!
+//! Sharing state between `main` and an exception handler
+//!
+//! What it covers:
+//! - `static` cells with safe access (`app::global`), in place of the
+//!   `static mut` of `bare0.rs`
+//! - a tick counter (`VolatileGlobal`), counted by `SysTick`, read by `main`
+//! - a history of tick times (`Mutex`), written by `SysTick` and read by `main`
+//!   in critical sections
+//! - a configuration set once by `main` (`Singleton`), read by `SysTick`
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::global::{Mutex, Singleton, VolatileGlobal};
+use cortex_m::peripheral::{syst::SystClkSource, DWT};
+use cortex_m::{asm, iprintln, Peripherals};
+use cortex_m_rt::{entry, exception};
+
+struct Config {
+    // report every `report` ticks
+    report: u32,
+}
+
+static CONFIG: Singleton<Config> = Singleton::new();
+static TICKS: VolatileGlobal<u32> = VolatileGlobal::new(0);
+static REPORTS: VolatileGlobal<u32> = VolatileGlobal::new(0);
+// CYCCNT at the last 4 ticks
+static HISTORY: Mutex<[u32; 4]> = Mutex::new([0; 4]);
+
+#[entry]
+fn main() -> ! {
+    let mut p = Peripherals::take().unwrap();
+    p.DCB.enable_trace();
+    p.DWT.enable_cycle_counter();
+    let stim = &mut p.ITM.stim[0];
+    iprintln!(stim, "global");
+
+    // before the SysTick is enabled, else it sees no configuration
+    CONFIG.init(Config { report: 10 });
+
+    let mut syst = p.SYST;
+    syst.set_clock_source(SystClkSource::Core);
+    syst.set_reload(1_600_000); // period = 100ms
+    syst.enable_counter();
+    syst.enable_interrupt();
+
+    let mut reported = 0;
+    loop {
+        asm::wfi();
+        if REPORTS.get() != reported {
+            reported = REPORTS.get();
+            let history = HISTORY.lock(|history| *history);
+            iprintln!(stim, "ticks {}, at {:?}", TICKS.get(), history);
+        }
+    }
+}
+
+#[exception]
+fn SysTick() {
+    let ticks = TICKS.fetch_add(1).wrapping_add(1);
+    HISTORY.lock(|history| history[ticks as usize % 4] = DWT::get_cycle_count());
+
+    if let Some(config) = CONFIG.get() {
+        if ticks % config.report == 0 {
+            REPORTS.fetch_add(1);
+        }
+    }
+}
+
+// Assignments
+// 0. Compile and run the example.
+//
+//    > cargo run --example global
+//
+//    How many cycles are there between the ticks in the history? Why are
+//    they not in order?
+//
+//    ** your answer here **
+//
+// 1. Replace `TICKS.fetch_add(1)` by `TICKS.set(TICKS.get() + 1)`. It works
+//    the same here, why? When would it not? (Hint, an interrupt of a higher
+//    priority also counting ticks.)
+//
+//    ** your answer here **
+//
+// 2. In `main`, read `HISTORY` by `let h = HISTORY.lock(|h| h);`. Why does it
+//    not compile?
+//
+//    ** your answer here **
+//
+// 3. Move `CONFIG.init` after `syst.enable_interrupt()`, and call it twice.
+//    What happens?
+//
+//    ** your answer here **
@@ -4,7 +4,7 @@
 #![no_main]
 #![no_std]
 
-use panic_halt as _;
+use app as _;
 
 use cortex_m_rt::entry;
 use cortex_m_semihosting::hprintln;
@@ -12,7 +12,7 @@
 #![no_main]
 #![no_std]
 
-use panic_halt as _;
+use app as _;
 
 use cortex_m::{iprintln, Peripherals};
 use cortex_m_rt::entry;
@@ -1,24 +1,20 @@
 //! Changing the panicking behavior
 //!
-//! The easiest way to change the panicking behavior is to use a different [panic handler crate][0].
+//! The panic handler (`src/panic.rs`) is selected by cargo features:
 //!
-//! [0]: https://crates.io/keywords/panic-impl
+//! > cargo run --example panic --features panic-semihosting
+//!
+//! - (none, or `panic-halt`), `panic!` halts execution; the message is ignored
+//! - `panic-semihosting`, reports the message to the host stderr using semihosting
+//! - `panic-itm`, logs the message using the ITM (Instrumentation Trace Macrocell)
 
 #![deny(unsafe_code)]
 #![deny(warnings)]
 #![no_main]
 #![no_std]
 
-// Pick one of these panic handlers:
-
-// `panic!` halts execution; the panic message is ignored
-use panic_halt as _;
-
-// Reports panic messages to the host stderr using semihosting
-//use panic_semihosting as _;
-
-// Logs panic messages using the ITM (Instrumentation Trace Macrocell)
-//use panic_itm as _;
+// The panic handler, as selected by the features
+use app as _;
 
 use cortex_m_rt::entry;
 
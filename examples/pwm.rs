@@ -0,0 +1,96 @@
+//! pwm.rs
+//!
+//! LED brightness and servo control by timer PWM
+//!
+//! What it covers:
+//! - PA5 (the LED) as TIM2_CH1, in alternate function 1 (`app::pwm`)
+//! - a breathing LED, the duty cycle stepped by `breathe`
+//! - a complementary output, TIM2_CH2 on PA1 (A1), active low at the same duty
+//! - a servo on TIM3_CH1, PA6 (D12), 50 Hz with pulses of 1 to 2 ms
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::bus::Mmio;
+use app::exti::{Pin, Port};
+use app::pwm::{self, Channel, Polarity, Pwm, Timer, PA5};
+use app::regs::{AF_TIM1_2, AF_TIM3_5};
+use app::time::{self, Duration, DurationExt as _, Hertz};
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+const PA1: Pin = Pin::new(Port::A, 1);
+const PA6: Pin = Pin::new(Port::A, 6);
+
+// reset state, 16 MHz HSI, APB1 not divided
+const TIMCLK: Hertz = Hertz::mhz(16);
+
+// a breath of 2s
+const STEPS: u32 = 100;
+
+#[entry]
+fn main() -> ! {
+    let mut c = cortex_m::Peripherals::take().unwrap();
+    c.DCB.enable_trace();
+    c.DWT.enable_cycle_counter();
+    let stim = &mut c.ITM.stim[0];
+    iprintln!(stim, "pwm");
+
+    let led = Pwm::new(unsafe { Mmio::new() }, Timer::Tim2, TIMCLK, Hertz::khz(1)).unwrap();
+    led.output(PA5, AF_TIM1_2);
+    led.output(PA1, AF_TIM1_2);
+    led.enable(Channel::C1, Polarity::ActiveHigh);
+    led.enable(Channel::C2, Polarity::ActiveLow);
+    iprintln!(stim, "led {:?}, {} steps", led.frequency(), led.max_duty());
+
+    let servo = Pwm::new(unsafe { Mmio::new() }, Timer::Tim3, TIMCLK, Hertz(50)).unwrap();
+    servo.output(PA6, AF_TIM3_5);
+    servo.enable(Channel::C1, Polarity::ActiveHigh);
+    iprintln!(
+        stim,
+        "servo {:?}, {} steps",
+        servo.frequency(),
+        servo.max_duty()
+    );
+
+    let mut step = 0;
+    loop {
+        let duty = pwm::breathe(step, STEPS, led.max_duty());
+        led.set_duty(Channel::C1, duty);
+        led.set_duty(Channel::C2, duty);
+
+        // the servo follows the LED, from 1 ms (dark) to 2 ms (bright)
+        let pulse = Duration::from_micros(1_000 + 1_000 * duty / led.max_duty());
+        servo.set_pulse(Channel::C1, pulse).unwrap();
+
+        step = (step + 1) % STEPS;
+        time::delay(20.millis());
+    }
+}
+
+// Assignments
+// 0. Compile and run the example.
+//
+//    > cargo run --example pwm
+//
+//    What are the frequency and the number of duty steps of the LED and the
+//    servo? Why does the servo have fewer steps (look at `pwm::timing`)?
+//
+//    ** your answer here **
+//
+// 1. Connect a LED (with a resistor) from 3.3V to PA1 (A1). How do the two
+//    LEDs compare? Why?
+//
+//    ** your answer here **
+//
+// 2. Set the LED frequency to 10 Hz. What do you see? What is the lowest
+//    frequency without visible flicker?
+//
+//    ** your answer here **
+//
+// 3. Run the example at 84 MHz (`Request::hsi(84_000_000)`, see `clocks.rs`),
+//    with `TIMCLK` from `Plan::timclk1`. Why is the timer clock not PCLK1?
+//
+//    ** your answer here **
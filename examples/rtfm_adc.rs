@@ -0,0 +1,201 @@
+//! rtfm_adc.rs
+//!
+//! Analog input, the internal temperature sensor and VREFINT
+//!
+//! What it covers:
+//! - single conversions and a scan, polling (`app::adc`)
+//! - continuous conversion of a sequence, moved by DMA2 (stream 0) into a
+//!   double buffer, one interrupt per buffer
+//! - an overrun of the ADC caught by its own interrupt (`OVRIE`), as the DMA
+//!   stops on an overrun
+//! - the DMA2 stream 0 interrupt, missing from the device crate, routed to a
+//!   free one (`app::vectors`)
+//! - the averages of a buffer passed along as a message (as the bytes of
+//!   `trace_data` in `bare10.rs`)
+//! - VDDA and the temperature, by the factory calibration
+//!
+//! Connect a potentiometer (or a wire to GND or 3.3V) to PA0 (A0).
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::adc::{self, Adc, Block, Calibration, Channel, Continuous, Readings, SampleTime};
+use app::adc::{ADC1_DMA, BLOCK_SIZE};
+use app::bus::Mmio;
+use app::exti::{Pin, Port};
+use app::time::{self, DurationExt as _};
+use app::vectors::{self, irq, Table};
+use cortex_m::iprintln;
+use stm32f4xx_hal::{prelude::*, stm32::ITM};
+
+const PA0: Pin = Pin::new(Port::A, 0);
+
+// the readings of one block in `TRACE` are traced (about 1 s)
+const TRACE: u32 = 512;
+
+#[derive(Debug)]
+pub enum Error {
+    Overrun,
+    Transfer,
+}
+
+#[rtfm::app(device = stm32f4xx_hal::stm32, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        // late resources
+        ADC1: Continuous<Mmio>,
+        CAL: Calibration,
+        ITM: ITM,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        // the two DMA buffers, `&'static mut` in `init`
+        static mut BUFFER0: Block = [0; BLOCK_SIZE];
+        static mut BUFFER1: Block = [0; BLOCK_SIZE];
+        static mut VECTORS: Table = Table::new();
+
+        let mut core = cx.core;
+        let device = cx.device;
+
+        // the cycle counter, for `time::delay`
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let stim = &mut core.ITM.stim[0];
+        iprintln!(stim, "rtfm_adc");
+
+        // power on DMA2, RM0368 6.3.9
+        device.RCC.ahb1enr.modify(|_, w| w.dma2en().set_bit());
+
+        let rcc = device.RCC.constrain();
+
+        // 16 MHz (default, all clocks)
+        let clocks = rcc.cfgr.freeze();
+
+        // the ADC1, ADC common and DMA2 stream 0 registers are only accessed
+        // through `ADC1`
+        let adc = Adc::new(unsafe { Mmio::new() }, clocks.pclk2().0).unwrap();
+        let pa0 = adc.input(PA0).unwrap();
+        adc.sample_time(pa0, SampleTime::Cycles480);
+        adc.sensors();
+        // tSTAB of the ADC, tSTART of the sensor
+        time::delay(10.micros());
+        iprintln!(stim, "ADCCLK {:?}", adc.adcclk());
+
+        let cal = Calibration::read(adc.bus());
+        iprintln!(stim, "{:?}", cal);
+
+        // single conversions
+        iprintln!(stim, "PA0 {:?}", adc.convert(pa0));
+        let channels = [pa0, Channel::VREFINT, Channel::TEMPERATURE];
+        iprintln!(stim, "scan {:?}", adc.scan(&channels));
+
+        // the STM32F401 of the device crate has no DMA vectors, DMA2 stream 0
+        // takes the handler (and priority) of `EXTI2`, bound to `dma2_stream0`
+        vectors::route(
+            &unsafe { Mmio::new() },
+            VECTORS,
+            irq::DMA2_STREAM0,
+            irq::EXTI2,
+        );
+
+        let adc = adc
+            .continuous(ADC1_DMA, &channels, (BUFFER0, BUFFER1))
+            .unwrap();
+
+        init::LateResources {
+            ADC1: adc,
+            CAL: cal,
+            ITM: core.ITM,
+        }
+    }
+
+    // the averages of a buffer, PA0, VREFINT and the temperature sensor
+    #[task(priority = 1, capacity = 4, resources = [CAL, ITM])]
+    fn readings(cx: readings::Context, readings: Readings) {
+        static mut BLOCKS: u32 = 0;
+
+        *BLOCKS = BLOCKS.wrapping_add(1);
+        if *BLOCKS % TRACE != 0 {
+            return;
+        }
+        let cal = cx.resources.CAL;
+        let vdda = cal.vdda_mv(readings[1]);
+        let stim = &mut cx.resources.ITM.stim[0];
+        iprintln!(
+            stim,
+            "PA0 {} mV, VDDA {} mV, {} ({:?})",
+            adc::millivolts(readings[0], vdda),
+            vdda,
+            cal.temperature(readings[2], vdda),
+            readings
+        );
+    }
+
+    #[task(priority = 1, resources = [ITM])]
+    fn trace_error(cx: trace_error::Context, error: Error) {
+        let stim = &mut cx.resources.ITM.stim[0];
+        iprintln!(stim, "{:?}", error);
+    }
+
+    // a buffer is complete, DMA2 stream 0 (routed to `EXTI2`, not used otherwise)
+    #[task(binds = EXTI2, priority = 2, resources = [ADC1], spawn = [readings, trace_error])]
+    fn dma2_stream0(cx: dma2_stream0::Context) {
+        let before = cx.resources.ADC1.counters();
+        if let Some(readings) = cx.resources.ADC1.on_dma_interrupt() {
+            // dropped, if the tasks fall behind
+            cx.spawn.readings(readings).ok();
+        }
+        let after = cx.resources.ADC1.counters();
+        if after.overrun != before.overrun {
+            cx.spawn.trace_error(Error::Overrun).ok();
+        }
+        if after.transfer != before.transfer {
+            cx.spawn.trace_error(Error::Transfer).ok();
+        }
+    }
+
+    // an overrun, the DMA requests are stopped, no more `dma2_stream0`
+    #[task(binds = ADC, priority = 2, resources = [ADC1], spawn = [trace_error])]
+    fn adc_overrun(cx: adc_overrun::Context) {
+        if cx.resources.ADC1.on_adc_interrupt() {
+            cx.spawn.trace_error(Error::Overrun).ok();
+        }
+    }
+
+    // Set of interrupt vectors, free to use for RTFM tasks
+    // 1 per priority level suffices
+    extern "C" {
+        fn EXTI0();
+    }
+};
+
+// Assignments
+// 0. Compile and run the example, and turn the potentiometer.
+//
+//    > cargo run --example rtfm_adc --features rtfm
+//
+//    What are VDDA and the temperature? Put a finger on the MCU, does the
+//    temperature rise?
+//
+//    ** your answer here **
+//
+// 1. How many conversions per second does the ADC make (see `SampleTime`, a
+//    conversion takes the sample time + 12 cycles of ADCCLK)? How many DMA
+//    interrupts per second?
+//
+//    ** your answer here **
+//
+// 2. Set the sample time of PA0 to `SampleTime::Cycles3`. What happens to the
+//    number of interrupts? Is `TRACE` still about 1 s?
+//
+//    ** your answer here **
+//
+// 3. Add a `for _ in 0..100_000 { asm::nop() }` to `readings`. Which messages
+//    are lost, and why is there no overrun of the ADC (the DMA still reads DR
+//    in time)?
+//
+//    ** your answer here **
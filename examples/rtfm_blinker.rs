@@ -0,0 +1,269 @@
+//! rtfm_blinker.rs
+//!
+//! LED blinker, controlled over the serial port
+//!
+//! What it covers:
+//! - `on`/`off` tasks rescheduling each other from `cx.scheduled`
+//! - frequency and duty cycle as shared state (`app::blinker`)
+//! - stopping and restarting a scheduled sequence
+//! - the command line interpreter of `bare10.rs` (`app::cli`)
+//! - the LED (PA5) driven by TIM2 PWM (`app::pwm`), fully on or off when
+//!   blinking, fading in and out when breathing
+//!
+//! Connect a terminal (115200 8N1), and try:
+//! - `set 4`, blink at 4 Hz
+//! - `duty 10`, 10% on
+//! - `off`, `on`, stop and restart blinking
+//! - `breathe`, fade in and out (once per period, up to the duty cycle)
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::blinker::{Blinker, Mode, Sequence};
+use app::bus::Mmio;
+use app::cli::{Blink, Cli, BLINK};
+use app::pwm::{Channel, Polarity, Pwm, Timer, PA5};
+use app::regs::AF_TIM1_2;
+use app::time::Hertz;
+use cortex_m::iprintln;
+use rtfm::cyccnt::U32Ext as _;
+use stm32f4xx_hal::{
+    prelude::*,
+    serial::{config::Config, Event, Rx, Serial},
+    stm32::{ITM, USART2},
+};
+
+// PA5 is TIM2_CH1
+const LED: Channel = Channel::C1;
+
+#[rtfm::app(device = stm32f4xx_hal::stm32, monotonic = rtfm::cyccnt::CYCCNT, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        // late resources
+        LED: Pwm<Mmio>,
+        RX: Rx<USART2>,
+        ITM: ITM,
+        CLI: Cli<Blink>,
+        BLINKER: Blinker,
+    }
+
+    #[init(spawn = [on])]
+    fn init(cx: init::Context) -> init::LateResources {
+        let mut core = cx.core;
+        let device = cx.device;
+
+        // Initialize (enable) the monotonic timer (CYCCNT)
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let stim = &mut core.ITM.stim[0];
+        iprintln!(stim, "rtfm_blinker");
+
+        let rcc = device.RCC.constrain();
+
+        // 16 MHz (default, all clocks)
+        let clocks = rcc.cfgr.freeze();
+
+        // TIM2 is on APB1, clocked at twice PCLK1 if APB1 is divided
+        let pclk1 = clocks.pclk1().0;
+        let timclk = if clocks.ppre1() == 1 {
+            pclk1
+        } else {
+            2 * pclk1
+        };
+
+        // the TIM2 registers are only accessed through `LED`, at 1 kHz
+        let led = Pwm::new(
+            unsafe { Mmio::new() },
+            Timer::Tim2,
+            Hertz(timclk),
+            Hertz::khz(1),
+        )
+        .unwrap();
+        led.output(PA5, AF_TIM1_2);
+        led.enable(LED, Polarity::ActiveHigh);
+
+        let gpioa = device.GPIOA.split();
+
+        let tx = gpioa.pa2.into_alternate_af7();
+        let rx = gpioa.pa3.into_alternate_af7();
+
+        let mut serial = Serial::usart2(
+            device.USART2,
+            (tx, rx),
+            Config::default().baudrate(115_200.bps()),
+            clocks,
+        )
+        .unwrap();
+
+        // generate interrupt on Rxne
+        serial.listen(Event::Rxne);
+        let (_tx, rx) = serial.split();
+
+        // CYCCNT counts core (AHB) clock cycles
+        let mut blinker = Blinker::new(clocks.hclk().0);
+        let sequence = blinker.start().unwrap();
+        cx.spawn.on(sequence).unwrap();
+
+        init::LateResources {
+            LED: led,
+            RX: rx,
+            ITM: core.ITM,
+            CLI: Cli::new(BLINK),
+            BLINKER: blinker,
+        }
+    }
+
+    // capacity 2, the message of the current sequence, and one of a stopped
+    // sequence (not yet due)
+    #[task(priority = 3, capacity = 2, resources = [LED, BLINKER], schedule = [off])]
+    fn on(cx: on::Context, sequence: Sequence) {
+        let blinker = cx.resources.BLINKER;
+        if !blinker.is_current(sequence) {
+            // a stale sequence, the led is off unless restarted
+            if !blinker.is_running() {
+                cx.resources.LED.set_duty(LED, 0);
+            }
+            return;
+        }
+
+        let led = cx.resources.LED;
+        if blinker.on_cycles() != 0 {
+            led.set_duty(LED, led.max_duty());
+        }
+        let at = cx.scheduled + blinker.on_cycles().cycles();
+        if cx.schedule.off(at, sequence).is_err() {
+            // the queue is full of stopped sequences, stop (restart by `on`)
+            blinker.stop();
+            led.set_duty(LED, 0);
+        }
+    }
+
+    #[task(priority = 3, capacity = 2, resources = [LED, BLINKER], schedule = [on])]
+    fn off(cx: off::Context, sequence: Sequence) {
+        let blinker = cx.resources.BLINKER;
+        if !blinker.is_current(sequence) {
+            if !blinker.is_running() {
+                cx.resources.LED.set_duty(LED, 0);
+            }
+            return;
+        }
+
+        if blinker.off_cycles() != 0 {
+            cx.resources.LED.set_duty(LED, 0);
+        }
+        let at = cx.scheduled + blinker.off_cycles().cycles();
+        if cx.schedule.on(at, sequence).is_err() {
+            // the queue is full of stopped sequences, stop (restart by `on`)
+            blinker.stop();
+        }
+    }
+
+    // steps the brightness, a breath per period
+    #[task(priority = 3, capacity = 2, resources = [LED, BLINKER], schedule = [breathe])]
+    fn breathe(cx: breathe::Context, sequence: Sequence, step: u32) {
+        let blinker = cx.resources.BLINKER;
+        let led = cx.resources.LED;
+        if !blinker.is_current(sequence) {
+            if !blinker.is_running() {
+                led.set_duty(LED, 0);
+            }
+            return;
+        }
+
+        let percent = blinker.brightness(step);
+        led.set_duty(LED, (led.max_duty() as u64 * percent as u64 / 100) as u32);
+        let at = cx.scheduled + blinker.breathe_cycles().cycles();
+        let next = step.wrapping_add(1);
+        if cx.schedule.breathe(at, sequence, next).is_err() {
+            // the queue is full of stopped sequences, stop (restart by `breathe`)
+            blinker.stop();
+            led.set_duty(LED, 0);
+        }
+    }
+
+    // command line interpreter, the blinker is locked while updated
+    #[task(priority = 1, capacity = 10, resources = [CLI, ITM, BLINKER], spawn = [on, breathe])]
+    fn cli(mut cx: cli::Context, byte: u8) {
+        let stim = &mut cx.resources.ITM.stim[0];
+        let command = match cx.resources.CLI.push(byte) {
+            Some(Ok(command)) => command,
+            Some(Err(err)) => {
+                iprintln!(stim, "cli {:?}", err);
+                return;
+            }
+            None => return,
+        };
+
+        let (result, start) = cx.resources.BLINKER.lock(|blinker| match command {
+            Blink::Set(hz) => (blinker.set_frequency(hz), None),
+            Blink::Duty(percent) => (blinker.set_duty(percent), None),
+            Blink::On => (
+                Ok(()),
+                blinker.set_mode(Mode::Blink).or_else(|| blinker.start()),
+            ),
+            Blink::Breathe => (
+                Ok(()),
+                blinker.set_mode(Mode::Breathe).or_else(|| blinker.start()),
+            ),
+            Blink::Off => {
+                blinker.stop();
+                (Ok(()), None)
+            }
+        });
+
+        // a new sequence, if not already running (in the same mode)
+        let spawned = match start {
+            Some(sequence) if command == Blink::Breathe => cx.spawn.breathe(sequence, 0).is_ok(),
+            Some(sequence) => cx.spawn.on(sequence).is_ok(),
+            None => true,
+        };
+        iprintln!(stim, "{:?} {:?}", command, result);
+        if !spawned {
+            // restarted too often within a period, the queue is full
+            cx.resources.BLINKER.lock(|blinker| blinker.stop());
+            iprintln!(stim, "busy, try again");
+        }
+    }
+
+    #[task(binds = USART2, priority = 2, resources = [RX], spawn = [cli])]
+    fn usart2(cx: usart2::Context) {
+        if let Ok(byte) = cx.resources.RX.read() {
+            // the interpreter buffers 10 bytes, more are lost
+            let _ = cx.spawn.cli(byte);
+        }
+    }
+
+    // Set of interrupt vectors, free to use for RTFM tasks
+    // 1 per priority level suffices
+    extern "C" {
+        fn EXTI0();
+        fn EXTI1();
+        fn EXTI2();
+    }
+};
+
+// Assignments
+// 0. Compile and run the example, and connect a terminal (115200 8N1).
+//
+//    > cargo run --example rtfm_blinker --features rtfm
+//
+//    Try `set 2`, `duty 10`, `off` and `on`.
+//
+// 1. Stop and restart the blinking quickly (`off` and `on` within a period).
+//    Why do the stopped and the restarted sequence never overlap?
+//    (Look at `Sequence` in `src/blinker.rs`.)
+//
+//    ** your answer here **
+//
+// 2. The `on` and `off` tasks reschedule from `cx.scheduled`,
+//    not from `Instant::now()`. Why does this give a blinking without drift?
+//
+//    ** your answer here **
+//
+// 3. Try `breathe`, then `duty 10` and `set 4`. Does the LED look 10% as
+//    bright as at `duty 100`? (Look at `pwm::breathe`.)
+//
+//    ** your answer here **
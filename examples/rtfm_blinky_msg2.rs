@@ -3,9 +3,9 @@
 #![no_main]
 #![no_std]
 
+use app as _;
 use cortex_m::peripheral::DWT;
 use cortex_m_semihosting::hprintln;
-use panic_halt as _;
 use rtfm::cyccnt::{Instant, U32Ext as _};
 use stm32f4xx_hal::stm32;
 
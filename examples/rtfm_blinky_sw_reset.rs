@@ -3,9 +3,9 @@
 #![no_main]
 #![no_std]
 
+use app as _;
 use cortex_m;
 use cortex_m::peripheral::DWT;
-use panic_halt as _;
 use rtfm::cyccnt::U32Ext;
 use stm32f4xx_hal::stm32;
 
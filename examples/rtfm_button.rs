@@ -0,0 +1,215 @@
+//! rtfm_button.rs
+//!
+//! LED blinker, started and stopped by the user button (B1, PC13)
+//!
+//! What it covers:
+//! - routing a GPIO pin to its EXTI line (`app::exti`)
+//! - a hardware task bound to a shared vector (`EXTI15_10`)
+//! - debouncing, by sampling the pin from a scheduled task while the line is
+//!   masked
+//! - button events delivered as RTFM tasks
+//! - the `on`/`off` blinker tasks of `rtfm_blinker.rs`
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::blinker::{Blinker, Sequence};
+use app::bus::Mmio;
+use app::exti::{Debounce, Edge, Event, Exti, Pull, Vector, PC13};
+use app::time::{self, DurationExt as _, Hertz};
+use cortex_m::iprintln;
+use rtfm::cyccnt::{Instant, U32Ext as _};
+use stm32f4xx_hal::{
+    gpio::{gpioa::PA5, Output, PushPull},
+    prelude::*,
+    stm32::ITM,
+};
+
+// the sample period, in cycles at the SYSCLK given to `time::set_sysclk`
+fn sample() -> u32 {
+    5.millis().cycles()
+}
+
+#[rtfm::app(device = stm32f4xx_hal::stm32, monotonic = rtfm::cyccnt::CYCCNT, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        // late resources
+        LED: PA5<Output<PushPull>>,
+        ITM: ITM,
+        EXTI: Exti<Mmio>,
+        BUTTON: Debounce,
+        BLINKER: Blinker,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        let mut core = cx.core;
+        let device = cx.device;
+
+        // Initialize (enable) the monotonic timer (CYCCNT)
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let stim = &mut core.ITM.stim[0];
+        iprintln!(stim, "rtfm_button");
+
+        let rcc = device.RCC.constrain();
+
+        // 16 MHz (default, all clocks)
+        let clocks = rcc.cfgr.freeze();
+        time::set_sysclk(Hertz(clocks.sysclk().0));
+
+        let gpioa = device.GPIOA.split();
+        let led = gpioa.pa5.into_push_pull_output();
+
+        // the SYSCFG and EXTI registers are only accessed through `EXTI`
+        let exti = Exti::new(unsafe { Mmio::new() });
+        // B1 has an external pull-up (Nucleo UM1724, 6.5), low when pressed
+        exti.input(PC13, Pull::None);
+        // both edges, the debouncing tells a press from a release
+        exti.listen(PC13, Edge::Both);
+
+        init::LateResources {
+            LED: led,
+            ITM: core.ITM,
+            EXTI: exti,
+            // 4 samples (20 ms) of the same level
+            BUTTON: Debounce::new(true, 4),
+            BLINKER: Blinker::new(clocks.hclk().0),
+        }
+    }
+
+    // the first edge of a burst, the rest of it is ignored while sampling
+    #[task(binds = EXTI15_10, priority = 2, resources = [EXTI], spawn = [debounce])]
+    fn exti15_10(cx: exti15_10::Context) {
+        let exti = cx.resources.EXTI;
+        for line in exti.take_pending(Vector::Exti15_10) {
+            if line == PC13.line() {
+                exti.mask(line);
+                cx.spawn.debounce().unwrap();
+            }
+        }
+    }
+
+    // samples PC13 until the level settles, then listens for the next edge
+    #[task(priority = 2, resources = [EXTI, BUTTON], schedule = [debounce], spawn = [button])]
+    fn debounce(cx: debounce::Context) {
+        let exti = cx.resources.EXTI;
+        let button = cx.resources.BUTTON;
+        if let Some(event) = button.sample(exti.is_high(PC13)) {
+            cx.spawn.button(event).unwrap();
+        }
+
+        if button.is_settling() {
+            cx.schedule
+                .debounce(Instant::now() + sample().cycles())
+                .unwrap();
+        } else {
+            // an edge while masked is still pending, and raised now
+            exti.unmask(PC13.line());
+        }
+    }
+
+    // a press toggles the blinker
+    #[task(priority = 1, resources = [ITM, BLINKER], spawn = [on])]
+    fn button(mut cx: button::Context, event: Event) {
+        let stim = &mut cx.resources.ITM.stim[0];
+        iprintln!(stim, "{:?}", event);
+        if event != Event::Pressed {
+            return;
+        }
+
+        let start = cx.resources.BLINKER.lock(|blinker| {
+            if blinker.is_running() {
+                blinker.stop();
+                None
+            } else {
+                blinker.start()
+            }
+        });
+        if let Some(sequence) = start {
+            if cx.spawn.on(sequence).is_err() {
+                // pressed too often within a period, the queue is full
+                cx.resources.BLINKER.lock(|blinker| blinker.stop());
+                iprintln!(stim, "busy, press again");
+            }
+        }
+    }
+
+    // capacity 2, the message of the current sequence, and one of a stopped
+    // sequence (not yet due)
+    #[task(priority = 3, capacity = 2, resources = [LED, BLINKER], schedule = [off])]
+    fn on(cx: on::Context, sequence: Sequence) {
+        let blinker = cx.resources.BLINKER;
+        if !blinker.is_current(sequence) {
+            // a stale sequence, the led is off unless restarted
+            if !blinker.is_running() {
+                cx.resources.LED.set_low().ok();
+            }
+            return;
+        }
+
+        if blinker.on_cycles() != 0 {
+            cx.resources.LED.set_high().ok();
+        }
+        let at = cx.scheduled + blinker.on_cycles().cycles();
+        if cx.schedule.off(at, sequence).is_err() {
+            // the queue is full of stopped sequences, stop (restart by a press)
+            blinker.stop();
+            cx.resources.LED.set_low().ok();
+        }
+    }
+
+    #[task(priority = 3, capacity = 2, resources = [LED, BLINKER], schedule = [on])]
+    fn off(cx: off::Context, sequence: Sequence) {
+        let blinker = cx.resources.BLINKER;
+        if !blinker.is_current(sequence) {
+            if !blinker.is_running() {
+                cx.resources.LED.set_low().ok();
+            }
+            return;
+        }
+
+        if blinker.off_cycles() != 0 {
+            cx.resources.LED.set_low().ok();
+        }
+        let at = cx.scheduled + blinker.off_cycles().cycles();
+        if cx.schedule.on(at, sequence).is_err() {
+            // the queue is full of stopped sequences, stop (restart by a press)
+            blinker.stop();
+        }
+    }
+
+    // Set of interrupt vectors, free to use for RTFM tasks
+    // 1 per priority level suffices
+    extern "C" {
+        fn EXTI0();
+        fn EXTI1();
+        fn EXTI2();
+    }
+};
+
+// Assignments
+// 0. Compile and run the example, and press the user button (B1).
+//
+//    > cargo run --example rtfm_button --features rtfm
+//
+//    Each press should start or stop the blinking, and the ITM trace shows
+//    `Pressed` and `Released`.
+//
+// 1. Set the `BUTTON` to 1 sample, and press the button repeatedly. Do you
+//    ever see a press without a release (or two presses)? Explain.
+//
+//    ** your answer here **
+//
+// 2. The `exti15_10` task masks the line, and `debounce` unmasks it. What
+//    would happen to the `debounce` queue (capacity 1) without the masking?
+//
+//    ** your answer here **
+//
+// 3. Why are the EXTI0..2 vectors free for dispatching tasks here, though the
+//    button uses an EXTI line?
+//
+//    ** your answer here **
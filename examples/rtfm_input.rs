@@ -0,0 +1,185 @@
+//! rtfm_input.rs
+//!
+//! Button gestures: press, release, click, double-click, long press and repeat
+//!
+//! What it covers:
+//! - EXTI handlers recording timestamped edges only (`app::exti`)
+//! - a single scheduled task telling the gestures of all buttons apart
+//!   (`app::input`)
+//! - per button timing (`Config`)
+//!
+//! Buttons:
+//! - the user button (B1, PC13), low when pressed, default timing, a click
+//!   toggles the LED
+//! - an external button from 3.3V to PA8 (D7), high when pressed,
+//!   clicks reported at once (no double-click), and faster repeats
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::bus::Mmio;
+use app::exti::{Edge, Exti, Pin, Port, Pull, Vector, PC13};
+use app::input::{Button, Config, Event, Events};
+use app::time::{self, Duration, DurationExt as _, Hertz, Instant};
+use cortex_m::iprintln;
+use rtfm::cyccnt::U32Ext as _;
+use stm32f4xx_hal::{
+    gpio::{gpioa::PA5, Output, PushPull},
+    prelude::*,
+    stm32::ITM,
+};
+
+const PA8: Pin = Pin::new(Port::A, 8);
+const PINS: [Pin; 2] = [PC13, PA8];
+
+// the poll period, in cycles at the SYSCLK given to `time::set_sysclk`
+fn poll() -> u32 {
+    10.millis().cycles()
+}
+
+#[rtfm::app(device = stm32f4xx_hal::stm32, monotonic = rtfm::cyccnt::CYCCNT, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        // late resources
+        LED: PA5<Output<PushPull>>,
+        ITM: ITM,
+        EXTI: Exti<Mmio>,
+        BUTTONS: [Button; 2],
+    }
+
+    #[init(schedule = [input])]
+    fn init(cx: init::Context) -> init::LateResources {
+        let mut core = cx.core;
+        let device = cx.device;
+
+        // Initialize (enable) the monotonic timer (CYCCNT)
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let stim = &mut core.ITM.stim[0];
+        iprintln!(stim, "rtfm_input");
+
+        let rcc = device.RCC.constrain();
+
+        // 16 MHz (default, all clocks)
+        let clocks = rcc.cfgr.freeze();
+        let sysclk = Hertz(clocks.sysclk().0);
+        time::set_sysclk(sysclk);
+
+        let gpioa = device.GPIOA.split();
+        let led = gpioa.pa5.into_push_pull_output();
+
+        // the SYSCFG and EXTI registers are only accessed through `EXTI`
+        let exti = Exti::new(unsafe { Mmio::new() });
+        exti.input(PC13, Pull::None);
+        exti.input(PA8, Pull::Down);
+        for &pin in &PINS {
+            exti.listen(pin, Edge::Both);
+        }
+
+        let external = Config {
+            active_low: false,
+            double_click: None,
+            repeat: Some(Duration::from_millis(100)),
+            ..Config::default()
+        };
+
+        cx.schedule.input(cx.start + poll().cycles()).unwrap();
+
+        init::LateResources {
+            LED: led,
+            ITM: core.ITM,
+            EXTI: exti,
+            BUTTONS: [
+                Button::new(Config::default(), sysclk),
+                Button::new(external, sysclk),
+            ],
+        }
+    }
+
+    #[task(binds = EXTI9_5, priority = 2, resources = [EXTI, BUTTONS])]
+    fn exti9_5(cx: exti9_5::Context) {
+        edges(cx.resources.EXTI, cx.resources.BUTTONS, Vector::Exti9_5);
+    }
+
+    #[task(binds = EXTI15_10, priority = 2, resources = [EXTI, BUTTONS])]
+    fn exti15_10(cx: exti15_10::Context) {
+        edges(cx.resources.EXTI, cx.resources.BUTTONS, Vector::Exti15_10);
+    }
+
+    // the gestures of all buttons, at a fixed period
+    #[task(priority = 1, resources = [LED, ITM, BUTTONS], schedule = [input])]
+    fn input(mut cx: input::Context) {
+        static mut ON: bool = false;
+
+        // the buttons are locked while polled, not while reported, and `now`
+        // is read in the lock (no edge recorded after it)
+        let events: [Events; 2] = cx.resources.BUTTONS.lock(|buttons| {
+            let now = Instant::now();
+            [buttons[0].poll(now), buttons[1].poll(now)]
+        });
+
+        let stim = &mut cx.resources.ITM.stim[0];
+        for (button, events) in events.iter().enumerate() {
+            for &event in events.iter() {
+                iprintln!(stim, "button {} {:?}", button, event);
+                if button == 0 && event == Event::Click {
+                    *ON = !*ON;
+                    if *ON {
+                        cx.resources.LED.set_high().ok();
+                    } else {
+                        cx.resources.LED.set_low().ok();
+                    }
+                }
+            }
+        }
+
+        cx.schedule.input(cx.scheduled + poll().cycles()).unwrap();
+    }
+
+    // Set of interrupt vectors, free to use for RTFM tasks
+    // 1 per priority level suffices
+    extern "C" {
+        fn EXTI0();
+        fn EXTI1();
+    }
+};
+
+// records the edges of the pending lines of `vector`
+fn edges(exti: &mut Exti<Mmio>, buttons: &mut [Button; 2], vector: Vector) {
+    let now = Instant::now();
+    for line in exti.take_pending(vector) {
+        for (pin, button) in PINS.iter().zip(buttons.iter_mut()) {
+            if pin.line() == line {
+                button.edge(now, exti.is_high(*pin));
+            }
+        }
+    }
+}
+
+// Assignments
+// 0. Compile and run the example, and try the gestures on B1.
+//
+//    > cargo run --example rtfm_input --features rtfm
+//
+//    Why is a click reported 300 ms after the release, and not at once?
+//
+//    ** your answer here **
+//
+// 1. Make a double click, as fast as you can, and look at the trace. Are the
+//    `Release` and the second `Press` reported by the same poll? Why is the
+//    double-click still told apart correctly?
+//
+//    ** your answer here **
+//
+// 2. Set the poll period (`poll`) to 1s. Which gestures are still reported
+//    correctly, and which are reported late? (Hint, `Button::edge`.)
+//
+//    ** your answer here **
+//
+// 3. Add a third button, with its own `Config`, on a pin of line 0..4. Which
+//    vector does it use, and what must change in the `extern` block?
+//
+//    ** your answer here **
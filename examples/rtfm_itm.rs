@@ -3,8 +3,8 @@
 
 use cortex_m::{iprint, iprintln};
 
+use app as _;
 use pac::Interrupt;
-use panic_semihosting as _;
 use rtfm::app;
 use stm32f4xx_hal::stm32 as pac;
 
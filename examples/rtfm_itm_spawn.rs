@@ -3,7 +3,7 @@
 
 use cortex_m::iprintln;
 
-use panic_semihosting as _;
+use app as _;
 use rtfm::app;
 
 #[app(device = stm32f4xx_hal::stm32, peripherals = true )]
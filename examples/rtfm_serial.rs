@@ -0,0 +1,126 @@
+//! rtfm_serial.rs
+//!
+//! Interrupt driven, buffered serial echo
+//!
+//! What it covers:
+//! - the buffered USART2 driver (`app::usart`), shared as a resource
+//! - RX and TX rings, no blocking (unlike `bare10.rs`)
+//! - error and lost byte accounting, instead of `asm::bkpt` (as in `bare9.rs`)
+//!
+//! Connect a terminal (115200 8N1) and type, each received byte is echoed.
+//! Once a second the error counters are traced over ITM.
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::bus::Mmio;
+use app::regs::USART2;
+use app::usart::BufferedSerial;
+use core::fmt::Write as _;
+use cortex_m::iprintln;
+use embedded_hal::serial::Read as _;
+use heapless::consts::*;
+use rtfm::cyccnt::U32Ext as _;
+use stm32f4xx_hal::{prelude::*, stm32::ITM};
+
+// 1s at 16 MHz
+const PERIOD: u32 = 16_000_000;
+
+#[rtfm::app(device = stm32f4xx_hal::stm32, monotonic = rtfm::cyccnt::CYCCNT, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        // late resources
+        SERIAL: BufferedSerial<Mmio, U64, U64>,
+        ITM: ITM,
+    }
+
+    #[init(schedule = [report])]
+    fn init(cx: init::Context) -> init::LateResources {
+        let mut core = cx.core;
+        let device = cx.device;
+
+        // Initialize (enable) the monotonic timer (CYCCNT)
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let stim = &mut core.ITM.stim[0];
+        iprintln!(stim, "rtfm_serial");
+
+        // power on USART2, RM0368 6.3.11
+        device.RCC.apb1enr.modify(|_, w| w.usart2en().set_bit());
+
+        let rcc = device.RCC.constrain();
+
+        // 16 MHz (default, all clocks)
+        let clocks = rcc.cfgr.freeze();
+
+        // PA2 (TX) and PA3 (RX), alternate function 7
+        let gpioa = device.GPIOA.split();
+        let _tx = gpioa.pa2.into_alternate_af7();
+        let _rx = gpioa.pa3.into_alternate_af7();
+
+        // the USART2 registers are only accessed through `SERIAL`
+        let mut serial = BufferedSerial::new(unsafe { Mmio::new() }, &USART2);
+        serial.init(clocks.pclk1().0, 115_200);
+
+        cx.schedule.report(cx.start + PERIOD.cycles()).unwrap();
+
+        init::LateResources {
+            SERIAL: serial,
+            ITM: core.ITM,
+        }
+    }
+
+    // echo, the SERIAL is locked while the received bytes are written back
+    #[task(priority = 1, resources = [SERIAL])]
+    fn echo(mut cx: echo::Context) {
+        cx.resources.SERIAL.lock(|serial| {
+            while let Ok(byte) = serial.read() {
+                // a full TX ring is counted (`tx_dropped`) by `fmt::Write`
+                let _ = write!(serial, "{}", byte as char);
+            }
+        });
+    }
+
+    #[task(priority = 1, resources = [SERIAL, ITM], schedule = [report])]
+    fn report(mut cx: report::Context) {
+        let counters = cx.resources.SERIAL.lock(|serial| serial.counters());
+        let stim = &mut cx.resources.ITM.stim[0];
+        iprintln!(stim, "{:?}", counters);
+
+        cx.schedule.report(cx.scheduled + PERIOD.cycles()).unwrap();
+    }
+
+    #[task(binds = USART2, priority = 2, resources = [SERIAL], spawn = [echo])]
+    fn usart2(cx: usart2::Context) {
+        let serial = cx.resources.SERIAL;
+        serial.on_interrupt();
+        if serial.received() != 0 {
+            // already spawned, if the echo is pending
+            let _ = cx.spawn.echo();
+        }
+    }
+
+    // Set of interrupt vectors, free to use for RTFM tasks
+    // 1 per priority level suffices
+    extern "C" {
+        fn EXTI0();
+        fn EXTI1();
+    }
+};
+
+// Assignments
+// 0. Compile and run the example, and connect a terminal (115200 8N1).
+//
+//    > cargo run --example rtfm_serial --features rtfm
+//
+//    Send `abcd` (as in `bare10.rs`). Is all data echoed?
+//
+//    ** your answer here **
+//
+// 1. Paste a long text (more than 64 characters) into the terminal.
+//    Which of the counters increase, and why?
+//
+//    ** your answer here **
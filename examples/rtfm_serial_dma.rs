@@ -0,0 +1,134 @@
+//! rtfm_serial_dma.rs
+//!
+//! Serial reception by DMA, frame by frame
+//!
+//! What it covers:
+//! - USART2 reception by DMA1 (stream 5, channel 4), `app::usart_dma`
+//! - idle line detection, one interrupt per frame (not per byte)
+//! - owned buffers passed along as messages (as in `rtfm_blinky_msg3.rs`)
+//! - an interrupt missing from the device crate (DMA1 stream 5), routed to a
+//!   free one by a vector table in RAM (`app::vectors`)
+//!
+//! Connect a terminal (115200 8N1), and send some text. Each frame (bytes
+//! sent back to back) is traced over ITM.
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::bus::Mmio;
+use app::regs::{DMA1, USART2};
+use app::usart_dma::{Buffer, DmaRx, Frame, BUFFER_SIZE, USART2_RX};
+use app::vectors::{self, irq, Table};
+use cortex_m::iprintln;
+use stm32f4xx_hal::{prelude::*, stm32::ITM};
+
+#[rtfm::app(device = stm32f4xx_hal::stm32, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        // late resources
+        RX: DmaRx<Mmio>,
+        ITM: ITM,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        // the two frame buffers, `&'static mut` in `init`
+        static mut BUFFER0: Buffer = [0; BUFFER_SIZE];
+        static mut BUFFER1: Buffer = [0; BUFFER_SIZE];
+        static mut VECTORS: Table = Table::new();
+
+        let mut core = cx.core;
+        let device = cx.device;
+
+        let stim = &mut core.ITM.stim[0];
+        iprintln!(stim, "rtfm_serial_dma");
+
+        // power on USART2 and DMA1, RM0368 6.3.9 and 6.3.11
+        device.RCC.apb1enr.modify(|_, w| w.usart2en().set_bit());
+        device.RCC.ahb1enr.modify(|_, w| w.dma1en().set_bit());
+
+        let rcc = device.RCC.constrain();
+
+        // 16 MHz (default, all clocks)
+        let clocks = rcc.cfgr.freeze();
+
+        // PA3 (RX), alternate function 7
+        let gpioa = device.GPIOA.split();
+        let _rx = gpioa.pa3.into_alternate_af7();
+
+        // the USART2 and DMA1 stream 5 registers are only accessed through `RX`
+        let mut rx = DmaRx::new(
+            unsafe { Mmio::new() },
+            &USART2,
+            &DMA1,
+            USART2_RX,
+            (BUFFER0, BUFFER1),
+        );
+        rx.init(clocks.pclk1().0, 115_200);
+
+        // the STM32F401 of the device crate has no DMA vectors, DMA1 stream 5
+        // takes the handler (and priority) of `EXTI2`, bound to `dma1_stream5`
+        vectors::route(
+            &unsafe { Mmio::new() },
+            VECTORS,
+            irq::DMA1_STREAM5,
+            irq::EXTI2,
+        );
+
+        init::LateResources {
+            RX: rx,
+            ITM: core.ITM,
+        }
+    }
+
+    // the frame is owned by the task, and handed back when done
+    #[task(priority = 1, resources = [RX, ITM])]
+    fn received(mut cx: received::Context, frame: Frame) {
+        let stim = &mut cx.resources.ITM.stim[0];
+        iprintln!(stim, "frame {:?}", frame.as_slice());
+
+        let counters = cx.resources.RX.lock(|rx| {
+            rx.release(frame);
+            rx.counters()
+        });
+        iprintln!(stim, "{:?}", counters);
+    }
+
+    // the line went idle
+    #[task(binds = USART2, priority = 2, resources = [RX], spawn = [received])]
+    fn usart2(cx: usart2::Context) {
+        if let Some(frame) = cx.resources.RX.on_usart_interrupt() {
+            // at most one frame is out (the spare buffer)
+            cx.spawn.received(frame).ok();
+        }
+    }
+
+    // the buffer is full, DMA1 stream 5 (routed to `EXTI2`, not used otherwise)
+    #[task(binds = EXTI2, priority = 2, resources = [RX], spawn = [received])]
+    fn dma1_stream5(cx: dma1_stream5::Context) {
+        if let Some(frame) = cx.resources.RX.on_dma_interrupt() {
+            cx.spawn.received(frame).ok();
+        }
+    }
+
+    // Set of interrupt vectors, free to use for RTFM tasks
+    // 1 per priority level suffices
+    extern "C" {
+        fn EXTI0();
+    }
+};
+
+// Assignments
+// 0. Compile and run the example, and connect a terminal (115200 8N1).
+//
+//    > cargo run --example rtfm_serial_dma --features rtfm
+//
+//    Send `abcd` (as in `bare10.rs`). How many interrupts were taken?
+//
+//    ** your answer here **
+//
+// 1. Paste a text longer than 64 characters. How is it split into frames?
+//
+//    ** your answer here **
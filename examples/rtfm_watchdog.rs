@@ -0,0 +1,178 @@
+//! rtfm_watchdog.rs
+//!
+//! Supervising the tasks by the independent watchdog (IWDG)
+//!
+//! What it covers:
+//! - the IWDG, configured from a timeout in milliseconds (`app::watchdog`)
+//! - a supervisor task feeding the watchdog only when each registered task
+//!   has checked in (the `Monitor`)
+//! - the reset cause (`RCC_CSR`), reported on the next boot
+//!
+//! After 5s the `work` task hangs (as would the `echo` of `bare10.rs`, blocked
+//! in `block!(tx.write(byte))`). The supervisor reports it missing, stops
+//! feeding the watchdog, and the MCU is reset.
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::bus::Mmio;
+use app::reset;
+use app::watchdog::{Iwdg, IwdgConfig, Monitor, Task};
+use cortex_m::{asm, iprintln};
+use rtfm::cyccnt::U32Ext as _;
+use stm32f4xx_hal::{
+    gpio::{gpioa::PA5, Output, PushPull},
+    prelude::*,
+    stm32::ITM,
+};
+
+// 16 MHz core clock (default), in CYCCNT cycles
+const MS: u32 = 16_000;
+
+const TIMEOUT_MS: u32 = 1000;
+// longer than the task periods, so each task checks in between feeds
+const SUPERVISOR_PERIOD: u32 = 300 * MS;
+const BLINK_PERIOD: u32 = 250 * MS;
+const WORK_PERIOD: u32 = 100 * MS;
+// runs of `work` before it hangs
+const WORK_RUNS: u32 = 50;
+
+#[rtfm::app(device = stm32f4xx_hal::stm32, monotonic = rtfm::cyccnt::CYCCNT, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        // late resources
+        LED: PA5<Output<PushPull>>,
+        ITM: ITM,
+        DOG: Iwdg<Mmio>,
+        MONITOR: Monitor,
+        BLINK: Task,
+        WORK: Task,
+        #[init(0)]
+        RUNS: u32,
+    }
+
+    #[init(schedule = [supervisor, blink, work])]
+    fn init(cx: init::Context) -> init::LateResources {
+        let mut core = cx.core;
+        let device = cx.device;
+
+        // Initialize (enable) the monotonic timer (CYCCNT)
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let stim = &mut core.ITM.stim[0];
+        let flags = reset::Flags::take(unsafe { &Mmio::new() });
+        iprintln!(stim, "rtfm_watchdog, reset by {:?}", flags.cause());
+        if flags.is_watchdog() {
+            iprintln!(stim, "recovered from a hung task");
+        }
+
+        let rcc = device.RCC.constrain();
+        let _clocks = rcc.cfgr.freeze();
+
+        let gpioa = device.GPIOA.split();
+        let led = gpioa.pa5.into_push_pull_output();
+
+        let mut monitor = Monitor::new();
+        let blink = monitor.register("blink").unwrap();
+        let work = monitor.register("work").unwrap();
+
+        let mut dog = Iwdg::new(unsafe { Mmio::new() });
+        dog.start(IwdgConfig::from_millis(TIMEOUT_MS).unwrap());
+
+        cx.schedule
+            .supervisor(cx.start + SUPERVISOR_PERIOD.cycles())
+            .unwrap();
+        cx.schedule.blink(cx.start, true).unwrap();
+        cx.schedule.work(cx.start).unwrap();
+
+        init::LateResources {
+            LED: led,
+            ITM: core.ITM,
+            DOG: dog,
+            MONITOR: monitor,
+            BLINK: blink,
+            WORK: work,
+        }
+    }
+
+    // the highest priority, so it is not starved by the tasks it supervises
+    #[task(priority = 3, resources = [DOG, MONITOR, ITM], schedule = [supervisor])]
+    fn supervisor(cx: supervisor::Context) {
+        let monitor = cx.resources.MONITOR;
+        let stim = &mut cx.resources.ITM.stim[0];
+        if !monitor.feed(cx.resources.DOG) {
+            for name in monitor.missing() {
+                iprintln!(stim, "{} missing", name);
+            }
+        }
+        cx.schedule
+            .supervisor(cx.scheduled + SUPERVISOR_PERIOD.cycles())
+            .unwrap();
+    }
+
+    #[task(priority = 1, resources = [LED, MONITOR, BLINK], schedule = [blink])]
+    fn blink(mut cx: blink::Context, on: bool) {
+        let task = *cx.resources.BLINK;
+        cx.resources.MONITOR.lock(|monitor| monitor.check_in(task));
+
+        if on {
+            cx.resources.LED.set_high().ok();
+        } else {
+            cx.resources.LED.set_low().ok();
+        }
+        cx.schedule
+            .blink(cx.scheduled + BLINK_PERIOD.cycles(), !on)
+            .unwrap();
+    }
+
+    #[task(priority = 2, resources = [MONITOR, WORK, RUNS], schedule = [work])]
+    fn work(mut cx: work::Context) {
+        *cx.resources.RUNS += 1;
+        if *cx.resources.RUNS == WORK_RUNS {
+            // hung, e.g., waiting for a peripheral that never gets ready
+            loop {
+                asm::nop();
+            }
+        }
+
+        let task = *cx.resources.WORK;
+        cx.resources.MONITOR.lock(|monitor| monitor.check_in(task));
+        cx.schedule
+            .work(cx.scheduled + WORK_PERIOD.cycles())
+            .unwrap();
+    }
+
+    // Set of interrupt vectors, free to use for RTFM tasks
+    // 1 per priority level suffices
+    extern "C" {
+        fn EXTI0();
+        fn EXTI1();
+        fn EXTI2();
+    }
+};
+
+// Assignments
+// 0. Compile and run the example.
+//
+//    > cargo run --example rtfm_watchdog --features rtfm
+//
+//    Which reset cause is reported on the first boot, and after `work` hangs?
+//
+//    ** your answer here **
+//
+// 1. After `work` hangs, `blink` is reported missing too. Why?
+//
+//    ** your answer here **
+//
+// 2. Raise `BLINK_PERIOD` to 1200 ms. No task hangs, still the MCU is reset.
+//    Why? How do the task periods bound the watchdog timeout?
+//
+//    ** your answer here **
+//
+// 3. Halt the program in the debugger for more than a second, and continue.
+//    What happens? (Look up `DBG_IWDG_STOP` of `DBGMCU_APB1_FZ` in RM0368.)
+//
+//    ** your answer here **
@@ -0,0 +1,98 @@
+//! time.rs
+//!
+//! Time units on top of the cycle counter
+//!
+//! What it covers:
+//! - measuring time by `Instant` (the `end - start` of `bare2.rs`), in cycles
+//!   and in microseconds (`app::time`)
+//! - blinking the LED by `delay`, in place of the `wait_cycles` of `bare6.rs`
+//! - the blinking period unchanged, when SYSCLK is raised from 16 to 84 MHz
+
+#![no_main]
+#![no_std]
+
+use app as _;
+
+use app::blinky;
+use app::bus::Mmio;
+use app::clocks::Request;
+use app::time::{self, DurationExt as _, Hertz, Instant};
+use cortex_m::{asm, iprintln, peripheral::itm::Stim};
+use cortex_m_rt::entry;
+
+const MHZ: u32 = 1_000_000;
+
+// burns CPU cycles by just looping `i` times (as in `bare2.rs`)
+#[inline(never)]
+fn wait(i: u32) {
+    for _ in 0..i {
+        asm::nop();
+    }
+}
+
+// blinks the LED `n` times, reporting the measured period
+fn blink(stim: &mut Stim, bus: &Mmio, n: u32) {
+    let half = 250.millis();
+    for _ in 0..n {
+        let start = Instant::now();
+        blinky::led_on(bus);
+        time::delay(half);
+        blinky::led_off(bus);
+        time::delay(half);
+        iprintln!(stim, "period {:?}", start.elapsed());
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let mut c = cortex_m::Peripherals::take().unwrap();
+    c.DCB.enable_trace();
+    c.DWT.enable_cycle_counter();
+    let stim = &mut c.ITM.stim[0];
+    let bus = unsafe { Mmio::new() };
+
+    let start = Instant::now();
+    wait(1_000_000);
+    let end = Instant::now();
+    iprintln!(
+        stim,
+        "wait {} cycles, {:?} at {:?}",
+        end.cycles_since(start),
+        end - start,
+        time::sysclk()
+    );
+
+    blinky::init(&bus);
+    blink(stim, &bus, 4);
+
+    let plan = Request::hsi(84 * MHZ).pclk1(42 * MHZ).plan().unwrap();
+    plan.apply(&bus);
+    // the ITM (SWO) is clocked by SYSCLK, from here on at 84 MHz
+    time::set_sysclk(Hertz(plan.sysclk));
+    blink(stim, &bus, 4);
+
+    loop {
+        asm::wfi();
+    }
+}
+
+// Assignments
+// 0. Compile and run the example.
+//
+//    > cargo run --example time --release
+//
+//    Compare the cycles of `wait` to the release build of `bare2.rs`.
+//
+//    ** your answer here **
+//
+// 1. After the clock is raised, is the LED blinking at the same rate? Comment
+//    out `time::set_sysclk`. Which period is reported, and which is the real
+//    one?
+//
+//    ** your answer here **
+//
+// 2. At 84 MHz, how long can be measured by `Instant` before CYCCNT wraps and
+//    the measurement is wrong? And before two instants compare in the wrong
+//    order?
+//
+//    ** your answer here **
@@ -0,0 +1,226 @@
+//! Benchmark results, host side
+//!
+//! Parses the lines written for the `bench!` reports (see `src/bench.rs` of the
+//! `app`), in the output of `itm-decode` or a semihosting console, and compares
+//! two runs, e.g., of a `dev` and a `release` build:
+//!
+//! ``` text
+//! bench wait profile=release clock=16000000 n=10 min=4010 max=4017 mean=4012 jitter=7 overhead=2 ...
+//! ```
+//!
+//! Text before `bench` (e.g., the `[0] ` port prefix) is skipped, as are the
+//! keys not known here.
+
+use std::fmt;
+
+/// One `bench!` report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub profile: String,
+    pub clock: u32,
+    pub n: u32,
+    pub min: u32,
+    pub max: u32,
+    pub mean: u32,
+    pub jitter: u32,
+    pub overhead: u32,
+}
+
+impl Record {
+    /// The mean in nanoseconds
+    pub fn mean_ns(&self) -> u64 {
+        u64::from(self.mean) * 1_000_000_000 / u64::from(self.clock)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// a key (`clock`, `mean`, ...) is missing
+    Missing(&'static str),
+    /// a value is not a number, or the clock is 0
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Missing(key) => write!(f, "missing `{}`", key),
+            Error::Invalid(field) => write!(f, "invalid `{}`", field),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Parses a report line, `None` if it is not one
+pub fn parse_line(line: &str) -> Option<Result<Record, Error>> {
+    let start = line.find("bench ")?;
+    let mut words = line[start..].split_whitespace().skip(1);
+    let name = words.next()?;
+    Some(parse_fields(name, words))
+}
+
+fn parse_fields<'a>(name: &str, words: impl Iterator<Item = &'a str>) -> Result<Record, Error> {
+    let mut profile = None;
+    let mut values = [None; 7];
+    const KEYS: [&str; 7] = ["clock", "n", "min", "max", "mean", "jitter", "overhead"];
+
+    for word in words {
+        let (key, value) = match word.find('=') {
+            Some(i) => (&word[..i], &word[i + 1..]),
+            None => return Err(Error::Invalid(word.into())),
+        };
+        if key == "profile" {
+            profile = Some(value);
+        } else if let Some(i) = KEYS.iter().position(|&k| k == key) {
+            values[i] = Some(value.parse().map_err(|_| Error::Invalid(word.into()))?);
+        }
+    }
+
+    let mut values = KEYS
+        .iter()
+        .zip(values.iter())
+        .map(|(&k, v)| v.ok_or(Error::Missing(k)));
+    let mut next = || values.next().unwrap();
+    let record = Record {
+        name: name.into(),
+        profile: profile.ok_or(Error::Missing("profile"))?.into(),
+        clock: next()?,
+        n: next()?,
+        min: next()?,
+        max: next()?,
+        mean: next()?,
+        jitter: next()?,
+        overhead: next()?,
+    };
+    if record.clock == 0 {
+        return Err(Error::Invalid("clock=0".into()));
+    }
+    Ok(record)
+}
+
+/// The reports in `text`, a later report of the same name replacing an earlier
+/// one; the malformed lines are returned as errors (with the line number)
+pub fn parse(text: &str) -> (Vec<Record>, Vec<(usize, Error)>) {
+    let mut records: Vec<Record> = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        match parse_line(line) {
+            Some(Ok(record)) => match records.iter_mut().find(|r| r.name == record.name) {
+                Some(r) => *r = record,
+                None => records.push(record),
+            },
+            Some(Err(e)) => errors.push((i + 1, e)),
+            None => {}
+        }
+    }
+    (records, errors)
+}
+
+/// A benchmark of two runs (missing in one of them if `None`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row<'a> {
+    pub name: &'a str,
+    pub base: Option<&'a Record>,
+    pub other: Option<&'a Record>,
+}
+
+impl Row<'_> {
+    /// How many times faster `other` is than `base` (by the mean time)
+    pub fn speedup(&self) -> Option<f64> {
+        match (self.base, self.other) {
+            (Some(b), Some(o)) if o.mean_ns() > 0 => Some(b.mean_ns() as f64 / o.mean_ns() as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Pairs the benchmarks by name, in the order of `base`, then the ones only in
+/// `other`
+pub fn compare<'a>(base: &'a [Record], other: &'a [Record]) -> Vec<Row<'a>> {
+    let mut rows: Vec<_> = base
+        .iter()
+        .map(|b| Row {
+            name: &b.name,
+            base: Some(b),
+            other: other.iter().find(|o| o.name == b.name),
+        })
+        .collect();
+    rows.extend(
+        other
+            .iter()
+            .filter(|o| !base.iter().any(|b| b.name == o.name))
+            .map(|o| Row {
+                name: &o.name,
+                base: None,
+                other: Some(o),
+            }),
+    );
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "bench wait profile=release clock=16000000 n=10 min=4010 max=4017 \
+                        mean=4012 jitter=7 overhead=2 min_ns=250625 max_ns=251062 mean_ns=250750";
+
+    #[test]
+    fn line() {
+        let record = parse_line(&format!("[0] {}", LINE)).unwrap().unwrap();
+        assert_eq!(
+            record,
+            Record {
+                name: "wait".into(),
+                profile: "release".into(),
+                clock: 16_000_000,
+                n: 10,
+                min: 4010,
+                max: 4017,
+                mean: 4012,
+                jitter: 7,
+                overhead: 2,
+            }
+        );
+        assert_eq!(record.mean_ns(), 250_750);
+        assert_eq!(parse_line("bare2"), None);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse_line("bench x profile=dev clock=16000000 n=1 min=1 max=1 mean=1 jitter=0"),
+            Some(Err(Error::Missing("overhead")))
+        );
+        assert_eq!(
+            parse_line("bench x profile=dev n=-1"),
+            Some(Err(Error::Invalid("n=-1".into())))
+        );
+        let (records, errors) = parse(&format!("{}\nbench x clock\n{}\n", LINE, LINE));
+        assert_eq!(records.len(), 1);
+        assert_eq!(errors, [(2, Error::Invalid("clock".into()))]);
+    }
+
+    #[test]
+    fn rows() {
+        let (dev, _) = parse(
+            "bench wait profile=dev clock=16000000 n=10 min=561000100 max=561000200 \
+             mean=561000167 jitter=100 overhead=8\n\
+             bench only_dev profile=dev clock=16000000 n=1 min=1 max=1 mean=1 jitter=0 overhead=8",
+        );
+        let (release, _) = parse(
+            "bench wait profile=release clock=16000000 n=10 min=4000000 max=4000010 \
+             mean=4000008 jitter=10 overhead=2\n\
+             bench only_release profile=release clock=84000000 n=1 min=1 max=1 mean=1 \
+             jitter=0 overhead=2",
+        );
+        let rows = compare(&dev, &release);
+        let names: Vec<_> = rows.iter().map(|r| r.name).collect();
+        assert_eq!(names, ["wait", "only_dev", "only_release"]);
+        assert_eq!(rows[0].speedup().map(|s| s.round()), Some(140.0));
+        assert_eq!(rows[1].speedup(), None);
+        assert_eq!(rows[2].base, None);
+    }
+}
@@ -0,0 +1,108 @@
+//! Compares the `bench!` reports of two runs, e.g., of a `dev` and a `release`
+//! build of the `bench` example
+//!
+//! > bench-compare BASE OTHER
+//!
+//! `BASE` and `OTHER` hold the reports, as written by `itm-decode` (stimulus
+//! port 0) or a semihosting console. For each benchmark, the mean in cycles and
+//! nanoseconds is printed, and how many times faster `OTHER` is. (Runs at
+//! different clocks are compared by time.)
+//!
+//! Malformed report lines are reported on stderr.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use itm_tools::bench::{self, Record};
+
+const USAGE: &str = "usage: bench-compare BASE OTHER";
+
+fn parse(args: impl Iterator<Item = String>) -> Result<(PathBuf, PathBuf), String> {
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.len() != 2 {
+        return Err("expected BASE and OTHER".into());
+    }
+    let other = paths.pop().unwrap();
+    let base = paths.pop().unwrap();
+    Ok((base, other))
+}
+
+fn main() {
+    let (base, other) = match parse(env::args().skip(1)) {
+        Ok(paths) => paths,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("error: {}", e);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&base, &other) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn read(path: &Path) -> io::Result<Vec<Record>> {
+    let (records, errors) = bench::parse(&fs::read_to_string(path)?);
+    for (line, e) in errors {
+        eprintln!("warning: {}:{}: {}", path.display(), line, e);
+    }
+    Ok(records)
+}
+
+// the profile of the runs (the first report), for the column headers
+fn profile(records: &[Record]) -> &str {
+    records.first().map_or("-", |r| &r.profile)
+}
+
+fn mean(record: Option<&Record>) -> String {
+    match record {
+        Some(r) => format!("{} ({} ns)", r.mean, r.mean_ns()),
+        None => "-".into(),
+    }
+}
+
+fn run(base: &Path, other: &Path) -> io::Result<()> {
+    let base = read(base)?;
+    let other = read(other)?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    writeln!(
+        stdout,
+        "{:<16} {:>28} {:>28} {:>9}",
+        "bench",
+        profile(&base),
+        profile(&other),
+        "speedup"
+    )?;
+    for row in bench::compare(&base, &other) {
+        let speedup = match row.speedup() {
+            Some(s) => format!("{:.2}x", s),
+            None => "-".into(),
+        };
+        writeln!(
+            stdout,
+            "{:<16} {:>28} {:>28} {:>9}",
+            row.name,
+            mean(row.base),
+            mean(row.other),
+            speedup
+        )?;
+    }
+    stdout.flush()
+}
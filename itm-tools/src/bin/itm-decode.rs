@@ -0,0 +1,152 @@
+//! Decodes an ITM capture (file or fifo), as written by `openocd`
+//!
+//! > itm-decode [-f] [-p PORT].. [-o DIR] [--packets] FILE
+//!
+//! - `-f`, `--follow`, keep reading at end of file (like `tail -f`)
+//! - `-p`, `--port`, stimulus port to output (repeatable, all if not given)
+//! - `-o`, `--output`, write the stream of port N to `DIR/stimN.txt`, instead of
+//!   stdout (where lines are prefixed by `[N] ` if more than one port)
+//! - `--packets`, print every decoded packet instead (timestamps, DWT, ...)
+//!
+//! Malformed packets are reported on stderr, and decoding continues.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+
+use itm_tools::{tail, Decoder, Demux};
+
+const USAGE: &str = "usage: itm-decode [-f] [-p PORT].. [-o DIR] [--packets] FILE";
+
+struct Options {
+    follow: bool,
+    ports: Vec<u8>,
+    output: Option<PathBuf>,
+    packets: bool,
+    file: PathBuf,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut follow = false;
+    let mut ports = Vec::new();
+    let mut output = None;
+    let mut packets = false;
+    let mut file = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--follow" => follow = true,
+            "-p" | "--port" => {
+                let port = args.next().ok_or("missing PORT")?;
+                match port.parse() {
+                    Ok(p) if p < 32 => ports.push(p),
+                    _ => return Err(format!("invalid port `{}`, expected 0..31", port)),
+                }
+            }
+            "-o" | "--output" => output = Some(args.next().ok_or("missing DIR")?.into()),
+            "--packets" => packets = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if file.is_none() => file = Some(arg.into()),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    Ok(Options {
+        follow,
+        ports,
+        output,
+        packets,
+        file: file.ok_or("missing FILE")?,
+    })
+}
+
+fn main() {
+    let options = match parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("error: {}", e);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let mut decoder = Decoder::new();
+    let mut demux = Demux::new(&options.ports);
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    // one file per port, opened on first output
+    let mut files: Vec<Option<File>> = (0..32).map(|_| None).collect();
+    if let Some(dir) = &options.output {
+        fs::create_dir_all(dir)?;
+    }
+
+    let prefix = demux.ports().count() > 1;
+    tail::read(&options.file, options.follow, |bytes| {
+        for result in decoder.feed(bytes) {
+            let packet = match result {
+                Ok(packet) => packet,
+                Err(e) => {
+                    eprintln!("warning: {}", e);
+                    continue;
+                }
+            };
+
+            if options.packets {
+                writeln!(stdout, "{:?}", packet)?;
+                continue;
+            }
+
+            let port = match demux.push(&packet) {
+                Some(port) => port,
+                None => continue,
+            };
+
+            match &options.output {
+                Some(dir) => {
+                    let file = match &mut files[port as usize] {
+                        Some(file) => file,
+                        file => file.insert(File::create(dir.join(format!("stim{}.txt", port)))?),
+                    };
+                    file.write_all(&demux.take(port))?;
+                    file.flush()?;
+                }
+                None if prefix => {
+                    let lines = demux.take_lines(port);
+                    for line in lines.split_inclusive(|&b| b == b'\n') {
+                        write!(stdout, "[{}] ", port)?;
+                        stdout.write_all(line)?;
+                    }
+                }
+                None => stdout.write_all(&demux.take(port))?,
+            }
+        }
+        stdout.flush()
+    })?;
+
+    // incomplete lines at end of input
+    if options.output.is_none() && prefix {
+        for port in demux.ports().collect::<Vec<_>>() {
+            let rest = demux.take(port);
+            if !rest.is_empty() {
+                write!(stdout, "[{}] ", port)?;
+                stdout.write_all(&rest)?;
+                writeln!(stdout)?;
+            }
+        }
+    }
+    stdout.flush()
+}
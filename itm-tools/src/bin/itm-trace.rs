@@ -0,0 +1,133 @@
+//! Formats the deferred traces (`trace!`) of an `app` binary
+//!
+//! > itm-trace [-f] [-l] ELF FILE
+//!
+//! - `-f`, `--follow`, keep reading at end of file (like `tail -f`)
+//! - `-l`, `--location`, append the `file:line:column` of the `trace!`
+//!
+//! Traces (stimulus port 1) are formatted using the format strings interned in
+//! the `.trace` section of the `ELF` (built with the `trace-deferred` feature).
+//! Text on stimulus port 0 (`iprintln!`) is passed through, in order.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+
+use itm_tools::trace::{self, FrameDecoder, Table};
+use itm_tools::{tail, Decoder, Demux, Packet};
+
+const USAGE: &str = "usage: itm-trace [-f] [-l] ELF FILE";
+
+struct Options {
+    follow: bool,
+    location: bool,
+    elf: PathBuf,
+    file: PathBuf,
+}
+
+fn parse(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut follow = false;
+    let mut location = false;
+    let mut paths = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "-f" | "--follow" => follow = true,
+            "-l" | "--location" => location = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.len() != 2 {
+        return Err("expected ELF and FILE".into());
+    }
+    let file = paths.pop().unwrap();
+    let elf = paths.pop().unwrap();
+    Ok(Options {
+        follow,
+        location,
+        elf,
+        file,
+    })
+}
+
+fn main() {
+    let options = match parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("error: {}", e);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let table = Table::from_elf(&fs::read(&options.elf)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if table.is_empty() {
+        eprintln!("warning: no interned format strings, built without `trace-deferred`?");
+    }
+
+    let mut decoder = Decoder::new();
+    let mut demux = Demux::new(&[0, trace::PORT]);
+    let mut frames = FrameDecoder::new();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    tail::read(&options.file, options.follow, |bytes| {
+        for result in decoder.feed(bytes) {
+            let packet = match result {
+                Ok(packet) => packet,
+                Err(e) => {
+                    eprintln!("warning: {}", e);
+                    continue;
+                }
+            };
+
+            if packet == Packet::Overflow {
+                eprintln!("warning: ITM overflow, trace data lost");
+                frames.reset();
+                continue;
+            }
+
+            match demux.push(&packet) {
+                Some(0) => stdout.write_all(&demux.take_lines(0))?,
+                Some(_) => {
+                    for frame in frames.push(&demux.take(trace::PORT)) {
+                        let frame = match frame {
+                            Ok(frame) => frame,
+                            Err(e) => {
+                                eprintln!("warning: {}", e);
+                                continue;
+                            }
+                        };
+                        write!(stdout, "{}", table.format(&frame))?;
+                        if let (true, Some(format)) = (options.location, table.get(frame.id)) {
+                            write!(stdout, " ({})", format.location)?;
+                        }
+                        writeln!(stdout)?;
+                    }
+                }
+                None => {}
+            }
+        }
+        stdout.flush()
+    })?;
+
+    // incomplete line at end of input
+    stdout.write_all(&demux.take(0))?;
+    stdout.flush()
+}
@@ -0,0 +1,509 @@
+//! ITM packet decoder
+//!
+//! Bytes are pushed one at a time (as they arrive from the fifo), and a packet
+//! is returned when complete. Header formats (ARMv7-M ARM, D4.2):
+//!
+//! ``` text
+//! 0b0000_0000             synchronization (>= 47 zero bits followed by a one bit)
+//! 0b0111_0000             overflow
+//! 0b0TTT_0000             local timestamp, format 2 (TTT = 1..6)
+//! 0b11TC_0000             local timestamp, format 1 (continued)
+//! 0b1001_0100             global timestamp, format 1 (continued)
+//! 0b1011_0100             global timestamp, format 2 (continued)
+//! 0bCEEE_1S00             extension (continued)
+//! 0bAAAA_A0SS             instrumentation, stimulus port A, payload size SS
+//! 0bAAAA_A1SS             hardware source (DWT), discriminator A, payload size SS
+//! ```
+
+use std::fmt;
+
+/// Local timestamp relation to the corresponding packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampControl {
+    /// in sync with the packet
+    Sync,
+    /// the timestamp was delayed relative to the packet
+    TimestampDelayed,
+    /// the packet was delayed relative to the timestamp
+    PacketDelayed,
+    /// both were delayed
+    BothDelayed,
+}
+
+/// Exception trace function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionFunction {
+    Enter,
+    Exit,
+    Return,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Sync,
+    Overflow,
+    /// software source, written to `ITM.stim[port]` (1, 2 or 4 bytes)
+    Instrumentation {
+        port: u8,
+        payload: Vec<u8>,
+    },
+    /// cycles since the previous local timestamp
+    LocalTimestamp {
+        delta: u32,
+        tc: TimestampControl,
+    },
+    /// global timestamp bits [25:0]
+    GlobalTimestamp1 {
+        low: u32,
+        clock_change: bool,
+        wrap: bool,
+    },
+    /// global timestamp bits [47:26] (or [63:26])
+    GlobalTimestamp2 {
+        high: u64,
+    },
+    Extension {
+        info: u32,
+        hardware: bool,
+    },
+    /// DWT event counter wrap, CPI/EXC/SLEEP/LSU/FOLD/CYC flags
+    EventCounter {
+        flags: u8,
+    },
+    ExceptionTrace {
+        number: u16,
+        function: ExceptionFunction,
+    },
+    /// periodic PC sample, `None` if the core was sleeping
+    PcSample {
+        pc: Option<u32>,
+    },
+    DataTracePc {
+        comparator: u8,
+        pc: u32,
+    },
+    DataTraceAddress {
+        comparator: u8,
+        address: u16,
+    },
+    DataTraceValue {
+        comparator: u8,
+        write: bool,
+        value: u32,
+    },
+    /// any other hardware source packet
+    Hardware {
+        discriminator: u8,
+        payload: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// reserved or invalid header byte
+    InvalidHeader(u8),
+    /// a continued packet longer than the protocol allows
+    PayloadTooLong(u8),
+    /// zero bits not followed by a sync (0x80) byte
+    BrokenSync,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidHeader(h) => write!(f, "invalid header 0x{:02x}", h),
+            Error::PayloadTooLong(h) => write!(f, "payload too long, header 0x{:02x}", h),
+            Error::BrokenSync => write!(f, "broken synchronization packet"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    // `n` zero bytes of a sync packet seen
+    Sync(u8),
+    // `len` payload bytes expected
+    Source { header: u8, len: usize },
+    // payload bytes with continuation bit, at most `max`
+    Continued { header: u8, max: usize },
+}
+
+/// Streaming ITM decoder
+pub struct Decoder {
+    state: State,
+    payload: Vec<u8>,
+    // a packet completed by the byte after a broken sync, see `pending`
+    pending: Option<Result<Packet, Error>>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            state: State::Header,
+            payload: Vec::with_capacity(8),
+            pending: None,
+        }
+    }
+
+    /// Decode all `bytes`, in order
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Result<Packet, Error>> {
+        let mut packets = Vec::new();
+        for &b in bytes {
+            packets.extend(self.push(b));
+            packets.extend(self.pending());
+        }
+        packets
+    }
+
+    /// Push the next byte, returns a packet (or an error) when complete
+    ///
+    /// A broken sync is reported first, the byte after it may complete a
+    /// packet of its own (e.g., an overflow), returned by `pending`.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Packet, Error>> {
+        match self.state {
+            State::Header => self.header(byte),
+            State::Sync(n) => match byte {
+                0x00 => {
+                    self.state = State::Sync(n.saturating_add(1));
+                    None
+                }
+                // 5 zero bytes gives 47 zero bits followed by the one bit
+                0x80 if n >= 5 => self.done(Ok(Packet::Sync)),
+                _ => {
+                    // resynchronize on this byte
+                    self.state = State::Header;
+                    // an invalid header (e.g., an early 0x80) is part of the
+                    // broken sync
+                    self.pending = self.header(byte).filter(|p| p.is_ok());
+                    Some(Err(Error::BrokenSync))
+                }
+            },
+            State::Source { header, len } => {
+                self.payload.push(byte);
+                if self.payload.len() == len {
+                    let packet = source(header, &self.payload);
+                    self.done(packet)
+                } else {
+                    None
+                }
+            }
+            State::Continued { header, max } => {
+                self.payload.push(byte);
+                if byte & 0x80 == 0 {
+                    let packet = continued(header, &self.payload);
+                    self.done(Ok(packet))
+                } else if self.payload.len() == max {
+                    self.done(Err(Error::PayloadTooLong(header)))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// The packet completed after a broken sync, to be taken after `push`
+    pub fn pending(&mut self) -> Option<Result<Packet, Error>> {
+        self.pending.take()
+    }
+
+    fn header(&mut self, h: u8) -> Option<Result<Packet, Error>> {
+        self.payload.clear();
+        match h {
+            0x00 => {
+                self.state = State::Sync(1);
+                None
+            }
+            0x70 => Some(Ok(Packet::Overflow)),
+            // local timestamp, format 2
+            _ if h & 0x8f == 0x00 => Some(Ok(Packet::LocalTimestamp {
+                delta: u32::from(h >> 4),
+                tc: TimestampControl::Sync,
+            })),
+            // local timestamp, format 1
+            _ if h & 0xcf == 0xc0 => self.continued(h, 4),
+            0x94 => self.continued(h, 4),
+            0xb4 => self.continued(h, 6),
+            // extension
+            _ if h & 0x0b == 0x08 => {
+                if h & 0x80 == 0 {
+                    Some(Ok(continued(h, &[])))
+                } else {
+                    self.continued(h, 4)
+                }
+            }
+            // instrumentation, hardware source
+            _ if h & 0x03 != 0 => {
+                let len = match h & 0x03 {
+                    0b01 => 1,
+                    0b10 => 2,
+                    _ => 4,
+                };
+                self.state = State::Source { header: h, len };
+                None
+            }
+            _ => Some(Err(Error::InvalidHeader(h))),
+        }
+    }
+
+    fn continued(&mut self, header: u8, max: usize) -> Option<Result<Packet, Error>> {
+        self.state = State::Continued { header, max };
+        None
+    }
+
+    fn done(&mut self, packet: Result<Packet, Error>) -> Option<Result<Packet, Error>> {
+        self.state = State::Header;
+        Some(packet)
+    }
+}
+
+// little endian value of a source payload
+fn value(payload: &[u8]) -> u32 {
+    payload
+        .iter()
+        .rev()
+        .fold(0, |v, &b| (v << 8) | u32::from(b))
+}
+
+// value of 7 bit continued payload
+fn value7(payload: &[u8]) -> u64 {
+    payload
+        .iter()
+        .rev()
+        .fold(0, |v, &b| (v << 7) | u64::from(b & 0x7f))
+}
+
+fn source(h: u8, payload: &[u8]) -> Result<Packet, Error> {
+    let a = h >> 3;
+    if h & 0x04 == 0 {
+        return Ok(Packet::Instrumentation {
+            port: a,
+            payload: payload.to_vec(),
+        });
+    }
+
+    let v = value(payload);
+    Ok(match (a, payload.len()) {
+        (0, 1) => Packet::EventCounter { flags: payload[0] },
+        (1, 2) => Packet::ExceptionTrace {
+            number: (v & 0x1ff) as u16,
+            function: match (v >> 12) & 0b11 {
+                0b01 => ExceptionFunction::Enter,
+                0b10 => ExceptionFunction::Exit,
+                0b11 => ExceptionFunction::Return,
+                _ => return Err(Error::InvalidHeader(h)),
+            },
+        },
+        (2, 4) => Packet::PcSample { pc: Some(v) },
+        (2, 1) if v == 0 => Packet::PcSample { pc: None },
+        (8..=15, _) if a & 1 == 0 && payload.len() == 4 => Packet::DataTracePc {
+            comparator: (a >> 1) & 0b11,
+            pc: v,
+        },
+        (8..=15, 2) if a & 1 == 1 => Packet::DataTraceAddress {
+            comparator: (a >> 1) & 0b11,
+            address: v as u16,
+        },
+        (16..=23, _) => Packet::DataTraceValue {
+            comparator: (a >> 1) & 0b11,
+            write: a & 1 == 1,
+            value: v,
+        },
+        _ => Packet::Hardware {
+            discriminator: a,
+            payload: payload.to_vec(),
+        },
+    })
+}
+
+fn continued(h: u8, payload: &[u8]) -> Packet {
+    let v = value7(payload);
+    match h {
+        0x94 => {
+            // the last byte (if 4) holds bits [25:21], ClkCh and Wrap
+            let last = if payload.len() == 4 { payload[3] } else { 0 };
+            Packet::GlobalTimestamp1 {
+                low: (v & 0x03ff_ffff) as u32,
+                clock_change: last & 0x20 != 0,
+                wrap: last & 0x40 != 0,
+            }
+        }
+        0xb4 => Packet::GlobalTimestamp2 { high: v },
+        _ if h & 0x0f == 0 => Packet::LocalTimestamp {
+            delta: v as u32,
+            tc: match (h >> 4) & 0b11 {
+                0b00 => TimestampControl::Sync,
+                0b01 => TimestampControl::TimestampDelayed,
+                0b10 => TimestampControl::PacketDelayed,
+                _ => TimestampControl::BothDelayed,
+            },
+        },
+        // extension, EX[2:0] in the header, then the payload
+        _ => Packet::Extension {
+            info: ((h >> 4) & 0b111) as u32 | (v << 3) as u32,
+            hardware: h & 0x04 != 0,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Vec<Result<Packet, Error>> {
+        Decoder::new().feed(bytes)
+    }
+
+    #[test]
+    fn sync_and_overflow() {
+        assert_eq!(
+            decode(&[0, 0, 0, 0, 0, 0x80, 0x70]),
+            [Ok(Packet::Sync), Ok(Packet::Overflow)]
+        );
+        assert_eq!(decode(&[0, 0, 0x80]), [Err(Error::BrokenSync)]);
+        // resynchronized on a packet of a single byte
+        assert_eq!(
+            decode(&[0, 0, 0x70, 0x30]),
+            [
+                Err(Error::BrokenSync),
+                Ok(Packet::Overflow),
+                Ok(Packet::LocalTimestamp {
+                    delta: 3,
+                    tc: TimestampControl::Sync
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn instrumentation() {
+        assert_eq!(
+            decode(&[0x01, b'a', 0x0a, b'b', b'c', 0x13, 1, 2, 3, 4]),
+            [
+                Ok(Packet::Instrumentation {
+                    port: 0,
+                    payload: b"a".to_vec()
+                }),
+                Ok(Packet::Instrumentation {
+                    port: 1,
+                    payload: b"bc".to_vec()
+                }),
+                Ok(Packet::Instrumentation {
+                    port: 2,
+                    payload: vec![1, 2, 3, 4]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_timestamps() {
+        assert_eq!(
+            decode(&[0x30, 0xc0, 0x85, 0x01, 0xe0, 0x7f]),
+            [
+                Ok(Packet::LocalTimestamp {
+                    delta: 3,
+                    tc: TimestampControl::Sync
+                }),
+                Ok(Packet::LocalTimestamp {
+                    delta: 0x85,
+                    tc: TimestampControl::Sync
+                }),
+                Ok(Packet::LocalTimestamp {
+                    delta: 0x7f,
+                    tc: TimestampControl::PacketDelayed
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn global_timestamps() {
+        assert_eq!(
+            decode(&[0x94, 0x81, 0x80, 0x80, 0x61, 0xb4, 0x82, 0x01]),
+            [
+                Ok(Packet::GlobalTimestamp1 {
+                    low: 1 | 1 << 21,
+                    clock_change: true,
+                    wrap: true
+                }),
+                Ok(Packet::GlobalTimestamp2 { high: 2 | 1 << 7 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn hardware_sources() {
+        assert_eq!(
+            decode(&[
+                0x05, 0x20, // event counter, CYC
+                0x0e, 0x0f, 0x10, // exception 15 (SysTick) entered
+                0x0e, 0x0f, 0x30, // exception 15 returned
+                0x17, 0x38, 0x07, 0x00, 0x08, // PC sample
+                0x15, 0x00, // sleeping
+                0x47, 0x00, 0x00, 0x00, 0x20, // data trace PC, comparator 0
+                0x4e, 0x34, 0x12, // data trace address, comparator 0
+                0x8d, 0x2a, // data trace value, write, comparator 0
+                0x46, 0x34, 0x12, // not an address (of a PC comparator)
+            ]),
+            [
+                Ok(Packet::EventCounter { flags: 0x20 }),
+                Ok(Packet::ExceptionTrace {
+                    number: 15,
+                    function: ExceptionFunction::Enter
+                }),
+                Ok(Packet::ExceptionTrace {
+                    number: 15,
+                    function: ExceptionFunction::Return
+                }),
+                Ok(Packet::PcSample {
+                    pc: Some(0x0800_0738)
+                }),
+                Ok(Packet::PcSample { pc: None }),
+                Ok(Packet::DataTracePc {
+                    comparator: 0,
+                    pc: 0x2000_0000
+                }),
+                Ok(Packet::DataTraceAddress {
+                    comparator: 0,
+                    address: 0x1234
+                }),
+                Ok(Packet::DataTraceValue {
+                    comparator: 0,
+                    write: true,
+                    value: 42
+                }),
+                Ok(Packet::Hardware {
+                    discriminator: 8,
+                    payload: vec![0x34, 0x12]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_and_too_long() {
+        assert_eq!(
+            decode(&[0x04, 0x01, b'x']),
+            [
+                Err(Error::InvalidHeader(0x04)),
+                Ok(Packet::Instrumentation {
+                    port: 0,
+                    payload: b"x".to_vec()
+                })
+            ]
+        );
+        assert_eq!(
+            decode(&[0xc0, 0x80, 0x80, 0x80, 0x80]),
+            [Err(Error::PayloadTooLong(0xc0))]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Stimulus port demultiplexer
+//!
+//! Collects the payload of instrumentation packets into one byte stream per
+//! stimulus port (0..32), e.g., ports 0, 1 and 2 as enabled in `openocd.gdb`.
+//! All other packets are ignored.
+
+use crate::decoder::Packet;
+
+/// Number of ITM stimulus ports
+pub const PORTS: usize = 32;
+
+pub struct Demux {
+    // enabled ports, bit n for port n
+    enabled: u32,
+    streams: Vec<Vec<u8>>,
+}
+
+impl Default for Demux {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl Demux {
+    /// Demultiplexes the given `ports` (all ports if empty)
+    pub fn new(ports: &[u8]) -> Self {
+        let enabled = if ports.is_empty() {
+            u32::MAX
+        } else {
+            ports.iter().fold(0, |e, &p| {
+                assert!((p as usize) < PORTS, "stimulus port {} out of range", p);
+                e | 1 << p
+            })
+        };
+        Demux {
+            enabled,
+            streams: vec![Vec::new(); PORTS],
+        }
+    }
+
+    pub fn is_enabled(&self, port: u8) -> bool {
+        (port as usize) < PORTS && self.enabled & 1 << port != 0
+    }
+
+    /// The enabled ports, in order
+    pub fn ports(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..PORTS as u8).filter(move |&p| self.is_enabled(p))
+    }
+
+    /// Routes `packet`, returns the port if its stream was appended to
+    pub fn push(&mut self, packet: &Packet) -> Option<u8> {
+        match packet {
+            Packet::Instrumentation { port, payload } if self.is_enabled(*port) => {
+                self.streams[*port as usize].extend_from_slice(payload);
+                Some(*port)
+            }
+            _ => None,
+        }
+    }
+
+    /// The bytes received on `port`, not yet taken
+    pub fn stream(&self, port: u8) -> &[u8] {
+        &self.streams[port as usize]
+    }
+
+    /// Takes all bytes received on `port`
+    pub fn take(&mut self, port: u8) -> Vec<u8> {
+        std::mem::take(&mut self.streams[port as usize])
+    }
+
+    /// Takes the complete lines (up to and including the last `\n`) received
+    /// on `port`, the rest is kept
+    pub fn take_lines(&mut self, port: u8) -> Vec<u8> {
+        let stream = &mut self.streams[port as usize];
+        match stream.iter().rposition(|&b| b == b'\n') {
+            Some(i) => {
+                let rest = stream.split_off(i + 1);
+                std::mem::replace(stream, rest)
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stim(port: u8, payload: &[u8]) -> Packet {
+        Packet::Instrumentation {
+            port,
+            payload: payload.to_vec(),
+        }
+    }
+
+    #[test]
+    fn separate_streams() {
+        let mut demux = Demux::new(&[0, 2]);
+        assert_eq!(demux.ports().collect::<Vec<_>>(), [0, 2]);
+
+        assert_eq!(demux.push(&stim(0, b"ab")), Some(0));
+        assert_eq!(demux.push(&stim(1, b"xx")), None);
+        assert_eq!(demux.push(&Packet::Overflow), None);
+        assert_eq!(demux.push(&stim(2, b"c")), Some(2));
+        assert_eq!(demux.push(&stim(0, b"d")), Some(0));
+
+        assert_eq!(demux.stream(0), b"abd");
+        assert_eq!(demux.stream(1), b"");
+        assert_eq!(demux.take(2), b"c");
+        assert_eq!(demux.stream(2), b"");
+    }
+
+    #[test]
+    fn lines() {
+        let mut demux = Demux::default();
+        demux.push(&stim(5, b"a\nb"));
+        assert_eq!(demux.take_lines(5), b"a\n");
+        assert_eq!(demux.take_lines(5), b"");
+        demux.push(&stim(5, b"c\nd\ne"));
+        assert_eq!(demux.take_lines(5), b"bc\nd\n");
+        assert_eq!(demux.stream(5), b"e");
+    }
+}
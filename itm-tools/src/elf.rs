@@ -0,0 +1,139 @@
+//! Minimal ELF32 (little endian) symbol table reader
+//!
+//! Only what is needed to look up the symbols of a section, e.g., the interned
+//! format strings in the `.trace` section of an `app` binary.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// not a 32 bit, little endian elf
+    NotElf32,
+    /// an offset or index outside of the file
+    Truncated,
+    /// the file has no symbol table (stripped)
+    NoSymbols,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotElf32 => write!(f, "not a 32 bit little endian elf file"),
+            Error::Truncated => write!(f, "truncated elf file"),
+            Error::NoSymbols => write!(f, "no symbol table (stripped elf file?)"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// section types
+const SHT_SYMTAB: u32 = 2;
+
+struct Section {
+    name: u32,
+    ty: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+}
+
+fn u16_at(elf: &[u8], at: usize) -> Result<u16, Error> {
+    elf.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(Error::Truncated)
+}
+
+fn u32_at(elf: &[u8], at: usize) -> Result<u32, Error> {
+    elf.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(Error::Truncated)
+}
+
+// the zero terminated string at `at` in the string table `strtab`
+fn str_at(elf: &[u8], strtab: &Section, at: u32) -> Result<String, Error> {
+    let end = strtab
+        .offset
+        .checked_add(strtab.size)
+        .ok_or(Error::Truncated)?;
+    let table = elf
+        .get(strtab.offset as usize..end as usize)
+        .ok_or(Error::Truncated)?;
+    let s = table.get(at as usize..).ok_or(Error::Truncated)?;
+    let end = s.iter().position(|&b| b == 0).ok_or(Error::Truncated)?;
+    Ok(String::from_utf8_lossy(&s[..end]).into_owned())
+}
+
+fn sections(elf: &[u8]) -> Result<Vec<Section>, Error> {
+    if elf.get(..6) != Some(&[0x7f, b'E', b'L', b'F', 1, 1]) {
+        return Err(Error::NotElf32);
+    }
+    let shoff = u32_at(elf, 0x20)? as usize;
+    let shentsize = u16_at(elf, 0x2e)? as usize;
+    let shnum = u16_at(elf, 0x30)? as usize;
+
+    (0..shnum)
+        .map(|i| {
+            let sh = shoff + i * shentsize;
+            Ok(Section {
+                name: u32_at(elf, sh)?,
+                ty: u32_at(elf, sh + 0x04)?,
+                offset: u32_at(elf, sh + 0x10)?,
+                size: u32_at(elf, sh + 0x14)?,
+                link: u32_at(elf, sh + 0x18)?,
+            })
+        })
+        .collect()
+}
+
+/// The symbols defined in the section named `section`, in symbol table order
+pub fn symbols(elf: &[u8], section: &str) -> Result<Vec<Symbol>, Error> {
+    let sections = sections(elf)?;
+    let shstrndx = u16_at(elf, 0x32)? as usize;
+    let shstrtab = sections.get(shstrndx).ok_or(Error::Truncated)?;
+
+    // the index of the section, none if not in the file
+    let mut index = None;
+    for (i, s) in sections.iter().enumerate() {
+        if str_at(elf, shstrtab, s.name)? == section {
+            index = Some(i);
+        }
+    }
+
+    let symtab = sections
+        .iter()
+        .find(|s| s.ty == SHT_SYMTAB)
+        .ok_or(Error::NoSymbols)?;
+    let strtab = sections.get(symtab.link as usize).ok_or(Error::Truncated)?;
+
+    let index = match index {
+        Some(index) => index,
+        None => return Ok(Vec::new()),
+    };
+
+    // Elf32_Sym, 16 bytes: name, value, size, info, other, shndx
+    let end = symtab
+        .offset
+        .checked_add(symtab.size)
+        .ok_or(Error::Truncated)?;
+    let mut symbols = Vec::new();
+    for at in (symtab.offset..end).step_by(16) {
+        let at = at as usize;
+        if u16_at(elf, at + 14)? as usize == index {
+            let name = str_at(elf, strtab, u32_at(elf, at)?)?;
+            if !name.is_empty() {
+                symbols.push(Symbol {
+                    name,
+                    value: u32_at(elf, at + 4)?,
+                });
+            }
+        }
+    }
+    Ok(symbols)
+}
@@ -0,0 +1,24 @@
+//! Host side ITM/SWO trace tools
+//!
+//! - `decoder`, decodes the ITM byte stream (as captured in `/tmp/itm.fifo`,
+//!   see `openocd.gdb`) into packets
+//! - `demux`, splits the instrumentation packets into one stream per
+//!   stimulus port
+//! - `trace`, formats the deferred traces of `trace!` (port 1), using the
+//!   format strings interned in the elf (read by `elf`)
+//! - `tail`, reads a capture file or fifo
+//! - `bench`, parses the reports of `bench!` and compares two runs (e.g., a
+//!   `dev` and a `release` build)
+//!
+//! The protocol is specified in the ARMv7-M Architecture Reference Manual,
+//! appendix D4 (Debug ITM and DWT Packet Protocol).
+
+pub mod bench;
+pub mod decoder;
+pub mod demux;
+pub mod elf;
+pub mod tail;
+pub mod trace;
+
+pub use decoder::{Decoder, Error, Packet};
+pub use demux::Demux;
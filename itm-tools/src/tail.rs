@@ -0,0 +1,31 @@
+//! Reading a capture file or fifo, optionally following it (like `tail -f`)
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// Poll interval at end of file, when following
+pub const POLL: Duration = Duration::from_millis(100);
+
+/// Calls `f` with the bytes read from `path` until end of file, or forever if
+/// `follow` (a fifo reopened by `openocd` is picked up as well)
+pub fn read<F>(path: &Path, follow: bool, mut f: F) -> io::Result<()>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    let mut input = File::open(path)?;
+    let mut buf = [0; 1024];
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            if !follow {
+                return Ok(());
+            }
+            thread::sleep(POLL);
+            continue;
+        }
+        f(&buf[..n])?;
+    }
+}
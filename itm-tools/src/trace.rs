@@ -0,0 +1,445 @@
+//! Deferred formatting trace, host side
+//!
+//! Decodes the frames written by `trace!` (see `src/trace.rs` of the `app`) on
+//! stimulus port `PORT`, and formats them using the format strings interned in
+//! the `.trace` section of the elf:
+//!
+//! ``` text
+//! header: [15:0] id, [19:16] number of arguments, [31:20] argument types (3 bits each)
+//! 0..=4 arguments, one word each
+//! ```
+//!
+//! The supported format specs are `{}`, `{:?}`, and `{:#0Nx}` like specs
+//! (`#`, `0` and width being optional) for the `x`, `X`, `b` and `o` radix.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::elf;
+
+/// The stimulus port of the deferred traces
+pub const PORT: u8 = 1;
+
+/// Arguments per frame
+pub const MAX_ARGS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arg {
+    Unsigned(u32),
+    Signed(i32),
+    Bool(bool),
+    Char(char),
+    Float(f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub id: u16,
+    pub args: Vec<Arg>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// more than `MAX_ARGS` arguments, or an unknown argument type
+    InvalidHeader(u32),
+    /// a `Char` argument that is not a `char`
+    InvalidChar(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidHeader(h) => write!(f, "invalid trace header 0x{:08x}", h),
+            Error::InvalidChar(c) => write!(f, "invalid char argument 0x{:08x}", c),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Reassembles frames from the stimulus port stream
+#[derive(Default)]
+pub struct FrameDecoder {
+    // bytes of the current word
+    bytes: Vec<u8>,
+    // header of the current frame, and its arguments so far
+    header: Option<u32>,
+    args: Vec<u32>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops the partial frame, e.g., on an ITM overflow
+    pub fn reset(&mut self) {
+        self.bytes.clear();
+        self.header = None;
+        self.args.clear();
+    }
+
+    /// Decodes the next `bytes` of the stream
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<Frame, Error>> {
+        let mut frames = Vec::new();
+        for &b in bytes {
+            self.bytes.push(b);
+            if self.bytes.len() == 4 {
+                let word = u32::from_le_bytes([
+                    self.bytes[0],
+                    self.bytes[1],
+                    self.bytes[2],
+                    self.bytes[3],
+                ]);
+                self.bytes.clear();
+                if let Some(frame) = self.word(word) {
+                    frames.push(frame);
+                }
+            }
+        }
+        frames
+    }
+
+    fn word(&mut self, word: u32) -> Option<Result<Frame, Error>> {
+        let header = match self.header {
+            Some(header) => {
+                self.args.push(word);
+                header
+            }
+            None => {
+                if (word >> 16 & 0xf) as usize > MAX_ARGS {
+                    return Some(Err(Error::InvalidHeader(word)));
+                }
+                self.header = Some(word);
+                word
+            }
+        };
+
+        if self.args.len() < (header >> 16 & 0xf) as usize {
+            return None;
+        }
+        let frame = frame(header, &self.args);
+        self.header = None;
+        self.args.clear();
+        Some(frame)
+    }
+}
+
+fn frame(header: u32, words: &[u32]) -> Result<Frame, Error> {
+    let args = words
+        .iter()
+        .enumerate()
+        .map(|(i, &w)| {
+            Ok(match header >> (20 + 3 * i) & 0b111 {
+                0 => Arg::Unsigned(w),
+                1 => Arg::Signed(w as i32),
+                2 => Arg::Bool(w != 0),
+                3 => Arg::Char(std::char::from_u32(w).ok_or(Error::InvalidChar(w))?),
+                4 => Arg::Float(f32::from_bits(w)),
+                _ => return Err(Error::InvalidHeader(header)),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Frame {
+        id: header as u16,
+        args,
+    })
+}
+
+/// An interned format string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    pub string: String,
+    /// `file:line:column` of the `trace!`
+    pub location: String,
+}
+
+/// The interned format strings of an elf, by id
+#[derive(Debug, Default)]
+pub struct Table {
+    formats: BTreeMap<u16, Format>,
+}
+
+impl Table {
+    pub fn from_elf(elf: &[u8]) -> Result<Self, elf::Error> {
+        let mut formats = BTreeMap::new();
+        for symbol in elf::symbols(elf, ".trace")? {
+            // `<format string>@<file>:<line>:<column>`
+            let (string, location) = match symbol.name.rfind('@') {
+                Some(i) => (&symbol.name[..i], &symbol.name[i + 1..]),
+                None => (&symbol.name[..], ""),
+            };
+            formats.insert(
+                symbol.value as u16,
+                Format {
+                    string: string.to_owned(),
+                    location: location.to_owned(),
+                },
+            );
+        }
+        Ok(Table { formats })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.formats.is_empty()
+    }
+
+    pub fn get(&self, id: u16) -> Option<&Format> {
+        self.formats.get(&id)
+    }
+
+    /// Formats `frame`, unknown ids and format errors are shown in `<..>`
+    pub fn format(&self, frame: &Frame) -> String {
+        match self.get(frame.id) {
+            Some(format) => format_args(&format.string, &frame.args),
+            None => format!("<unknown trace id {}> {:?}", frame.id, frame.args),
+        }
+    }
+}
+
+// a parsed `{:#0Nx}` spec
+#[derive(Default)]
+struct Spec {
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    ty: Option<char>,
+}
+
+fn spec(s: &str) -> Option<Spec> {
+    let s = match s.strip_prefix(':') {
+        Some(s) => s,
+        None if s.is_empty() => return Some(Spec::default()),
+        None => return None,
+    };
+    let mut spec = Spec::default();
+    let mut chars = s.chars().peekable();
+    if chars.peek() == Some(&'#') {
+        spec.alternate = true;
+        chars.next();
+    }
+    if chars.peek() == Some(&'0') {
+        spec.zero = true;
+        chars.next();
+    }
+    while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+        spec.width = spec.width * 10 + d as usize;
+        chars.next();
+    }
+    spec.ty = chars.next();
+    match (spec.ty, chars.next()) {
+        (None, None) | (Some('?' | 'x' | 'X' | 'b' | 'o'), None) => Some(spec),
+        _ => None,
+    }
+}
+
+fn render(arg: Arg, spec: &Spec) -> String {
+    // radix formats give the bits (two's complement)
+    let bits = match arg {
+        Arg::Unsigned(v) => Some(v),
+        Arg::Signed(v) => Some(v as u32),
+        _ => None,
+    };
+    let (prefix, body) = match (spec.ty, bits) {
+        (Some('x'), Some(v)) => ("0x", format!("{:x}", v)),
+        (Some('X'), Some(v)) => ("0x", format!("{:X}", v)),
+        (Some('b'), Some(v)) => ("0b", format!("{:b}", v)),
+        (Some('o'), Some(v)) => ("0o", format!("{:o}", v)),
+        (Some('?'), _) => ("", debug(arg)),
+        _ => ("", display(arg)),
+    };
+    let (sign, body) = match body.strip_prefix('-') {
+        Some(body) => ("-", body.to_owned()),
+        None => ("", body),
+    };
+    let prefix = if spec.alternate { prefix } else { "" };
+
+    let len = sign.len() + prefix.len() + body.chars().count();
+    let pad = spec.width.saturating_sub(len);
+    let numeric = matches!(arg, Arg::Unsigned(_) | Arg::Signed(_) | Arg::Float(_));
+    if spec.zero && numeric {
+        format!("{}{}{}{}", sign, prefix, "0".repeat(pad), body)
+    } else if numeric {
+        format!("{}{}{}{}", " ".repeat(pad), sign, prefix, body)
+    } else {
+        format!("{}{}", body, " ".repeat(pad))
+    }
+}
+
+fn display(arg: Arg) -> String {
+    match arg {
+        Arg::Unsigned(v) => v.to_string(),
+        Arg::Signed(v) => v.to_string(),
+        Arg::Bool(v) => v.to_string(),
+        Arg::Char(v) => v.to_string(),
+        Arg::Float(v) => v.to_string(),
+    }
+}
+
+fn debug(arg: Arg) -> String {
+    match arg {
+        Arg::Char(v) => format!("{:?}", v),
+        Arg::Float(v) => format!("{:?}", v),
+        _ => display(arg),
+    }
+}
+
+/// Formats `args` by the format string `fmt`
+pub fn format_args(fmt: &str, args: &[Arg]) -> String {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut rest = fmt;
+    while let Some(i) = rest.find(['{', '}']) {
+        out += &rest[..i];
+        let brace = &rest[i..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            out += &brace[..1];
+            rest = &brace[2..];
+            continue;
+        }
+        let end = match brace.find('}') {
+            Some(end) if brace.starts_with('{') => end,
+            _ => {
+                out += "<unmatched brace>";
+                return out;
+            }
+        };
+        match spec(&brace[1..end]) {
+            Some(spec) => match args.next() {
+                Some(&arg) => out += &render(arg, &spec),
+                None => out += "<missing argument>",
+            },
+            None => out += &format!("<unsupported {}>", &brace[..=end]),
+        }
+        rest = &brace[end + 1..];
+    }
+    out += rest;
+    if args.next().is_some() {
+        out += " <extra arguments>";
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn frames() {
+        let mut d = FrameDecoder::new();
+        let bytes = words(&[
+            0x0003,                     // id 3, no arguments
+            0x0001 | 2 << 16 | 1 << 23, // id 1, unsigned, signed
+            97,
+            -5i32 as u32,
+            0x0002 | 3 << 16 | 3 << 20 | 2 << 23 | 4 << 26, // char, bool, float
+            'ä' as u32,
+            1,
+            1.5f32.to_bits(),
+        ]);
+        // split at an odd place
+        let mut frames = d.push(&bytes[..7]);
+        frames.extend(d.push(&bytes[7..]));
+        assert_eq!(
+            frames,
+            [
+                Ok(Frame {
+                    id: 3,
+                    args: vec![]
+                }),
+                Ok(Frame {
+                    id: 1,
+                    args: vec![Arg::Unsigned(97), Arg::Signed(-5)]
+                }),
+                Ok(Frame {
+                    id: 2,
+                    args: vec![Arg::Char('ä'), Arg::Bool(true), Arg::Float(1.5)]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let mut d = FrameDecoder::new();
+        assert_eq!(
+            d.push(&words(&[5 << 16])),
+            [Err(Error::InvalidHeader(5 << 16))]
+        );
+        assert_eq!(
+            d.push(&words(&[1 << 16 | 7 << 20, 0])),
+            [Err(Error::InvalidHeader(1 << 16 | 7 << 20))]
+        );
+        assert_eq!(
+            d.push(&words(&[1 << 16 | 3 << 20, 0xd800])),
+            [Err(Error::InvalidChar(0xd800))]
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let mut d = FrameDecoder::new();
+        assert!(d.push(&words(&[1 << 16, 0])[..6]).is_empty());
+        d.reset();
+        assert_eq!(
+            d.push(&words(&[9])),
+            [Ok(Frame {
+                id: 9,
+                args: vec![]
+            })]
+        );
+    }
+
+    #[test]
+    fn format() {
+        use Arg::*;
+        assert_eq!(
+            format_args("Ok {:?} ({} cycles)", &[Unsigned(97), Unsigned(12)]),
+            "Ok 97 (12 cycles)"
+        );
+        assert_eq!(format_args("{{{}}}", &[Signed(-1)]), "{-1}");
+        assert_eq!(
+            format_args(
+                "{:x} {:#X} {:#010x}",
+                &[Unsigned(255), Unsigned(255), Unsigned(255)]
+            ),
+            "ff 0xFF 0x000000ff"
+        );
+        assert_eq!(
+            format_args("{:#b} {:o}", &[Unsigned(5), Unsigned(8)]),
+            "0b101 10"
+        );
+        assert_eq!(format_args("{:x}", &[Signed(-1)]), "ffffffff");
+        assert_eq!(
+            format_args(
+                "[{:4}] [{:04}] [{:3}]",
+                &[Unsigned(7), Signed(-7), Char('a')]
+            ),
+            "[   7] [-007] [a  ]"
+        );
+        assert_eq!(
+            format_args(
+                "{} {:?} {} {:?}",
+                &[Char('a'), Char('a'), Float(1.0), Float(1.0)]
+            ),
+            "a 'a' 1 1.0"
+        );
+        assert_eq!(format_args("{}", &[Bool(false)]), "false");
+    }
+
+    #[test]
+    fn format_errors() {
+        use Arg::*;
+        assert_eq!(format_args("{} {}", &[Unsigned(1)]), "1 <missing argument>");
+        assert_eq!(
+            format_args("{}", &[Unsigned(1), Unsigned(2)]),
+            "1 <extra arguments>"
+        );
+        assert_eq!(format_args("{x} {}", &[Unsigned(1)]), "<unsupported {x}> 1");
+        assert_eq!(format_args("a } b", &[]), "a <unmatched brace>");
+    }
+}
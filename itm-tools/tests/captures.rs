@@ -0,0 +1,157 @@
+//! Decodes recorded ITM captures (`tests/captures/*.bin`) and compares the
+//! stimulus port streams with the expected output (and the `bench-compare` of
+//! the `bench-*.txt` reports)
+//!
+//! `trace.elf` is a minimal elf holding the `.trace` section and symbols, as
+//! linked for an `app` built with `trace-deferred`.
+//!
+//! > cargo test --manifest-path itm-tools/Cargo.toml --target x86_64-unknown-linux-gnu
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use itm_tools::decoder::{ExceptionFunction, Packet};
+use itm_tools::trace::{Format, Table};
+use itm_tools::{Decoder, Demux, Error};
+
+fn capture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("captures")
+        .join(name)
+}
+
+fn read(name: &str) -> Vec<u8> {
+    fs::read(capture(name)).unwrap()
+}
+
+fn demux(name: &str, ports: &[u8]) -> (Demux, Vec<Result<Packet, Error>>) {
+    let packets = Decoder::new().feed(&read(name));
+    let mut demux = Demux::new(ports);
+    for packet in packets.iter().flatten() {
+        demux.push(packet);
+    }
+    (demux, packets)
+}
+
+#[test]
+fn bare2() {
+    let (demux, packets) = demux("bare2.bin", &[0]);
+    assert_eq!(packets[0], Ok(Packet::Sync));
+    assert!(packets.iter().all(|p| p.is_ok()));
+    assert_eq!(demux.stream(0), &read("bare2.txt")[..]);
+}
+
+#[test]
+fn mixed_ports() {
+    let (demux, packets) = demux("mixed.bin", &[0, 1, 2]);
+    for port in 0..3 {
+        assert_eq!(
+            demux.stream(port),
+            &read(&format!("mixed-stim{}.txt", port))[..],
+            "stimulus port {}",
+            port
+        );
+    }
+
+    // the non instrumentation packets are decoded on the way
+    assert!(packets.contains(&Ok(Packet::Overflow)));
+    assert!(packets.contains(&Ok(Packet::ExceptionTrace {
+        number: 22,
+        function: ExceptionFunction::Enter
+    })));
+    assert!(packets.contains(&Ok(Packet::PcSample { pc: None })));
+    assert_eq!(
+        packets.iter().filter(|p| p.is_err()).collect::<Vec<_>>(),
+        [&Err(Error::InvalidHeader(0x04))]
+    );
+}
+
+#[test]
+fn mixed_single_port() {
+    let (demux, _) = demux("mixed.bin", &[1]);
+    assert_eq!(demux.stream(0), b"");
+    assert_eq!(demux.stream(1), &read("mixed-stim1.txt")[..]);
+}
+
+#[test]
+fn cli() {
+    let out = Command::new(env!("CARGO_BIN_EXE_itm-decode"))
+        .arg(capture("mixed.bin"))
+        .args(["-p", "0", "-p", "2"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "[0] init\n[2] 42\n[0] idle\n[2] 43\n"
+    );
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "warning: invalid header 0x04\n"
+    );
+}
+
+#[test]
+fn cli_output_dir() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("mixed");
+    let _ = fs::remove_dir_all(&dir);
+    let status = Command::new(env!("CARGO_BIN_EXE_itm-decode"))
+        .arg(capture("mixed.bin"))
+        .arg("-o")
+        .arg(&dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    for port in 0..3 {
+        assert_eq!(
+            fs::read(dir.join(format!("stim{}.txt", port))).unwrap(),
+            read(&format!("mixed-stim{}.txt", port))
+        );
+    }
+    assert!(!dir.join("stim3.txt").exists());
+}
+
+#[test]
+fn trace_table() {
+    let table = Table::from_elf(&read("trace.elf")).unwrap();
+    assert_eq!(
+        table.get(0),
+        Some(&Format {
+            string: "Ok {:?} ({} cycles)".into(),
+            location: "examples/bare8.rs:96:21".into()
+        })
+    );
+    assert_eq!(table.get(1).unwrap().string, "goto sleep");
+    assert_eq!(table.get(3), None);
+}
+
+#[test]
+fn trace_cli() {
+    let out = Command::new(env!("CARGO_BIN_EXE_itm-trace"))
+        .arg(capture("trace.elf"))
+        .arg(capture("trace.bin"))
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(out.stdout, read("trace.txt"));
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "warning: ITM overflow, trace data lost\n"
+    );
+}
+
+#[test]
+fn bench_compare_cli() {
+    let out = Command::new(env!("CARGO_BIN_EXE_bench-compare"))
+        .arg(capture("bench-dev.txt"))
+        .arg(capture("bench-release.txt"))
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(out.stdout, read("bench-compare.txt"));
+    assert!(String::from_utf8(out.stderr)
+        .unwrap()
+        .ends_with("bench-release.txt:6: missing `n`\n"));
+}
@@ -0,0 +1,208 @@
+//! Runs the semihosting examples under QEMU, no devkit needed
+//!
+//! The examples are built for the `qemu` board (see `build.rs` of the `app`),
+//! and booted on the `netduinoplus2` (Cortex-M4) machine of `qemu-system-arm`.
+//! Semihosting output (stdout and stderr) is captured, and the run ends when the
+//! program exits (through semihosting) or the timeout expires (most examples
+//! loop forever).
+//!
+//! Expected output is kept in `transcripts/<example>.txt`, where `...` matches
+//! any text (e.g., register values in a panic message).
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const TARGET: &str = "thumbv7em-none-eabihf";
+
+/// How long an example may run, unless set by `QEMU_TIMEOUT_MS`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// exited through semihosting with this status
+    Exit(i32),
+    /// still running when the timeout expired
+    Timeout,
+}
+
+#[derive(Debug)]
+pub struct Run {
+    pub output: String,
+    pub outcome: Outcome,
+}
+
+/// The `app` project folder
+pub fn app_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_owned()
+}
+
+/// Is `qemu-system-arm` available
+pub fn qemu_available() -> bool {
+    Command::new("qemu-system-arm")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Builds the `examples` for the `qemu` board, panics reported by semihosting
+pub fn build(examples: &[&str]) -> io::Result<()> {
+    let app = app_dir();
+    let mut cargo = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".into()));
+    cargo.current_dir(&app).args([
+        "build",
+        "--features",
+        "qemu panic-semihosting",
+        "--target",
+        TARGET,
+    ]);
+    for example in examples {
+        cargo.args(["--example", example]);
+    }
+
+    if !cargo.status()?.success() {
+        return Err(io::Error::other("cargo build failed"));
+    }
+    Ok(())
+}
+
+/// The elf file of a built `example`
+pub fn elf(example: &str) -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| app_dir().join("target"))
+        .join(TARGET)
+        .join("debug")
+        .join("examples")
+        .join(example)
+}
+
+/// Boots `elf` on QEMU and captures its semihosting output
+pub fn run(elf: &Path, timeout: Duration) -> io::Result<Run> {
+    // merge stderr into stdout, keeping the order of the output
+    let mut qemu = Command::new("sh")
+        .arg("-c")
+        .arg(
+            "exec qemu-system-arm -cpu cortex-m4 -machine netduinoplus2 \
+             -display none -monitor none -serial none \
+             -semihosting-config enable=on,target=native -kernel \"$0\" 2>&1",
+        )
+        .arg(elf)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut stdout = qemu.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+
+    let start = Instant::now();
+    let outcome = loop {
+        if let Some(status) = qemu.try_wait()? {
+            break Outcome::Exit(status.code().unwrap_or(-1));
+        }
+        if start.elapsed() >= timeout {
+            qemu.kill()?;
+            qemu.wait()?;
+            break Outcome::Timeout;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let output = reader.join().unwrap();
+    Ok(Run {
+        output: String::from_utf8_lossy(&output).into_owned(),
+        outcome,
+    })
+}
+
+/// The run timeout, `QEMU_TIMEOUT_MS` or `DEFAULT_TIMEOUT`
+pub fn timeout() -> Duration {
+    env::var("QEMU_TIMEOUT_MS")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// The expected output of `example`
+pub fn transcript(example: &str) -> io::Result<String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("transcripts")
+        .join(example)
+        .with_extension("txt");
+    fs::read_to_string(path)
+}
+
+/// Does `output` match the `transcript`, where `...` matches any text
+pub fn matches(transcript: &str, output: &str) -> bool {
+    let mut parts = transcript.split("...");
+    let first = parts.next().unwrap();
+    if !output.starts_with(first) {
+        return false;
+    }
+    let mut rest = &output[first.len()..];
+
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        // no wildcard
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(i) => rest = &rest[i + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn exact() {
+        assert!(matches("bare3\n", "bare3\n"));
+        assert!(!matches("bare3\n", "bare3\nmore\n"));
+        assert!(!matches("bare3\n", "bare"));
+        assert!(matches("", ""));
+    }
+
+    #[test]
+    fn wildcard() {
+        let t = "panicked at ...r0: ..., pc: ...}...\n";
+        assert!(matches(
+            t,
+            "panicked at 'x { r0: 0x1, pc: 0x2 }', a.rs:1:1\n"
+        ));
+        assert!(matches(
+            t,
+            "panicked at a.rs:1:1:\nx { r0: 0x1, pc: 0x2 }\n"
+        ));
+        assert!(!matches(t, "panicked at 'x { r1: 0x1, pc: 0x2 }'\n"));
+        assert!(!matches(t, "panicked at 'x { r0: 0x1, pc: 0x2 }'"));
+    }
+
+    #[test]
+    fn wildcard_does_not_overlap() {
+        assert!(!matches("ab...ba", "aba"));
+        assert!(matches("ab...ba", "abba"));
+    }
+}
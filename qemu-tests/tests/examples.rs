@@ -0,0 +1,65 @@
+//! Boots the semihosting examples on QEMU and compares their output with
+//! the transcripts (skipped if `qemu-system-arm` is not installed)
+//!
+//! > cargo test --manifest-path qemu-tests/Cargo.toml --target x86_64-unknown-linux-gnu
+
+use std::sync::Once;
+
+use qemu_tests::{build, elf, matches, qemu_available, run, timeout, transcript, Outcome};
+
+const EXAMPLES: &[&str] = &["hello", "bare0", "bare3", "panic", "crash"];
+
+// build all examples once (tests run in parallel)
+fn build_once() {
+    static BUILD: Once = Once::new();
+    BUILD.call_once(|| {
+        build(EXAMPLES).expect("building the examples");
+    });
+}
+
+fn check(example: &str, outcome: Outcome) {
+    if !qemu_available() {
+        eprintln!("qemu-system-arm not found, skipping `{}`", example);
+        return;
+    }
+    build_once();
+
+    let run = run(&elf(example), timeout()).expect("running qemu");
+    let expected = transcript(example).expect("reading transcript");
+
+    assert!(
+        matches(&expected, &run.output),
+        "`{}` output differs\n--- expected\n{}--- got\n{}",
+        example,
+        expected,
+        run.output
+    );
+    assert_eq!(run.outcome, outcome, "`{}` outcome", example);
+}
+
+#[test]
+fn hello() {
+    check("hello", Outcome::Timeout);
+}
+
+#[test]
+fn bare0() {
+    check("bare0", Outcome::Timeout);
+}
+
+#[test]
+fn bare3() {
+    check("bare3", Outcome::Timeout);
+}
+
+#[test]
+fn panic() {
+    // reports the panic (`panic-semihosting`), then exits QEMU (`qemu`)
+    check("panic", Outcome::Exit(1));
+}
+
+#[test]
+fn crash() {
+    // the HardFault handler panics
+    check("crash", Outcome::Exit(1));
+}
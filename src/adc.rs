@@ -0,0 +1,743 @@
+//! ADC1, single conversions, scans and continuous conversion by DMA (RM0368 11)
+//!
+//! The ADC converts the channels of a sequence (up to 16, in any order), each
+//! after its sample time (3 to 480 ADC clock cycles). Three ways to run it:
+//!
+//! - `convert`, a single conversion of one channel, polling
+//! - `scan`, a single conversion of each channel of a sequence, polling
+//! - `continuous`, the sequence converted over and over, moved by DMA2 into a
+//!   double buffer (`DBM`, the DMA switches buffer at each transfer complete)
+//!
+//! In continuous mode, the DMA interrupt averages the block just completed
+//! (while the DMA fills the other one) into `Readings`, one value per channel,
+//! small enough to be sent to a task by value, as the bytes of `trace_data` in
+//! `bare10.rs` (`rtfm_adc.rs`):
+//!
+//! ``` ignore
+//! // DMA2 stream 0 routed to `EXTI2` (`app::vectors`)
+//! #[task(binds = EXTI2, priority = 2, resources = [ADC1], spawn = [readings])]
+//! fn dma2_stream0(cx: dma2_stream0::Context) {
+//!     if let Some(readings) = cx.resources.ADC1.on_dma_interrupt() {
+//!         cx.spawn.readings(readings).ok();
+//!     }
+//! }
+//! ```
+//!
+//! An overrun (the DMA did not read DR in time) stops the DMA requests, so it
+//! is caught by the ADC interrupt (`OVRIE`), `on_adc_interrupt` restarts the
+//! conversion.
+//!
+//! The internal temperature sensor and VREFINT (1.21 V typical) are converted
+//! by the factory `Calibration`, measured at 30 C and 110 C, at VDDA = 3.3 V.
+//! VREFINT gives the actual VDDA, and so the inputs in millivolts.
+
+use core::fmt;
+use core::mem::offset_of;
+use core::sync::atomic::{self, Ordering};
+
+use heapless::{consts::U16, Vec};
+
+use crate::bus::RegisterBus;
+use crate::exti::{Pin, Port};
+use crate::regs::{
+    Adc as AdcRegs, Dma, DmaStream, Gpio, ADC1, ADC_CCR_ADCPRE, ADC_CCR_TSVREFE, ADC_CR1_OVRIE,
+    ADC_CR1_SCAN, ADC_CR2_ADON, ADC_CR2_CONT, ADC_CR2_DDS, ADC_CR2_DMA, ADC_CR2_EOCS,
+    ADC_CR2_SWSTART, ADC_SR_EOC, ADC_SR_OVR, ADC_SR_STRT, APB2ENR_ADC1EN, DMA2, ISR_ALL, ISR_TCIF,
+    ISR_TEIF, MODER_ANALOG, SQR1_L, SXCR_CHSEL, SXCR_CIRC, SXCR_CT, SXCR_DBM, SXCR_EN, SXCR_MINC,
+    SXCR_MSIZE, SXCR_PL, SXCR_PSIZE, SXCR_TCIE, SXCR_TEIE,
+};
+use crate::time::Hertz;
+use crate::usart_dma::Request;
+
+/// Max number of channels of a sequence
+pub const MAX_CHANNELS: usize = 16;
+
+/// Max ADC clock (at VDDA of 2.4 to 3.6 V), data sheet 6.3.21
+pub const ADCCLK_MAX: u32 = 36_000_000;
+
+/// Full scale, at 12 bits
+pub const FULL_SCALE: u32 = 4095;
+
+/// Samples of a DMA buffer, a whole number of sequences
+pub const BLOCK_SIZE: usize = 64;
+
+pub type Block = [u16; BLOCK_SIZE];
+
+/// Raw values, one per channel of the sequence, in order
+pub type Readings = Vec<u16, U16>;
+
+/// `ADC1`, DMA2 stream 0, channel 0 (or stream 4, channel 0)
+pub const ADC1_DMA: Request = Request {
+    stream: 0,
+    channel: 0,
+};
+
+// polls of a conversion, before giving up (a conversion takes at most 492 ADC
+// clock cycles, i.e., about 2_000 polls at SYSCLK / 4)
+const POLL_LIMIT: u32 = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// no ADC prescaler (2, 4, 6 or 8) within `ADCCLK_MAX`
+    Clock,
+    /// a sequence of no channels, or more than `MAX_CHANNELS`
+    Sequence,
+    /// a conversion did not end
+    Timeout,
+    /// a conversion ended before the previous one was read
+    Overrun,
+}
+
+/// An input channel of ADC1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel(u8);
+
+impl Channel {
+    /// The internal reference voltage, ADC1_IN17
+    pub const VREFINT: Channel = Channel(17);
+    /// The temperature sensor, ADC1_IN18 (shared with VBAT on the STM32F401)
+    pub const TEMPERATURE: Channel = Channel(18);
+
+    /// The input of `pin` (ADC1_IN0..15: PA0..7, PB0..1, PC0..5), if any
+    pub const fn of(pin: Pin) -> Option<Channel> {
+        match (pin.port, pin.pin) {
+            (Port::A, 0..=7) => Some(Channel(pin.pin)),
+            (Port::B, 0..=1) => Some(Channel(8 + pin.pin)),
+            (Port::C, 0..=5) => Some(Channel(10 + pin.pin)),
+            _ => None,
+        }
+    }
+
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+}
+
+/// Sample time, in ADC clock cycles (`SMPx`), RM0368 11.12.4
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleTime {
+    Cycles3 = 0,
+    Cycles15 = 1,
+    Cycles28 = 2,
+    Cycles56 = 3,
+    Cycles84 = 4,
+    Cycles112 = 5,
+    Cycles144 = 6,
+    Cycles480 = 7,
+}
+
+impl SampleTime {
+    const ALL: [SampleTime; 8] = [
+        SampleTime::Cycles3,
+        SampleTime::Cycles15,
+        SampleTime::Cycles28,
+        SampleTime::Cycles56,
+        SampleTime::Cycles84,
+        SampleTime::Cycles112,
+        SampleTime::Cycles144,
+        SampleTime::Cycles480,
+    ];
+
+    pub const fn cycles(self) -> u32 {
+        match self {
+            SampleTime::Cycles3 => 3,
+            SampleTime::Cycles15 => 15,
+            SampleTime::Cycles28 => 28,
+            SampleTime::Cycles56 => 56,
+            SampleTime::Cycles84 => 84,
+            SampleTime::Cycles112 => 112,
+            SampleTime::Cycles144 => 144,
+            SampleTime::Cycles480 => 480,
+        }
+    }
+
+    /// The shortest sample time of at least `ns` nanoseconds at `adcclk`
+    /// (the longest if none), e.g., 10 us for the temperature sensor
+    pub fn at_least(ns: u32, adcclk: Hertz) -> SampleTime {
+        let cycles = (ns as u64 * adcclk.0 as u64).div_ceil(1_000_000_000);
+        SampleTime::ALL
+            .iter()
+            .copied()
+            .find(|t| t.cycles() as u64 >= cycles)
+            .unwrap_or(SampleTime::Cycles480)
+    }
+}
+
+/// The `ADCPRE` bits and the ADC clock, the fastest within `ADCCLK_MAX`
+pub fn prescaler(pclk2: u32) -> Result<(u32, u32), Error> {
+    (0..4)
+        .map(|pre| (pre, pclk2 / (2 * (pre + 1))))
+        .find(|&(_, adcclk)| adcclk <= ADCCLK_MAX)
+        .ok_or(Error::Clock)
+}
+
+/// ADC1, over a `RegisterBus`
+pub struct Adc<B: RegisterBus> {
+    bus: B,
+    adcclk: u32,
+}
+
+impl<B: RegisterBus> Adc<B> {
+    /// Powers on ADC1 at 12 bits, clocked by `pclk2` (Hz) divided by the
+    /// smallest prescaler within `ADCCLK_MAX`
+    ///
+    /// The ADC is ready for conversions after 3 us (`tSTAB`).
+    pub fn new(bus: B, pclk2: u32) -> Result<Self, Error> {
+        let (pre, adcclk) = prescaler(pclk2)?;
+        bus.modify_u32(RCC_APB2ENR, 0, APB2ENR_ADC1EN.mask());
+        bus.modify_u32(
+            ADC_CCR,
+            ADC_CCR_ADCPRE.mask(),
+            ADC_CCR_ADCPRE.val(pre).bits(),
+        );
+        bus.write_u32(ADC1_CR1, 0);
+        // EOC at the end of each conversion (not of the sequence)
+        bus.write_u32(ADC1_CR2, ADC_CR2_ADON.mask() | ADC_CR2_EOCS.mask());
+        Ok(Adc { bus, adcclk })
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn adcclk(&self) -> Hertz {
+        Hertz(self.adcclk)
+    }
+
+    /// Sets `pin` to analog mode, its channel if it has one
+    pub fn input(&self, pin: Pin) -> Option<Channel> {
+        let channel = Channel::of(pin)?;
+        self.bus.modify_u32(RCC_AHB1ENR, 0, 1 << pin.port.index());
+        let field = Gpio::moder(pin.pin);
+        self.bus.modify_u32(
+            pin.port.reg(offset_of!(Gpio, MODER)),
+            field.mask(),
+            field.val(MODER_ANALOG).bits(),
+        );
+        Some(channel)
+    }
+
+    /// Powers on the temperature sensor and VREFINT, and sets their sample
+    /// time to 10 us (the minimum of the sensor, data sheet 6.3.22)
+    ///
+    /// The sensor is ready after 10 us (`tSTART`).
+    pub fn sensors(&self) {
+        self.bus.modify_u32(ADC_CCR, 0, ADC_CCR_TSVREFE.mask());
+        let time = SampleTime::at_least(10_000, self.adcclk());
+        self.sample_time(Channel::TEMPERATURE, time);
+        self.sample_time(Channel::VREFINT, time);
+    }
+
+    pub fn sample_time(&self, channel: Channel, time: SampleTime) {
+        let field = AdcRegs::smp(channel.0);
+        let smpr = ADC1.reg(AdcRegs::smpr_offset(channel.0));
+        self.bus
+            .modify_u32(smpr, field.mask(), field.val(time as u32).bits());
+    }
+
+    /// A single conversion of `channel`
+    pub fn convert(&self, channel: Channel) -> Result<u16, Error> {
+        Ok(self.scan(&[channel])?[0])
+    }
+
+    /// A single conversion of each of `channels`, in order
+    pub fn scan(&self, channels: &[Channel]) -> Result<Readings, Error> {
+        self.sequence(channels)?;
+        // flags are cleared by writing 0 (rc_w0), 1 leaves them as they are
+        self.bus.write_u32(ADC1_SR, !ADC_SR_OVR.mask());
+        self.bus.modify_u32(ADC1_CR2, 0, ADC_CR2_SWSTART.mask());
+
+        let mut readings = Readings::new();
+        for _ in channels {
+            let value = self.wait()?;
+            readings.push(value).ok();
+        }
+        Ok(readings)
+    }
+
+    /// Converts `channels` over and over, moved into `buffers` by DMA2
+    /// (`request`), see `Continuous`
+    ///
+    /// DMA2 must be clocked (`RCC_AHB1ENR`).
+    pub fn continuous(
+        self,
+        request: Request,
+        channels: &[Channel],
+        buffers: (&'static mut Block, &'static mut Block),
+    ) -> Result<Continuous<B>, Error> {
+        if channels.is_empty() || channels.len() > MAX_CHANNELS {
+            return Err(Error::Sequence);
+        }
+        let stream = |offset| DMA2.reg(Dma::stream_offset(request.stream, offset));
+        let isr = DMA2.reg(Dma::isr_offset(request.stream));
+        let mut continuous = Continuous {
+            isr,
+            // LIFCR/HIFCR are 8 bytes after LISR/HISR
+            ifcr: isr + (offset_of!(Dma, LIFCR) - offset_of!(Dma, LISR)) as u32,
+            flags: Dma::flags_offset(request.stream),
+            cr: stream(offset_of!(DmaStream, CR)),
+            ndtr: stream(offset_of!(DmaStream, NDTR)),
+            par: stream(offset_of!(DmaStream, PAR)),
+            m0ar: stream(offset_of!(DmaStream, M0AR)),
+            m1ar: stream(offset_of!(DmaStream, M1AR)),
+            channel: request.channel,
+            channels: channels.len(),
+            buffers: [buffers.0, buffers.1],
+            counters: Counters::default(),
+            adc: self,
+        };
+        continuous.adc.sequence(channels)?;
+        continuous.start();
+        Ok(continuous)
+    }
+
+    // the regular sequence, scanned if longer than one
+    fn sequence(&self, channels: &[Channel]) -> Result<(), Error> {
+        if channels.is_empty() || channels.len() > MAX_CHANNELS {
+            return Err(Error::Sequence);
+        }
+        for (rank, channel) in channels.iter().enumerate() {
+            let field = AdcRegs::sq(rank);
+            let sqr = ADC1.reg(AdcRegs::sqr_offset(rank));
+            self.bus
+                .modify_u32(sqr, field.mask(), field.val(channel.0 as u32).bits());
+        }
+        let length = SQR1_L.val(channels.len() as u32 - 1).bits();
+        self.bus.modify_u32(ADC1_SQR1, SQR1_L.mask(), length);
+        let scan = if channels.len() > 1 {
+            ADC_CR1_SCAN.mask()
+        } else {
+            0
+        };
+        self.bus.modify_u32(ADC1_CR1, ADC_CR1_SCAN.mask(), scan);
+        Ok(())
+    }
+
+    // the next conversion, reading DR clears EOC
+    fn wait(&self) -> Result<u16, Error> {
+        for _ in 0..POLL_LIMIT {
+            let sr = self.bus.read_u32(ADC1_SR);
+            if sr & ADC_SR_OVR.mask() != 0 {
+                self.bus.write_u32(ADC1_SR, !ADC_SR_OVR.mask());
+                return Err(Error::Overrun);
+            }
+            if sr & ADC_SR_EOC.mask() != 0 {
+                return Ok(self.bus.read_u32(ADC1_DR) as u16);
+            }
+        }
+        Err(Error::Timeout)
+    }
+}
+
+/// Blocks and errors of the continuous conversion, since `continuous`
+/// (wrapping)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    /// blocks completed
+    pub blocks: u32,
+    /// conversions lost, the DMA did not read DR in time (restarted)
+    pub overrun: u32,
+    /// DMA transfer errors
+    pub transfer: u32,
+}
+
+/// Continuous conversion of a sequence into a double buffer
+///
+/// Each buffer holds a whole number of sequences. At the end of a buffer
+/// (the DMA transfer complete interrupt) the DMA switches to the other one,
+/// and `on_dma_interrupt` averages the completed buffer per channel, before
+/// the DMA comes back to it.
+pub struct Continuous<B: RegisterBus> {
+    adc: Adc<B>,
+    // DMA registers
+    isr: u32,
+    ifcr: u32,
+    flags: u8,
+    cr: u32,
+    ndtr: u32,
+    par: u32,
+    m0ar: u32,
+    m1ar: u32,
+    channel: u8,
+    // channels of the sequence
+    channels: usize,
+    // written by the DMA, `CT` tells which one
+    buffers: [&'static mut Block; 2],
+    counters: Counters,
+}
+
+impl<B: RegisterBus> Continuous<B> {
+    /// To be called from the DMA stream interrupt handler, the averages of a
+    /// completed buffer
+    pub fn on_dma_interrupt(&mut self) -> Option<Readings> {
+        let flags = (self.adc.bus.read_u32(self.isr) >> self.flags) & ISR_ALL;
+        self.adc.bus.write_u32(self.ifcr, flags << self.flags);
+        if flags & ISR_TEIF != 0 {
+            // the stream is disabled on a transfer error
+            self.counters.transfer = self.counters.transfer.wrapping_add(1);
+            self.restart();
+            return None;
+        }
+        if self.on_adc_interrupt() {
+            return None;
+        }
+        if flags & ISR_TCIF == 0 {
+            return None;
+        }
+        self.counters.blocks = self.counters.blocks.wrapping_add(1);
+
+        // the buffer not targeted by the DMA (`CT`) is the completed one
+        let target = self.adc.bus.read_u32(self.cr) & SXCR_CT.mask() != 0;
+        atomic::compiler_fence(Ordering::SeqCst);
+        let block = &self.buffers[if target { 0 } else { 1 }];
+        Some(averages(&block[..self.len()], self.channels))
+    }
+
+    /// To be called from the ADC interrupt handler (`OVRIE`), true on an
+    /// overrun, the conversion restarted
+    ///
+    /// The DMA requests are stopped on an overrun (RM0368 11.8.1), so there is
+    /// no DMA interrupt to recover from it.
+    pub fn on_adc_interrupt(&mut self) -> bool {
+        if self.adc.bus.read_u32(ADC1_SR) & ADC_SR_OVR.mask() == 0 {
+            return false;
+        }
+        self.counters.overrun = self.counters.overrun.wrapping_add(1);
+        self.restart();
+        true
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.adc.bus
+    }
+
+    /// Stops the conversion and the DMA, back to single conversions
+    pub fn stop(self) -> Adc<B> {
+        self.halt();
+        self.adc
+    }
+
+    // samples per buffer, a whole number of sequences
+    fn len(&self) -> usize {
+        BLOCK_SIZE / self.channels * self.channels
+    }
+
+    fn start(&mut self) {
+        let bus = &self.adc.bus;
+        bus.write_u32(self.ifcr, ISR_ALL << self.flags);
+        bus.write_u32(self.par, ADC1_DR);
+        bus.write_u32(self.m0ar, self.buffers[0].as_mut_ptr() as usize as u32);
+        bus.write_u32(self.m1ar, self.buffers[1].as_mut_ptr() as usize as u32);
+        bus.write_u32(self.ndtr, self.len() as u32);
+        // peripheral to memory (DIR 0), half words, high priority
+        bus.write_u32(
+            self.cr,
+            SXCR_CHSEL.val(self.channel as u32).bits()
+                | SXCR_PL.val(0b10).bits()
+                | SXCR_MSIZE.val(0b01).bits()
+                | SXCR_PSIZE.val(0b01).bits()
+                | SXCR_MINC.mask()
+                | SXCR_CIRC.mask()
+                | SXCR_DBM.mask()
+                | SXCR_TCIE.mask()
+                | SXCR_TEIE.mask(),
+        );
+        bus.modify_u32(self.cr, 0, SXCR_EN.mask());
+
+        // DMA requests for as long as the DMA reads DR (DDS)
+        bus.write_u32(ADC1_SR, !(ADC_SR_OVR.mask() | ADC_SR_STRT.mask()));
+        bus.modify_u32(ADC1_CR1, 0, ADC_CR1_OVRIE.mask());
+        bus.write_u32(
+            ADC1_CR2,
+            ADC_CR2_ADON.mask() | ADC_CR2_CONT.mask() | ADC_CR2_DMA.mask() | ADC_CR2_DDS.mask(),
+        );
+        bus.modify_u32(ADC1_CR2, 0, ADC_CR2_SWSTART.mask());
+    }
+
+    fn halt(&self) {
+        let bus = &self.adc.bus;
+        bus.write_u32(ADC1_CR2, ADC_CR2_ADON.mask() | ADC_CR2_EOCS.mask());
+        bus.modify_u32(ADC1_CR1, ADC_CR1_OVRIE.mask(), 0);
+        bus.modify_u32(self.cr, SXCR_EN.mask(), 0);
+        while bus.read_u32(self.cr) & SXCR_EN.mask() != 0 {}
+    }
+
+    fn restart(&mut self) {
+        self.halt();
+        self.start();
+    }
+}
+
+// the mean of each of `channels`, over the sequences of `samples`, at least
+// one (`Continuous::len`)
+fn averages(samples: &[u16], channels: usize) -> Readings {
+    let sequences = (samples.len() / channels) as u32;
+    (0..channels)
+        .map(|channel| {
+            let sum: u32 = samples
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .map(|&s| s as u32)
+                .sum();
+            ((sum + sequences / 2) / sequences) as u16
+        })
+        .collect()
+}
+
+/// A temperature, in hundredths of a degree Celsius
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Celsius(pub i32);
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02} C", sign, abs / 100, abs % 100)
+    }
+}
+
+/// Factory calibration, converted at 12 bits at VDDA = 3.3 V, data sheet
+/// 6.3.22 and 6.3.23
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration {
+    /// the temperature sensor at 30 C
+    pub ts_cal1: u16,
+    /// the temperature sensor at 110 C
+    pub ts_cal2: u16,
+    /// VREFINT at 30 C
+    pub vrefint_cal: u16,
+}
+
+/// VDDA of the `Calibration`
+pub const CAL_VDDA_MV: u32 = 3300;
+
+impl Calibration {
+    /// Reads the values from the system memory
+    pub fn read<B: RegisterBus>(bus: &B) -> Self {
+        // half words, read as the words holding them
+        let ts = bus.read_u32(TS_CAL);
+        let vrefint = bus.read_u32(VREFINT_CAL - 2);
+        Calibration {
+            ts_cal1: ts as u16,
+            ts_cal2: (ts >> 16) as u16,
+            vrefint_cal: (vrefint >> 16) as u16,
+        }
+    }
+
+    /// VDDA, in millivolts, from a conversion of VREFINT
+    pub fn vdda_mv(&self, vrefint: u16) -> u32 {
+        let vrefint = (vrefint as u32).max(1);
+        (CAL_VDDA_MV * self.vrefint_cal as u32 + vrefint / 2) / vrefint
+    }
+
+    /// The temperature, from a conversion of the sensor at `vdda_mv`
+    pub fn temperature(&self, raw: u16, vdda_mv: u32) -> Celsius {
+        // the conversion at the VDDA of the calibration
+        let raw = raw as i64 * vdda_mv as i64 / CAL_VDDA_MV as i64;
+        let (cal1, cal2) = (self.ts_cal1 as i64, self.ts_cal2 as i64);
+        let span = (cal2 - cal1).max(1);
+        Celsius((3000 + (raw - cal1) * 8000 / span) as i32)
+    }
+}
+
+/// An input, in millivolts, from a conversion at `vdda_mv`
+pub fn millivolts(raw: u16, vdda_mv: u32) -> u32 {
+    (raw as u32 * vdda_mv + FULL_SCALE / 2) / FULL_SCALE
+}
+
+#[rustfmt::skip]
+pub mod address {
+    use super::*;
+    use crate::regs::{AdcCommon, Rcc, ADC_COMMON, RCC};
+
+    pub const RCC_AHB1ENR: u32      = RCC.reg(offset_of!(Rcc, AHB1ENR));
+    pub const RCC_APB2ENR: u32      = RCC.reg(offset_of!(Rcc, APB2ENR));
+    pub const ADC1_SR: u32          = ADC1.reg(offset_of!(AdcRegs, SR));
+    pub const ADC1_CR1: u32         = ADC1.reg(offset_of!(AdcRegs, CR1));
+    pub const ADC1_CR2: u32         = ADC1.reg(offset_of!(AdcRegs, CR2));
+    pub const ADC1_SQR1: u32        = ADC1.reg(offset_of!(AdcRegs, SQR1));
+    pub const ADC1_DR: u32          = ADC1.reg(offset_of!(AdcRegs, DR));
+    pub const ADC_CCR: u32          = ADC_COMMON.reg(offset_of!(AdcCommon, CCR));
+    // system memory, data sheet 6.3.22 and 6.3.23
+    pub const VREFINT_CAL: u32      = 0x1FFF_7A2A;
+    pub const TS_CAL: u32           = 0x1FFF_7A2C;
+}
+
+use address::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Access::*, MemoryBus};
+
+    const ADC1_SQR2: u32 = 0x4001_2030;
+    const ADC1_SQR3: u32 = 0x4001_2034;
+    const ADC1_SMPR1: u32 = 0x4001_200C;
+    const DMA2_LISR: u32 = 0x4002_6400;
+    const DMA2_LIFCR: u32 = 0x4002_6408;
+    const DMA2_S0CR: u32 = 0x4002_6410;
+    const DMA2_S0NDTR: u32 = 0x4002_6414;
+    const DMA2_S0PAR: u32 = 0x4002_6418;
+
+    const PA0: Pin = Pin::new(Port::A, 0);
+
+    fn adc() -> Adc<MemoryBus> {
+        Adc::new(MemoryBus::new(), 16_000_000).unwrap()
+    }
+
+    #[test]
+    fn setup() {
+        let adc = adc();
+        let bus = adc.bus();
+        assert_eq!(adc.adcclk(), Hertz::mhz(8));
+        assert_eq!(bus.peek(ADC_CCR), 0);
+        assert_eq!(bus.peek(ADC1_CR2), 0x401);
+        assert_eq!(bus.peek(RCC_APB2ENR), 1 << 8);
+
+        assert_eq!(adc.input(PA0), Some(Channel(0)));
+        assert_eq!(bus.peek(0x4002_0000), 0b11);
+        assert_eq!(adc.input(Pin::new(Port::A, 8)), None);
+        adc.sensors();
+        assert_eq!(bus.peek(ADC_CCR), 1 << 23);
+        // 10 us at 8 MHz, 84 cycles for channels 17 and 18
+        assert_eq!(bus.peek(ADC1_SMPR1), 0o44 << 21);
+
+        // 84 MHz, 42 MHz would be too fast
+        assert_eq!(prescaler(84_000_000), Ok((1, 21_000_000)));
+        assert_eq!(prescaler(300_000_000), Err(Error::Clock));
+    }
+
+    #[test]
+    fn channels() {
+        assert_eq!(Channel::of(Pin::new(Port::B, 1)), Some(Channel(9)));
+        assert_eq!(Channel::of(Pin::new(Port::C, 5)), Some(Channel(15)));
+        assert_eq!(Channel::of(Pin::new(Port::C, 6)), None);
+        assert_eq!(Channel::of(Pin::new(Port::H, 0)), None);
+
+        let mhz = Hertz::mhz(21);
+        assert_eq!(SampleTime::at_least(0, mhz), SampleTime::Cycles3);
+        assert_eq!(SampleTime::at_least(10_000, mhz), SampleTime::Cycles480);
+        assert_eq!(SampleTime::at_least(1_000, mhz), SampleTime::Cycles28);
+    }
+
+    #[test]
+    fn scan() {
+        let adc = adc();
+        let bus = adc.bus();
+        bus.preset(ADC1_SR, ADC_SR_EOC.mask());
+        bus.preset(ADC1_DR, 1234);
+        let channels = [0, 1, 4, 8, 9, 10, 17, 18].map(Channel);
+        let readings = adc.scan(&channels).unwrap();
+        assert_eq!(readings, [1234; 8]);
+        assert_eq!(
+            bus.peek(ADC1_SQR3),
+            1 << 5 | 4 << 10 | 8 << 15 | 9 << 20 | 10 << 25
+        );
+        assert_eq!(bus.peek(ADC1_SQR2), 17 | 18 << 5);
+        assert_eq!(bus.peek(ADC1_SQR1), 7 << 20);
+        assert_eq!(bus.peek(ADC1_CR1), 1 << 8);
+    }
+
+    #[test]
+    fn single() {
+        let adc = adc();
+        let bus = adc.bus();
+        bus.preset(ADC1_SR, ADC_SR_EOC.mask());
+        bus.preset(ADC1_DR, 1234);
+        adc.scan(&[Channel(0), Channel(1)]).unwrap();
+        // back to a single channel, not scanned
+        assert_eq!(adc.convert(Channel::VREFINT), Ok(1234));
+        assert_eq!(bus.peek(ADC1_SQR1), 0);
+        assert_eq!(bus.peek(ADC1_CR1), 0);
+
+        assert_eq!(adc.scan(&[]), Err(Error::Sequence));
+    }
+
+    #[test]
+    fn continuous() {
+        let buffers = (
+            Box::leak(Box::new([100; BLOCK_SIZE])),
+            Box::leak(Box::new([200; BLOCK_SIZE])),
+        );
+        let channels = [Channel(0), Channel::VREFINT, Channel::TEMPERATURE];
+        let mut adc = adc().continuous(ADC1_DMA, &channels, buffers).unwrap();
+
+        // 21 sequences of 3 channels
+        assert_eq!(adc.bus().peek(DMA2_S0NDTR), 63);
+        assert_eq!(adc.bus().peek(DMA2_S0PAR), ADC1_DR);
+        // DBM, PL high, MSIZE/PSIZE half words, MINC, CIRC, TCIE, TEIE, EN
+        assert_eq!(adc.bus().peek(DMA2_S0CR), 0x0006_2D15);
+        // DDS, DMA, CONT, ADON, and started
+        assert_eq!(adc.bus().peek(ADC1_CR2), 1 << 30 | 0x303);
+        // OVRIE, SCAN
+        assert_eq!(adc.bus().peek(ADC1_CR1), 1 << 26 | 1 << 8);
+        assert_eq!(adc.on_dma_interrupt(), None);
+
+        // buffer 0 completed, the DMA moved on to buffer 1
+        adc.bus().preset(DMA2_LISR, ISR_TCIF);
+        adc.bus().preset(DMA2_S0CR, SXCR_CT.mask() | SXCR_EN.mask());
+        assert_eq!(adc.on_dma_interrupt().unwrap(), [100; 3]);
+        assert_eq!(adc.bus().peek(DMA2_LIFCR), ISR_TCIF);
+        adc.bus().preset(DMA2_S0CR, SXCR_EN.mask());
+        assert_eq!(adc.on_dma_interrupt().unwrap(), [200; 3]);
+        assert_eq!(adc.counters().blocks, 2);
+
+        // an overrun stops the DMA requests, restarted
+        let cleared = Write(ADC1_SR, !(ADC_SR_OVR.mask() | ADC_SR_STRT.mask()));
+        adc.bus().preset(ADC1_SR, ADC_SR_OVR.mask());
+        adc.bus().clear_log();
+        assert_eq!(adc.on_dma_interrupt(), None);
+        assert_eq!(adc.counters().overrun, 1);
+        // the flags written, not read back (rc_w0)
+        assert!(adc.bus().accesses().contains(&cleared));
+        assert_eq!(adc.bus().peek(DMA2_S0CR) & SXCR_EN.mask(), 1);
+
+        // no more DMA interrupts, restarted by the ADC interrupt
+        assert!(!adc.on_adc_interrupt());
+        adc.bus().preset(ADC1_SR, ADC_SR_OVR.mask());
+        adc.bus().clear_log();
+        assert!(adc.on_adc_interrupt());
+        assert_eq!(adc.counters().overrun, 2);
+        assert!(adc.bus().accesses().contains(&cleared));
+        assert_eq!(adc.bus().peek(ADC1_CR1) & 1 << 26, 1 << 26);
+        assert_eq!(adc.bus().peek(DMA2_S0CR) & SXCR_EN.mask(), 1);
+
+        assert_eq!(
+            averages(&[1, 10, 2, 20, 4, 40], 2),
+            [2, 23].iter().copied().collect::<Readings>()
+        );
+    }
+
+    #[test]
+    fn calibration() {
+        let bus = MemoryBus::new();
+        bus.preset(TS_CAL, 1191 << 16 | 943);
+        bus.preset(VREFINT_CAL - 2, 1500 << 16 | 0x1234);
+        let cal = Calibration::read(&bus);
+        assert_eq!(
+            cal,
+            Calibration {
+                ts_cal1: 943,
+                ts_cal2: 1191,
+                vrefint_cal: 1500,
+            }
+        );
+
+        assert_eq!(cal.vdda_mv(1500), 3300);
+        assert_eq!(cal.vdda_mv(1650), 3000);
+        assert_eq!(cal.temperature(943, 3300), Celsius(3000));
+        assert_eq!(cal.temperature(1191, 3300), Celsius(11000));
+        // the same voltage, read at VDDA = 3.0 V
+        assert_eq!(cal.temperature(1000 * 33 / 30, 3000), Celsius(4838));
+        assert_eq!(Celsius(4838).to_string(), "48.38 C");
+        assert_eq!(Celsius(-505).to_string(), "-5.05 C");
+
+        assert_eq!(millivolts(4095, 3300), 3300);
+        assert_eq!(millivolts(2048, 3000), 1500);
+    }
+}
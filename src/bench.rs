@@ -0,0 +1,293 @@
+//! Micro benchmarks, timed by the DWT cycle counter (CYCCNT)
+//!
+//! `bare2.rs` times `wait(1_000_000)` once, reading CYCCNT before and after.
+//! `bench!` runs the code `n` times instead, and subtracts the overhead of the
+//! measurement itself (two CYCCNT reads, calibrated by timing nothing):
+//!
+//! ``` ignore
+//! let report = app::bench!("wait", 10, wait(1_000));
+//! iprintln!(stim, "{}", report);
+//! ```
+//!
+//! A `Report` is displayed as one machine readable line (to compare runs on
+//! the host by `bench-compare`, see `itm-tools`), or, by `{:#}`, for reading:
+//!
+//! ``` text
+//! bench wait profile=release clock=16000000 n=10 min=4010 max=4017 mean=4012 jitter=7 overhead=2 min_ns=250625 max_ns=251062 mean_ns=250750
+//! wait (release, 16 MHz, 10 runs): min 4010, max 4017, mean 4012 (jitter 7) cycles, mean 250750 ns
+//! ```
+//!
+//! The line is the same over ITM (`iprintln!`) and semihosting (`hprintln!`).
+//! Interrupts taken during a run are counted, disable them
+//! (`interrupt::free`) for steady numbers.
+
+use core::fmt;
+use core::mem;
+use core::ptr;
+
+use cortex_m::peripheral::DWT;
+
+use crate::time::{self, Hertz};
+
+/// Cycle statistics of a number of runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub n: u32,
+    /// 0 if empty, as `max`
+    pub min: u32,
+    pub max: u32,
+    sum: u64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats::new()
+    }
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Stats {
+            n: 0,
+            min: 0,
+            max: 0,
+            sum: 0,
+        }
+    }
+
+    pub fn push(&mut self, cycles: u32) {
+        self.min = if self.n == 0 {
+            cycles
+        } else {
+            self.min.min(cycles)
+        };
+        self.n += 1;
+        self.max = self.max.max(cycles);
+        self.sum += u64::from(cycles);
+    }
+
+    /// Mean cycles (rounded down), 0 if empty
+    pub fn mean(&self) -> u32 {
+        if self.n == 0 {
+            0
+        } else {
+            (self.sum / u64::from(self.n)) as u32
+        }
+    }
+
+    /// Spread from the fastest to the slowest run
+    pub fn jitter(&self) -> u32 {
+        if self.n == 0 {
+            0
+        } else {
+            self.max - self.min
+        }
+    }
+}
+
+/// The result of `bench!`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub name: &'static str,
+    pub stats: Stats,
+    /// Cycles of an empty measurement, subtracted from each run
+    pub overhead: u32,
+    pub clock: Hertz,
+}
+
+impl Report {
+    /// `cycles` in nanoseconds, at the clock of the report
+    pub fn nanos(&self, cycles: u32) -> u64 {
+        u64::from(cycles) * 1_000_000_000 / u64::from(self.clock.0)
+    }
+}
+
+/// The build profile, `dev` (debug assertions on) or `release`
+pub fn profile() -> &'static str {
+    if cfg!(debug_assertions) {
+        "dev"
+    } else {
+        "release"
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = &self.stats;
+        if f.alternate() {
+            write!(
+                f,
+                "{} ({}, {} MHz, {} runs): min {}, max {}, mean {} (jitter {}) cycles, mean {} ns",
+                self.name,
+                profile(),
+                self.clock.0 / 1_000_000,
+                s.n,
+                s.min,
+                s.max,
+                s.mean(),
+                s.jitter(),
+                self.nanos(s.mean())
+            )
+        } else {
+            write!(
+                f,
+                "bench {} profile={} clock={} n={} min={} max={} mean={} jitter={} overhead={} \
+                 min_ns={} max_ns={} mean_ns={}",
+                self.name,
+                profile(),
+                self.clock.0,
+                s.n,
+                s.min,
+                s.max,
+                s.mean(),
+                s.jitter(),
+                self.overhead,
+                self.nanos(s.min),
+                self.nanos(s.max),
+                self.nanos(s.mean())
+            )
+        }
+    }
+}
+
+/// Keeps the optimizer from removing the computation of `x`
+pub fn black_box<T>(x: T) -> T {
+    // the read is opaque to the optimizer
+    unsafe {
+        let r = ptr::read_volatile(&x);
+        mem::forget(x);
+        r
+    }
+}
+
+/// Runs `f` `n` times (after one warm up run), timed by CYCCNT at the current
+/// SYSCLK, see `bench!`
+pub fn run<R>(name: &'static str, n: u32, f: impl FnMut() -> R) -> Report {
+    run_with(name, n, time::sysclk(), DWT::get_cycle_count, f)
+}
+
+// `run`, reading the cycle counter by `now`
+fn run_with<R>(
+    name: &'static str,
+    n: u32,
+    clock: Hertz,
+    mut now: impl FnMut() -> u32,
+    mut f: impl FnMut() -> R,
+) -> Report {
+    let empty = measure(n, &mut now, || (), 0);
+    let overhead = empty.min;
+
+    // warm up (e.g., the flash cache)
+    black_box(f());
+    let stats = measure(n, &mut now, f, overhead);
+    Report {
+        name,
+        stats,
+        overhead,
+        clock,
+    }
+}
+
+fn measure<R>(
+    n: u32,
+    now: &mut impl FnMut() -> u32,
+    mut f: impl FnMut() -> R,
+    overhead: u32,
+) -> Stats {
+    let mut stats = Stats::new();
+    for _ in 0..n {
+        let start = now();
+        black_box(f());
+        let end = now();
+        stats.push(end.wrapping_sub(start).saturating_sub(overhead));
+    }
+    stats
+}
+
+/// Runs an expression `n` times, see the module documentation
+///
+/// ``` ignore
+/// let report = app::bench!("name", n, expression);
+/// ```
+#[macro_export]
+macro_rules! bench {
+    ($name:expr, $n:expr, $body:expr) => {
+        $crate::bench::run($name, $n, || $body)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    // a counter advancing 3 cycles per read, `cost` more per run of the code
+    fn bench(costs: &[u32]) -> Report {
+        let counter = Cell::new(u32::MAX - 50);
+        let read = || {
+            let now = counter.get().wrapping_add(3);
+            counter.set(now);
+            now
+        };
+        let mut runs = costs.iter().cycle();
+        run_with("test", costs.len() as u32, Hertz::mhz(16), read, || {
+            counter.set(counter.get().wrapping_add(*runs.next().unwrap()));
+        })
+    }
+
+    #[test]
+    fn overhead_subtracted() {
+        // the warm up run takes the first cost, the runs start over at the second
+        let report = bench(&[100, 10, 40, 30]);
+        assert_eq!(report.overhead, 3);
+        assert_eq!(report.stats.n, 4);
+        assert_eq!(report.stats.min, 10);
+        assert_eq!(report.stats.max, 100);
+        assert_eq!(report.stats.mean(), 45);
+        assert_eq!(report.stats.jitter(), 90);
+    }
+
+    #[test]
+    fn empty() {
+        let stats = Stats::new();
+        assert_eq!(stats.min, 0);
+        assert_eq!(stats.mean(), 0);
+        assert_eq!(stats.jitter(), 0);
+
+        // no runs, nothing to subtract
+        let report = run_with("test", 0, Hertz::mhz(16), || 0, || ());
+        assert_eq!(report.overhead, 0);
+        assert_eq!(report.stats, Stats::new());
+    }
+
+    #[test]
+    fn report() {
+        let mut stats = Stats::new();
+        for cycles in &[4010, 4017, 4012, 4011] {
+            stats.push(*cycles);
+        }
+        let report = Report {
+            name: "wait",
+            stats,
+            overhead: 2,
+            clock: Hertz::mhz(16),
+        };
+        assert_eq!(report.nanos(16), 1_000);
+        assert_eq!(
+            format!("{}", report),
+            format!(
+                "bench wait profile={} clock=16000000 n=4 min=4010 max=4017 mean=4012 \
+                 jitter=7 overhead=2 min_ns=250625 max_ns=251062 mean_ns=250750",
+                profile()
+            )
+        );
+        assert_eq!(
+            format!("{:#}", report),
+            format!(
+                "wait ({}, 16 MHz, 4 runs): min 4010, max 4017, mean 4012 (jitter 7) cycles, \
+                 mean 250750 ns",
+                profile()
+            )
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! LED blinker with adjustable frequency and duty cycle
+//!
+//! The state shared by the `on` and `off` tasks of `rtfm_blinker.rs`, which
+//! reschedule each other (from `cx.scheduled`) after `on_cycles` and
+//! `off_cycles` respectively. Frequency (Hz) and duty cycle (%) are converted to
+//! CYCCNT cycles from the core clock (`Clocks::hclk`), and take effect at the
+//! next edge.
+//!
+//! In the `Breathe` mode the LED is driven by PWM (`app::pwm`), fading in and
+//! out once per period, up to the duty cycle as peak brightness. A `breathe`
+//! task steps the brightness every `breathe_cycles`.
+//!
+//! Scheduled tasks cannot be cancelled, so each start hands out a new
+//! `Sequence`, passed along as the message of the `on` and `off` tasks. A task
+//! of a stale sequence (stopped, or replaced by a restart) ends it instead of
+//! rescheduling, so there is never more than one sequence running.
+
+use crate::pwm;
+
+/// Shortest period, leaves time for the `on`/`off` tasks to run
+pub const MIN_PERIOD: u32 = 10_000;
+
+/// Brightness steps per period, when breathing
+pub const BREATHE_STEPS: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// zero, or shorter than `MIN_PERIOD` cycles
+    Frequency,
+    /// above 100%
+    Duty,
+}
+
+/// How the LED is driven
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// on and off (`on`/`off` tasks)
+    Blink,
+    /// fading in and out (`breathe` task)
+    Breathe,
+}
+
+/// Identifies a started sequence of `on`/`off` tasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sequence(u32);
+
+pub struct Blinker {
+    // core clock, Hz
+    clock: u32,
+    // cycles
+    period: u32,
+    // percent
+    duty: u32,
+    running: bool,
+    mode: Mode,
+    // the most recently started sequence
+    sequence: Sequence,
+}
+
+impl Blinker {
+    /// A stopped blinker, 1 Hz and 50% duty cycle, for a core clock of `clock` Hz
+    pub fn new(clock: u32) -> Self {
+        Blinker {
+            clock,
+            period: clock,
+            duty: 50,
+            running: false,
+            mode: Mode::Blink,
+            sequence: Sequence(0),
+        }
+    }
+
+    pub fn set_frequency(&mut self, hz: u32) -> Result<(), Error> {
+        if hz == 0 || self.clock / hz < MIN_PERIOD {
+            return Err(Error::Frequency);
+        }
+        self.period = self.clock / hz;
+        Ok(())
+    }
+
+    pub fn set_duty(&mut self, percent: u32) -> Result<(), Error> {
+        if percent > 100 {
+            return Err(Error::Duty);
+        }
+        self.duty = percent;
+        Ok(())
+    }
+
+    /// Period in cycles
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    /// Duty cycle in percent
+    pub fn duty(&self) -> u32 {
+        self.duty
+    }
+
+    /// Cycles the LED is on, per period
+    pub fn on_cycles(&self) -> u32 {
+        (self.period as u64 * self.duty as u64 / 100) as u32
+    }
+
+    /// Cycles the LED is off, per period
+    pub fn off_cycles(&self) -> u32 {
+        self.period - self.on_cycles()
+    }
+
+    /// Cycles between brightness steps, when breathing
+    pub fn breathe_cycles(&self) -> u32 {
+        (self.period / BREATHE_STEPS).max(MIN_PERIOD)
+    }
+
+    /// Brightness in percent at `step` of a breath (of `BREATHE_STEPS`)
+    pub fn brightness(&self, step: u32) -> u32 {
+        pwm::breathe(step, BREATHE_STEPS, self.duty)
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Sets the mode, the new sequence (to spawn the task of `mode` with) if
+    /// running in another mode, the running sequence ends at its next edge
+    pub fn set_mode(&mut self, mode: Mode) -> Option<Sequence> {
+        if mode == self.mode {
+            return None;
+        }
+        self.mode = mode;
+        if !self.running {
+            return None;
+        }
+        self.stop();
+        self.start()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Starts blinking, the new sequence (to spawn the `on` task with), or
+    /// `None` if already running
+    pub fn start(&mut self) -> Option<Sequence> {
+        if self.running {
+            return None;
+        }
+        self.running = true;
+        self.sequence = Sequence(self.sequence.0.wrapping_add(1));
+        Some(self.sequence)
+    }
+
+    /// Stops blinking, the sequence ends at its next edge
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    /// `false` if `sequence` is stopped or replaced (the task ends the
+    /// sequence, instead of rescheduling)
+    pub fn is_current(&self, sequence: Sequence) -> bool {
+        self.running && sequence == self.sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK: u32 = 16_000_000;
+
+    #[test]
+    fn cycles() {
+        let mut b = Blinker::new(CLOCK);
+        assert_eq!((b.on_cycles(), b.off_cycles()), (8_000_000, 8_000_000));
+
+        b.set_frequency(4).unwrap();
+        b.set_duty(25).unwrap();
+        assert_eq!(b.period(), 4_000_000);
+        assert_eq!((b.on_cycles(), b.off_cycles()), (1_000_000, 3_000_000));
+
+        b.set_duty(0).unwrap();
+        assert_eq!((b.on_cycles(), b.off_cycles()), (0, 4_000_000));
+        b.set_duty(100).unwrap();
+        assert_eq!((b.on_cycles(), b.off_cycles()), (4_000_000, 0));
+    }
+
+    #[test]
+    fn core_clock() {
+        let mut b = Blinker::new(84_000_000);
+        b.set_frequency(3).unwrap();
+        b.set_duty(33).unwrap();
+        assert_eq!(b.period(), 28_000_000);
+        assert_eq!(b.on_cycles() + b.off_cycles(), b.period());
+        assert_eq!(b.on_cycles(), 9_240_000);
+    }
+
+    #[test]
+    fn limits() {
+        let mut b = Blinker::new(CLOCK);
+        assert_eq!(b.set_frequency(0), Err(Error::Frequency));
+        assert_eq!(b.set_frequency(CLOCK / MIN_PERIOD), Ok(()));
+        assert_eq!(
+            b.set_frequency(CLOCK / MIN_PERIOD + 1),
+            Err(Error::Frequency)
+        );
+        assert_eq!(b.set_duty(101), Err(Error::Duty));
+        // unchanged on error
+        assert_eq!((b.period(), b.duty()), (MIN_PERIOD, 50));
+    }
+
+    #[test]
+    fn start_stop() {
+        let mut b = Blinker::new(CLOCK);
+        assert!(!b.is_running());
+
+        let s = b.start().unwrap();
+        assert!(b.is_current(s));
+        // no second, overlapping, sequence
+        assert_eq!(b.start(), None);
+
+        b.stop();
+        assert!(!b.is_running());
+        // the pending task ends the sequence
+        assert!(!b.is_current(s));
+    }
+
+    #[test]
+    fn breathe() {
+        let mut b = Blinker::new(CLOCK);
+        assert_eq!(b.set_mode(Mode::Breathe), None);
+        let s1 = b.start().unwrap();
+        assert_eq!(b.set_mode(Mode::Breathe), None);
+
+        // 50 steps of 320_000 cycles (20 ms) per second, up to the duty
+        assert_eq!(b.breathe_cycles(), 320_000);
+        assert_eq!(b.brightness(0), 0);
+        assert_eq!(b.brightness(BREATHE_STEPS / 2), 50);
+
+        // back to blinking, in a new sequence
+        let s2 = b.set_mode(Mode::Blink).unwrap();
+        assert!(!b.is_current(s1));
+        assert!(b.is_current(s2));
+    }
+
+    #[test]
+    fn restart() {
+        let mut b = Blinker::new(CLOCK);
+        let s1 = b.start().unwrap();
+
+        // restarted before the pending task of `s1` has run
+        b.stop();
+        let s2 = b.start().unwrap();
+        assert_ne!(s1, s2);
+        assert!(!b.is_current(s1));
+        assert!(b.is_current(s2));
+    }
+}
@@ -0,0 +1,98 @@
+//! User LED (PA5) blinky of `bare4.rs`, over a `RegisterBus`
+//!
+//! see the Reference Manual RM0368 (www.st.com/resource/en/reference_manual/dm00096844.pdf)
+//! rcc,     chapter 6
+//! gpio,    chapter 8
+
+use crate::bus::RegisterBus;
+
+// Register addresses as constants, from the register blocks of `regs.rs`
+#[rustfmt::skip]
+pub mod address {
+    use crate::regs::{Gpio, Rcc, GPIOA, RCC};
+    use core::mem::offset_of;
+
+    pub const RCC_AHB1ENR: u32      = RCC.reg(offset_of!(Rcc, AHB1ENR));
+    pub const GPIOA_MODER: u32      = GPIOA.reg(offset_of!(Gpio, MODER));
+    pub const GPIOA_BSRR: u32       = GPIOA.reg(offset_of!(Gpio, BSRR));
+}
+
+use address::*;
+
+/// Power on GPIOA and configure PA5 as output
+pub fn init<B: RegisterBus>(bus: &B) {
+    // power on GPIOA, RM0368 6.3.9
+    bus.modify_u32(RCC_AHB1ENR, 0, 1);
+
+    // configure PA5 as output, RM0368 8.4.1
+    bus.modify_u32(GPIOA_MODER, 0b11 << (5 * 2), 0b01 << (5 * 2));
+}
+
+/// Set PA5 high (turn on led), RM0368 8.4.7
+pub fn led_on<B: RegisterBus>(bus: &B) {
+    bus.write_u32(GPIOA_BSRR, 1 << 5);
+}
+
+/// Set PA5 low (turn off led), RM0368 8.4.7
+pub fn led_off<B: RegisterBus>(bus: &B) {
+    bus.write_u32(GPIOA_BSRR, 1 << (5 + 16));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Access::*, MemoryBus};
+
+    // GPIOA_MODER reset value (debug pins PA13-15 in alternate function mode)
+    const MODER_RESET: u32 = 0xA800_0000;
+
+    #[test]
+    fn addresses() {
+        assert_eq!(RCC_AHB1ENR, 0x4002_3830);
+        assert_eq!(GPIOA_MODER, 0x4002_0000);
+        assert_eq!(GPIOA_BSRR, 0x4002_0018);
+    }
+
+    #[test]
+    fn init_sequence() {
+        let bus = MemoryBus::new();
+        bus.preset(GPIOA_MODER, MODER_RESET);
+
+        init(&bus);
+
+        assert_eq!(
+            &bus.accesses()[..],
+            &[
+                Read(RCC_AHB1ENR, 0),
+                Write(RCC_AHB1ENR, 1),
+                Read(GPIOA_MODER, MODER_RESET),
+                Write(GPIOA_MODER, MODER_RESET | 0b01 << 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn init_keeps_other_bits() {
+        let bus = MemoryBus::new();
+        bus.preset(RCC_AHB1ENR, 1 << 2);
+        bus.preset(GPIOA_MODER, MODER_RESET | 0b11 << 10);
+
+        init(&bus);
+
+        assert_eq!(bus.peek(RCC_AHB1ENR), 1 << 2 | 1);
+        assert_eq!(bus.peek(GPIOA_MODER), MODER_RESET | 0b01 << 10);
+    }
+
+    #[test]
+    fn blink_writes_bsrr_only() {
+        let bus = MemoryBus::new();
+
+        led_on(&bus);
+        led_off(&bus);
+
+        assert_eq!(
+            &bus.accesses()[..],
+            &[Write(GPIOA_BSRR, 1 << 5), Write(GPIOA_BSRR, 1 << 21)]
+        );
+    }
+}
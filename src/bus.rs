@@ -0,0 +1,169 @@
+//! Register access through a pluggable bus
+//!
+//! `bare4.rs` accesses the peripherals by volatile reads/writes on absolute
+//! addresses. Here the same accesses go through the `RegisterBus` trait, so code
+//! built on top of it can run on:
+//! - `Mmio`, real memory mapped IO (on the target)
+//! - `MemoryBus`, an in-memory register file recording each access (on the host)
+
+use core::cell::RefCell;
+use core::ptr;
+
+use heapless::consts::*;
+use heapless::{LinearMap, Vec};
+
+/// 32 bit register access by absolute address
+pub trait RegisterBus {
+    /// Read the register at `addr`
+    fn read_u32(&self, addr: u32) -> u32;
+
+    /// Write `val` to the register at `addr`
+    fn write_u32(&self, addr: u32, val: u32);
+
+    /// Read the register at `addr`, clear the `mask` bits and set the `bits`
+    #[inline(always)]
+    fn modify_u32(&self, addr: u32, mask: u32, bits: u32) {
+        let r = self.read_u32(addr) & !mask;
+        self.write_u32(addr, r | bits);
+    }
+}
+
+/// Memory mapped IO, volatile access to the physical address
+pub struct Mmio {
+    _0: (),
+}
+
+impl Mmio {
+    /// # Safety
+    ///
+    /// Every address later passed to the bus must be a valid, aligned
+    /// register address (RM0368, memory map, section 2.3).
+    pub const unsafe fn new() -> Self {
+        Mmio { _0: () }
+    }
+}
+
+impl RegisterBus for Mmio {
+    #[inline(always)]
+    fn read_u32(&self, addr: u32) -> u32 {
+        unsafe { ptr::read_volatile(addr as *const _) }
+    }
+
+    #[inline(always)]
+    fn write_u32(&self, addr: u32, val: u32) {
+        unsafe { ptr::write_volatile(addr as *mut _, val) }
+    }
+}
+
+/// A single register access, as recorded by `MemoryBus`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// `Read(addr, value read)`
+    Read(u32, u32),
+    /// `Write(addr, value written)`
+    Write(u32, u32),
+}
+
+/// Max number of distinct registers held by a `MemoryBus`
+pub type Registers = U32;
+/// Max number of accesses recorded by a `MemoryBus`
+pub type LogLength = U128;
+
+/// In-memory register file, for running register level code on the host
+///
+/// Registers that have not been written (or preset) read as 0.
+pub struct MemoryBus {
+    regs: RefCell<LinearMap<u32, u32, Registers>>,
+    log: RefCell<Vec<Access, LogLength>>,
+}
+
+impl MemoryBus {
+    pub fn new() -> Self {
+        MemoryBus {
+            regs: RefCell::new(LinearMap::new()),
+            log: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Set the register at `addr` (e.g., to its reset value), not recorded
+    pub fn preset(&self, addr: u32, val: u32) {
+        self.store(addr, val);
+    }
+
+    /// Current value of the register at `addr`, not recorded
+    pub fn peek(&self, addr: u32) -> u32 {
+        self.regs.borrow().get(&addr).cloned().unwrap_or(0)
+    }
+
+    /// The accesses recorded so far, in order
+    pub fn accesses(&self) -> Vec<Access, LogLength> {
+        self.log.borrow().clone()
+    }
+
+    /// Forget the accesses recorded so far (the register values are kept)
+    pub fn clear_log(&self) {
+        self.log.borrow_mut().clear();
+    }
+
+    fn store(&self, addr: u32, val: u32) {
+        if self.regs.borrow_mut().insert(addr, val).is_err() {
+            panic!("MemoryBus: out of registers");
+        }
+    }
+
+    fn record(&self, access: Access) {
+        if self.log.borrow_mut().push(access).is_err() {
+            panic!("MemoryBus: access log full");
+        }
+    }
+}
+
+impl Default for MemoryBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegisterBus for MemoryBus {
+    fn read_u32(&self, addr: u32) -> u32 {
+        let val = self.peek(addr);
+        self.record(Access::Read(addr, val));
+        val
+    }
+
+    fn write_u32(&self, addr: u32, val: u32) {
+        self.store(addr, val);
+        self.record(Access::Write(addr, val));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_register_reads_zero() {
+        let bus = MemoryBus::new();
+        assert_eq!(bus.read_u32(0x4002_0000), 0);
+        assert_eq!(&bus.accesses()[..], &[Access::Read(0x4002_0000, 0)]);
+    }
+
+    #[test]
+    fn preset_is_not_recorded() {
+        let bus = MemoryBus::new();
+        bus.preset(0x4002_0000, 0xA800_0000);
+        assert_eq!(bus.peek(0x4002_0000), 0xA800_0000);
+        assert!(bus.accesses().is_empty());
+    }
+
+    #[test]
+    fn modify_reads_then_writes() {
+        let bus = MemoryBus::new();
+        bus.preset(0x10, 0b1111);
+        bus.modify_u32(0x10, 0b0110, 0b0100);
+        assert_eq!(
+            &bus.accesses()[..],
+            &[Access::Read(0x10, 0b1111), Access::Write(0x10, 0b1101)]
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! Input capture, measuring signals by the timers (RM0368 13.3.5, 13.3.6)
+//!
+//! `bare6.rs` and `bare7.rs` route SYSCLK to MCO2 (PC9), to be measured by an
+//! oscilloscope. Two ways to measure without one:
+//!
+//! - `Capture`, the PWM input mode of a timer: channel 1 captures the counter
+//!   at each rising edge of TI1 (then resets it), channel 2 at each falling
+//!   edge, giving the period and the high time in timer ticks. The timer clock
+//!   is the reference, so the signal must be slower than the ticks, and
+//!   faster than the counter wraps.
+//! - `Counter`, a frequency counter on TIM5: the signal on TI1 (PA0) clocks
+//!   the counter (external clock mode 1), and channel 4 captures it at every
+//!   8th edge of the LSE (the 32.768 kHz crystal, internally routed by
+//!   `TIM5_OR`). The reference is the crystal, not a clock derived from
+//!   SYSCLK, so it also measures SYSCLK itself.
+//!
+//! The self test (`capture.rs`) loops MCO2 (SYSCLK / 5) back to PA0 by a
+//! wire, and reports the measured SYSCLK against the planned one:
+//!
+//! ``` ignore
+//! clocks::mco(&bus, Mco::Mco2(Mco2::Sysclk), 5)?;
+//! clocks::lse_on(&bus)?;
+//! let counter = Counter::new(bus);
+//! counter.input();
+//! let report = counter.self_test(plan.sysclk, 5)?;
+//! ```
+//!
+//! The counter input is sampled by the timer clock, so the signal must be
+//! below half of it (at most SYSCLK / 5 on MCO2, for a timer clock of at least
+//! SYSCLK / 2).
+
+use core::fmt;
+use core::mem::offset_of;
+
+use crate::bus::RegisterBus;
+use crate::clocks::LSE_HZ;
+use crate::exti::{Pin, Port};
+use crate::pwm::{self, Timer};
+use crate::regs::{
+    Tim, AF_TIM3_5, CCER_CCE, CCER_CCP, CCMR_CCS, CCMR_ICPSC, CCS_TI_OTHER, CCS_TI_SAME, CR1_CEN,
+    EGR_UG, SMCR_SMS, SMCR_TS, SMS_EXTERNAL_CLOCK, SMS_RESET, TI4_RMP_LSE, TIM5_OR_TI4_RMP,
+    TS_TI1FP1,
+};
+use crate::time::Hertz;
+
+/// TIM5_CH1 (and TIM2_CH1) in alternate function 2, A0 on the Nucleo
+pub const PA0: Pin = Pin::new(Port::A, 0);
+
+/// LSE edges per capture of the `Counter` (the input prescaler of channel 4)
+pub const LSE_EDGES: u32 = 8;
+
+// polls of a capture flag, before giving up (a capture is expected every
+// 244 us, i.e., within about 2_000 polls at 84 MHz)
+const POLL_LIMIT: u32 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// prescaler out of 1 to 65536
+    Prescaler,
+    /// no capture, the reference (LSE) is not running
+    NoReference,
+    /// no edges counted, the input is not connected (MCO2 to PA0)
+    NoSignal,
+    /// a capture was missed while polling
+    Overcapture,
+}
+
+/// A period of a signal, in timer ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pulse {
+    pub period: u32,
+    pub high: u32,
+    /// The tick frequency
+    pub tick: Hertz,
+}
+
+impl Pulse {
+    /// Frequency in Hz, rounded
+    pub fn frequency(&self) -> u32 {
+        ((self.tick.0 as u64 + self.period as u64 / 2) / self.period as u64) as u32
+    }
+
+    /// Duty cycle in percent, rounded
+    pub fn duty(&self) -> u32 {
+        ((self.high as u64 * 100 + self.period as u64 / 2) / self.period as u64) as u32
+    }
+}
+
+// the `CCMRx` field of `channel`, set to `bits` (relative to `Tim::ccmr`)
+fn ccmr<B: RegisterBus>(bus: &B, timer: Timer, channel: u8, bits: u32) {
+    let field = Tim::ccmr(channel);
+    let ccmr = timer.reg(Tim::ccmr_offset(channel));
+    bus.modify_u32(ccmr, field.mask(), bits << field.offset());
+}
+
+// captures of `channel` since the last read, reading clears the flag
+fn poll<B: RegisterBus>(bus: &B, timer: Timer, channel: u8) -> Option<u32> {
+    let sr = bus.read_u32(timer.reg(offset_of!(Tim, SR)));
+    if sr & Tim::ccif(channel).mask() == 0 {
+        return None;
+    }
+    Some(bus.read_u32(timer.reg(Tim::ccr_offset(channel))))
+}
+
+/// PWM input (period and duty) on channel 1 of a timer, over a `RegisterBus`
+pub struct Capture<B: RegisterBus> {
+    bus: B,
+    timer: Timer,
+    tick: Hertz,
+}
+
+impl<B: RegisterBus> Capture<B> {
+    /// Powers on and starts `timer`, counting ticks of `timclk` divided by
+    /// `prescaler` (1 to 65536)
+    pub fn new(bus: B, timer: Timer, timclk: Hertz, prescaler: u32) -> Result<Self, Error> {
+        if prescaler == 0 || prescaler > 0x1_0000 {
+            return Err(Error::Prescaler);
+        }
+        let reg = |offset| timer.reg(offset);
+        bus.modify_u32(RCC_APB1ENR, 0, timer.enable().mask());
+        bus.write_u32(reg(offset_of!(Tim, PSC)), prescaler - 1);
+        // the whole counter, no PWM output
+        bus.write_u32(reg(offset_of!(Tim, ARR)), timer.counter_max());
+        bus.write_u32(reg(offset_of!(Tim, EGR)), EGR_UG.mask());
+
+        // TI1 to both channels, 1 at the rising edge, 2 at the falling edge
+        ccmr(&bus, timer, 0, CCMR_CCS.val(CCS_TI_SAME).bits());
+        ccmr(&bus, timer, 1, CCMR_CCS.val(CCS_TI_OTHER).bits());
+        let ch2 = (CCER_CCE.mask() | CCER_CCP.mask()) << Tim::ccer(1).offset();
+        bus.write_u32(reg(offset_of!(Tim, CCER)), CCER_CCE.mask() | ch2);
+        // the counter restarts at each rising edge
+        let smcr = SMCR_TS.val(TS_TI1FP1).bits() | SMCR_SMS.val(SMS_RESET).bits();
+        bus.write_u32(reg(offset_of!(Tim, SMCR)), smcr);
+        bus.write_u32(reg(offset_of!(Tim, CR1)), CR1_CEN.mask());
+
+        Ok(Capture {
+            bus,
+            timer,
+            tick: Hertz(timclk.0 / prescaler),
+        })
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    /// Connects `pin` to TI1, by alternate function `af` (e.g., `PA6` to
+    /// TIM3_CH1 by `AF_TIM3_5`)
+    pub fn input(&self, pin: Pin, af: u32) {
+        pwm::alternate(&self.bus, pin, af);
+    }
+
+    /// The last period, if a rising edge was captured since the last poll
+    pub fn poll(&self) -> Option<Pulse> {
+        let period = poll(&self.bus, self.timer, 0)?;
+        let high = self.bus.read_u32(self.timer.reg(Tim::ccr_offset(1)));
+        if period == 0 {
+            return None;
+        }
+        Some(Pulse {
+            period,
+            high,
+            tick: self.tick,
+        })
+    }
+}
+
+/// Frequency of `edges` counted over `captures` of the `Counter`
+pub fn counted_hz(edges: u32, captures: u32) -> u32 {
+    let time = (LSE_EDGES * captures) as u64;
+    ((edges as u64 * LSE_HZ as u64 + time / 2) / time) as u32
+}
+
+/// A frequency counter on TIM5, the LSE as reference, over a `RegisterBus`
+pub struct Counter<B: RegisterBus> {
+    bus: B,
+}
+
+impl<B: RegisterBus> Counter<B> {
+    /// Powers on and starts TIM5, counting the edges of TI1, captured by
+    /// channel 4 at every `LSE_EDGES`th LSE edge
+    ///
+    /// The LSE must be on (`clocks::lse_on`).
+    pub fn new(bus: B) -> Self {
+        let timer = Timer::Tim5;
+        let reg = |offset| timer.reg(offset);
+        bus.modify_u32(RCC_APB1ENR, 0, timer.enable().mask());
+        bus.write_u32(reg(offset_of!(Tim, PSC)), 0);
+        bus.write_u32(reg(offset_of!(Tim, ARR)), u32::MAX);
+        bus.write_u32(reg(offset_of!(Tim, EGR)), EGR_UG.mask());
+
+        let or = TIM5_OR_TI4_RMP.val(TI4_RMP_LSE).bits();
+        bus.write_u32(reg(offset_of!(Tim, OR)), or);
+        ccmr(&bus, timer, 0, CCMR_CCS.val(CCS_TI_SAME).bits());
+        // capture every 8th edge (`LSE_EDGES`)
+        let ch4 = CCMR_CCS.val(CCS_TI_SAME).bits() | CCMR_ICPSC.val(0b11).bits();
+        ccmr(&bus, timer, 3, ch4);
+        let ccer = CCER_CCE.mask() << Tim::ccer(3).offset();
+        bus.write_u32(reg(offset_of!(Tim, CCER)), ccer);
+        // TI1 clocks the counter
+        let smcr = SMCR_TS.val(TS_TI1FP1).bits() | SMCR_SMS.val(SMS_EXTERNAL_CLOCK).bits();
+        bus.write_u32(reg(offset_of!(Tim, SMCR)), smcr);
+        bus.write_u32(reg(offset_of!(Tim, CR1)), CR1_CEN.mask());
+        Counter { bus }
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    /// Connects `PA0` (the only TI1 pin of TIM5 on the Nucleo) as the input
+    pub fn input(&self) {
+        pwm::alternate(&self.bus, PA0, AF_TIM3_5);
+    }
+
+    /// The counter at the last capture, if any since the last poll
+    pub fn poll(&self) -> Option<u32> {
+        poll(&self.bus, Timer::Tim5, 3)
+    }
+
+    fn wait(&self) -> Result<u32, Error> {
+        (0..POLL_LIMIT)
+            .find_map(|_| self.poll())
+            .ok_or(Error::NoReference)
+    }
+
+    /// Measures the input frequency over `captures` (of `LSE_EDGES` LSE edges
+    /// each, 244 us), polling
+    ///
+    /// Interrupts should be disabled, else a capture may be missed.
+    pub fn measure(&self, captures: u32) -> Result<u32, Error> {
+        let sr = Timer::Tim5.reg(offset_of!(Tim, SR));
+        let overcapture = Tim::ccof(3).mask();
+        let first = self.wait()?;
+        // flags are cleared by writing 0 (rc_w0), writing 1 leaves them as
+        // they are, a read-modify-write would clear a flag set in between
+        self.bus.write_u32(sr, !overcapture);
+        let mut last = first;
+        for _ in 0..captures {
+            last = self.wait()?;
+        }
+        if self.bus.read_u32(sr) & overcapture != 0 {
+            return Err(Error::Overcapture);
+        }
+
+        let edges = last.wrapping_sub(first);
+        if edges == 0 {
+            return Err(Error::NoSignal);
+        }
+        Ok(counted_hz(edges, captures))
+    }
+
+    /// Measures SYSCLK on MCO2 (looped back to `PA0`), divided by `div`, over
+    /// 128 captures (31 ms)
+    pub fn self_test(&self, planned: u32, div: u8) -> Result<Report, Error> {
+        let hz = self.measure(128)?;
+        Ok(Report {
+            planned,
+            measured: hz * div as u32,
+        })
+    }
+}
+
+/// The planned and the measured SYSCLK
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub planned: u32,
+    pub measured: u32,
+}
+
+impl Report {
+    /// The error of the measured SYSCLK, in parts per million of the planned
+    pub fn error_ppm(&self) -> i32 {
+        let diff = self.measured as i64 - self.planned as i64;
+        (diff * 1_000_000 / self.planned as i64) as i32
+    }
+
+    /// Within `ppm` of the planned SYSCLK (the HSI is trimmed to 1%, 10_000
+    /// ppm, at 25 C, a crystal or the ST-LINK MCO to about 50 ppm)
+    pub fn passed(&self, ppm: u32) -> bool {
+        self.error_ppm().unsigned_abs() <= ppm
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SYSCLK planned {} Hz, measured {} Hz ({:+} ppm)",
+            self.planned,
+            self.measured,
+            self.error_ppm()
+        )
+    }
+}
+
+#[rustfmt::skip]
+pub mod address {
+    use super::*;
+    use crate::regs::{Rcc, RCC};
+
+    pub const RCC_APB1ENR: u32      = RCC.reg(offset_of!(Rcc, APB1ENR));
+}
+
+use address::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Access::*, MemoryBus};
+
+    fn tim(timer: Timer, offset: usize) -> u32 {
+        timer.reg(offset)
+    }
+
+    #[test]
+    fn pwm_input() {
+        let capture = Capture::new(MemoryBus::new(), Timer::Tim3, Hertz::mhz(16), 16).unwrap();
+        let bus = capture.bus();
+        assert_eq!(bus.peek(tim(Timer::Tim3, offset_of!(Tim, PSC))), 15);
+        assert_eq!(bus.peek(tim(Timer::Tim3, offset_of!(Tim, ARR))), 0xFFFF);
+        // CC1S = TI1, CC2S = TI1 (of channel 1)
+        assert_eq!(bus.peek(tim(Timer::Tim3, offset_of!(Tim, CCMR1))), 0x0201);
+        // CC1E, CC2E and CC2P (falling)
+        assert_eq!(bus.peek(tim(Timer::Tim3, offset_of!(Tim, CCER))), 0x31);
+        // TS = TI1FP1, SMS = reset
+        assert_eq!(bus.peek(tim(Timer::Tim3, offset_of!(Tim, SMCR))), 0x54);
+        assert_eq!(bus.peek(tim(Timer::Tim3, offset_of!(Tim, CR1))), 1);
+
+        assert_eq!(capture.poll(), None);
+        // the servo of `pwm.rs`, 50 Hz, 1.5 ms, in 1 us ticks
+        bus.preset(tim(Timer::Tim3, offset_of!(Tim, SR)), 1 << 1);
+        bus.preset(tim(Timer::Tim3, Tim::ccr_offset(0)), 20_000);
+        bus.preset(tim(Timer::Tim3, Tim::ccr_offset(1)), 1_500);
+        let pulse = capture.poll().unwrap();
+        assert_eq!((pulse.frequency(), pulse.duty()), (50, 8));
+
+        // the whole 32 bit counter
+        let tim2 = Capture::new(MemoryBus::new(), Timer::Tim2, Hertz::mhz(16), 16).unwrap();
+        let arr = tim(Timer::Tim2, offset_of!(Tim, ARR));
+        assert_eq!(tim2.bus().peek(arr), u32::MAX);
+
+        let prescaler = Capture::new(MemoryBus::new(), Timer::Tim3, Hertz::mhz(16), 0);
+        assert!(matches!(prescaler, Err(Error::Prescaler)));
+    }
+
+    #[test]
+    fn counter() {
+        let counter = Counter::new(MemoryBus::new());
+        let bus = counter.bus();
+        let tim5 = |offset| tim(Timer::Tim5, offset);
+        assert_eq!(bus.peek(tim5(offset_of!(Tim, ARR))), u32::MAX);
+        assert_eq!(bus.peek(tim5(offset_of!(Tim, OR))), 0b10 << 6);
+        assert_eq!(bus.peek(tim5(offset_of!(Tim, CCMR1))), 0x01);
+        // CC4S = TI4, IC4PSC = 8
+        assert_eq!(bus.peek(tim5(offset_of!(Tim, CCMR2))), 0x0D00);
+        assert_eq!(bus.peek(tim5(offset_of!(Tim, CCER))), 0x1000);
+        // TS = TI1FP1, SMS = external clock
+        assert_eq!(bus.peek(tim5(offset_of!(Tim, SMCR))), 0x57);
+        assert_eq!(bus.peek(RCC_APB1ENR), 1 << 3);
+
+        counter.input();
+        assert!(bus.accesses().contains(&Write(0x4002_0000, 0b10)));
+    }
+
+    #[test]
+    fn not_connected() {
+        let counter = Counter::new(MemoryBus::new());
+        // captures, of a counter standing still
+        let bus = counter.bus();
+        bus.preset(Timer::Tim5.reg(offset_of!(Tim, SR)), 1 << 4);
+        bus.preset(Timer::Tim5.reg(Tim::ccr_offset(3)), 1234);
+        assert_eq!(counter.measure(4), Err(Error::NoSignal));
+        // CC4OF cleared alone, not read back
+        let sr = Timer::Tim5.reg(offset_of!(Tim, SR));
+        assert!(bus.accesses().contains(&Write(sr, !(1 << 12))));
+    }
+
+    #[test]
+    fn frequencies() {
+        // SYSCLK / 5 at 84 MHz, 4101.5625 edges per capture
+        assert_eq!(counted_hz(525_000, 128), 16_800_000);
+        assert_eq!(counted_hz(525_001, 128), 16_800_032);
+        assert_eq!(counted_hz(4_096 * 128, 128), 16_777_216);
+        assert_eq!(counted_hz(0, 1), 0);
+
+        let report = Report {
+            planned: 84_000_000,
+            measured: 83_160_000,
+        };
+        assert_eq!(report.error_ppm(), -10_000);
+        assert!(report.passed(20_000));
+        assert!(!report.passed(5_000));
+        assert_eq!(
+            report.to_string(),
+            "SYSCLK planned 84000000 Hz, measured 83160000 Hz (-10000 ppm)"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Line oriented command interpreter
+//!
+//! The command line interpreter of `bare10.rs` (assignment 3). Bytes are pushed
+//! one at a time (e.g., from the `usart2` task), a line ends with CR or LF, and
+//! its first word is looked up in a command table:
+//!
+//! ``` ignore
+//! let mut cli = Cli::new(BLINK);
+//! for &b in b"set 2\r" {
+//!     if let Some(r) = cli.push(b) {
+//!         assert_eq!(r, Ok(Blink::Set(2)));
+//!     }
+//! }
+//! ```
+//!
+//! A line holds at most `LineLength` bytes (not counting the CR/LF), a longer
+//! line is discarded and reported as `Error::Overflow`, even if backspaced to
+//! fit again (the bytes beyond the limit are lost).
+
+use heapless::consts::*;
+use heapless::Vec;
+
+/// Max number of bytes of a line
+pub type LineLength = U10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// the line did not fit the buffer
+    Overflow,
+    /// the command is not in the table
+    UnknownCommand,
+    /// the command takes an argument
+    MissingArgument,
+    /// too many arguments
+    UnexpectedArgument,
+    /// the argument is not a (32 bit, unsigned) decimal number
+    InvalidNumber,
+}
+
+/// How a command is parsed
+pub enum Command<C> {
+    /// `name`
+    Plain(C),
+    /// `name <int>`
+    Int(fn(u32) -> C),
+}
+
+/// A command table, `(name, command)`
+pub type Table<C> = [(&'static str, Command<C>)];
+
+/// The blink controller commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blink {
+    /// `set <int>`, blink frequency in Hz
+    Set(u32),
+    /// `duty <int>`, duty cycle in percent
+    Duty(u32),
+    /// `on`, start blinking
+    On,
+    /// `off`, stop blinking
+    Off,
+    /// `breathe`, start fading in and out (by PWM)
+    Breathe,
+}
+
+/// The blink controller command table
+pub const BLINK: &Table<Blink> = &[
+    ("set", Command::Int(Blink::Set)),
+    ("duty", Command::Int(Blink::Duty)),
+    ("on", Command::Plain(Blink::On)),
+    ("off", Command::Plain(Blink::Off)),
+    ("breathe", Command::Plain(Blink::Breathe)),
+];
+
+// ASCII backspace and delete (sent by the backspace key of most terminals)
+const BS: u8 = 0x08;
+const DEL: u8 = 0x7f;
+
+pub struct Cli<C: 'static> {
+    table: &'static Table<C>,
+    line: Vec<u8, LineLength>,
+    overflow: bool,
+}
+
+impl<C: Copy> Cli<C> {
+    pub fn new(table: &'static Table<C>) -> Self {
+        Cli {
+            table,
+            line: Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// Pushes a received byte, returns the parsed command (or error) at the end
+    /// of a (non empty) line
+    pub fn push(&mut self, byte: u8) -> Option<Result<C, Error>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let result = if self.overflow {
+                    Some(Err(Error::Overflow))
+                } else if self.line.iter().all(u8::is_ascii_whitespace) {
+                    // empty line, or the LF of CR LF
+                    None
+                } else {
+                    Some(self.parse())
+                };
+                self.line = Vec::new();
+                self.overflow = false;
+                result
+            }
+            // an overflowed line stays rejected, backspace or not
+            BS | DEL => {
+                self.line.pop();
+                None
+            }
+            _ => {
+                if self.line.push(byte).is_err() {
+                    self.overflow = true;
+                }
+                None
+            }
+        }
+    }
+
+    fn parse(&self) -> Result<C, Error> {
+        let mut words = self
+            .line
+            .split(u8::is_ascii_whitespace)
+            .filter(|w| !w.is_empty());
+
+        let name = words.next().ok_or(Error::UnknownCommand)?;
+        let command = self
+            .table
+            .iter()
+            .find(|(n, _)| n.as_bytes() == name)
+            .map(|(_, c)| c)
+            .ok_or(Error::UnknownCommand)?;
+
+        let command = match command {
+            Command::Plain(c) => *c,
+            Command::Int(f) => f(int(words.next().ok_or(Error::MissingArgument)?)?),
+        };
+        match words.next() {
+            Some(_) => Err(Error::UnexpectedArgument),
+            None => Ok(command),
+        }
+    }
+}
+
+// a decimal number
+fn int(word: &[u8]) -> Result<u32, Error> {
+    word.iter().try_fold(0u32, |n, &d| {
+        if !d.is_ascii_digit() {
+            return Err(Error::InvalidNumber);
+        }
+        n.checked_mul(10)
+            .and_then(|n| n.checked_add((d - b'0') as u32))
+            .ok_or(Error::InvalidNumber)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the results of pushing `input`
+    fn run(input: &[u8]) -> std::vec::Vec<Result<Blink, Error>> {
+        let mut cli = Cli::new(BLINK);
+        input.iter().filter_map(|&b| cli.push(b)).collect()
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            run(b"set 1\rduty 20\ron\roff\rbreathe\r"),
+            [
+                Ok(Blink::Set(1)),
+                Ok(Blink::Duty(20)),
+                Ok(Blink::On),
+                Ok(Blink::Off),
+                Ok(Blink::Breathe)
+            ]
+        );
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(run(b"on\n"), [Ok(Blink::On)]);
+        assert_eq!(run(b"on\r\noff\r\n"), [Ok(Blink::On), Ok(Blink::Off)]);
+        // no result until the line ends
+        assert_eq!(run(b"on"), []);
+        assert_eq!(run(b"\r\n \r"), []);
+    }
+
+    #[test]
+    fn whitespace_and_backspace() {
+        assert_eq!(run(b"  set\t 42 \r"), [Ok(Blink::Set(42))]);
+        assert_eq!(run(b"of\x08n\r"), [Ok(Blink::On)]);
+        assert_eq!(run(b"\x7fon\r"), [Ok(Blink::On)]);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(run(b"set 0\r"), [Ok(Blink::Set(0))]);
+        assert_eq!(run(b"set 007\r"), [Ok(Blink::Set(7))]);
+        assert_eq!(run(b"set -1\r"), [Err(Error::InvalidNumber)]);
+        assert_eq!(run(b"set 1x\r"), [Err(Error::InvalidNumber)]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(run(b"blink\r"), [Err(Error::UnknownCommand)]);
+        assert_eq!(run(b"ON\r"), [Err(Error::UnknownCommand)]);
+        assert_eq!(run(b"set\r"), [Err(Error::MissingArgument)]);
+        assert_eq!(run(b"on 1\r"), [Err(Error::UnexpectedArgument)]);
+        assert_eq!(run(b"set 1 2\r"), [Err(Error::UnexpectedArgument)]);
+    }
+
+    #[test]
+    fn overflow() {
+        // 10 bytes fit
+        assert_eq!(run(b"set 123456\r"), [Ok(Blink::Set(123_456))]);
+        // 11 bytes do not, the whole line is discarded
+        assert_eq!(
+            run(b"set 1234567\ron\r"),
+            [Err(Error::Overflow), Ok(Blink::On)]
+        );
+        assert_eq!(run(b"set 4294967296\r"), [Err(Error::Overflow)]);
+        // backspaced to 9 bytes, still discarded
+        assert_eq!(run(b"set 1234567\x7f\x7f\r"), [Err(Error::Overflow)]);
+    }
+
+    #[test]
+    fn number_range() {
+        assert_eq!(int(b"4294967295"), Ok(u32::MAX));
+        assert_eq!(int(b"4294967296"), Err(Error::InvalidNumber));
+    }
+}
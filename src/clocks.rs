@@ -0,0 +1,711 @@
+//! Clock tree planner, for the RCC (RM0368 6.2)
+//!
+//! `bare7.rs` asks why `sysclk(64.mhz()).pclk1(64.mhz())` is wrong, and
+//! `bare6.rs` routes SYSCLK to MCO2 by setting the `CFGR` bits by hand. Here
+//! the wanted clocks are given by a `Request`, and `plan` computes the PLL
+//! (M, N, P, Q), the AHB/APB prescalers and the flash wait states, or tells
+//! which limit of the STM32F401 is broken:
+//!
+//! ``` ignore
+//! let plan = Request::hsi(84_000_000).pclk1(42_000_000).plan()?;
+//! plan.apply(&bus);
+//! clocks::mco(&bus, Mco::Mco2(Mco2::Sysclk), 4)?;
+//! ```
+//!
+//! Clocks not given run as fast as allowed. The limits (RM0368 3.4, 6.2,
+//! 6.3.2, and the STM32F401xD/E datasheet):
+//! - SYSCLK, HCLK and PCLK2 at most 84 MHz, PCLK1 (APB1) at most 42 MHz
+//! - HSE from 4 to 26 MHz
+//! - PLL, VCO input from 1 to 2 MHz, VCO output from 192 to 432 MHz,
+//!   N from 192 to 432, P one of 2, 4, 6, 8, Q from 2 to 15, and the Q output
+//!   (USB OTG FS, SDIO) at most 48 MHz
+//! - a flash wait state per 30 MHz of HCLK (at 2.7 to 3.6 V)
+
+use core::fmt;
+use core::mem::offset_of;
+
+use crate::bus::RegisterBus;
+use crate::regs::{
+    Flash, Gpio, Pwr, Rcc, ACR_DCEN, ACR_ICEN, ACR_LATENCY, ACR_PRFTEN, AHB1ENR_GPIOAEN,
+    AHB1ENR_GPIOCEN, APB1ENR_PWREN, BDCR_LSEON, BDCR_LSERDY, CFGR_HPRE, CFGR_MCO1, CFGR_MCO1PRE,
+    CFGR_MCO2, CFGR_MCO2PRE, CFGR_PPRE1, CFGR_PPRE2, CFGR_SW, CFGR_SWS, CR_HSEBYP, CR_HSEON,
+    CR_HSERDY, CR_PLLON, CR_PLLRDY, FLASH, GPIOA, GPIOC, MODER_ALTERNATE, OSPEEDR_VERY_HIGH,
+    PLLCFGR_PLLM, PLLCFGR_PLLN, PLLCFGR_PLLP, PLLCFGR_PLLQ, PLLCFGR_PLLSRC, PWR, PWR_CR_DBP, RCC,
+    SW_HSE, SW_HSI, SW_PLL,
+};
+
+#[rustfmt::skip]
+pub mod address {
+    use super::*;
+
+    pub const RCC_CR: u32           = RCC.reg(offset_of!(Rcc, CR));
+    pub const RCC_PLLCFGR: u32      = RCC.reg(offset_of!(Rcc, PLLCFGR));
+    pub const RCC_CFGR: u32         = RCC.reg(offset_of!(Rcc, CFGR));
+    pub const RCC_AHB1ENR: u32      = RCC.reg(offset_of!(Rcc, AHB1ENR));
+    pub const RCC_APB1ENR: u32      = RCC.reg(offset_of!(Rcc, APB1ENR));
+    pub const RCC_BDCR: u32         = RCC.reg(offset_of!(Rcc, BDCR));
+    pub const PWR_CR: u32           = PWR.reg(offset_of!(Pwr, CR));
+    pub const FLASH_ACR: u32        = FLASH.reg(offset_of!(Flash, ACR));
+}
+
+use address::*;
+
+/// HSI frequency
+pub const HSI_HZ: u32 = 16_000_000;
+/// LSE frequency (a 32.768 kHz crystal)
+pub const LSE_HZ: u32 = 32_768;
+
+pub const SYSCLK_MAX: u32 = 84_000_000;
+pub const PCLK1_MAX: u32 = 42_000_000;
+pub const PCLK2_MAX: u32 = 84_000_000;
+
+const HSE_MIN: u32 = 4_000_000;
+const HSE_MAX: u32 = 26_000_000;
+const VCO_IN_MIN: u32 = 1_000_000;
+const VCO_IN_MAX: u32 = 2_000_000;
+const VCO_OUT_MIN: u64 = 192_000_000;
+const VCO_OUT_MAX: u64 = 432_000_000;
+const PLLN_MIN: u64 = 192;
+const PLLN_MAX: u64 = 432;
+const PLLQ_MAX: u64 = 15;
+const USB_HZ: u64 = 48_000_000;
+// HCLK per flash wait state, 2.7 to 3.6 V, RM0368 3.4 Table 6
+const WAIT_STATE_HZ: u32 = 30_000_000;
+// polls of LSERDY before giving up, at least the 2 s start up time of the LSE
+// at SYSCLK up to 84 MHz (a poll takes more than 4 cycles)
+const LSE_POLL_LIMIT: u32 = 40_000_000;
+
+// (divisor, HPRE)
+#[rustfmt::skip]
+const AHB_PRESCALERS: [(u32, u32); 9] = [
+    (1, 0b0000), (2, 0b1000), (4, 0b1001), (8, 0b1010), (16, 0b1011),
+    (64, 0b1100), (128, 0b1101), (256, 0b1110), (512, 0b1111),
+];
+// (divisor, PPRE1/PPRE2)
+#[rustfmt::skip]
+const APB_PRESCALERS: [(u32, u32); 5] = [
+    (1, 0b000), (2, 0b100), (4, 0b101), (8, 0b110), (16, 0b111),
+];
+
+/// Clock source of SYSCLK (or of the PLL)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// the internal 16 MHz RC oscillator
+    Hsi,
+    /// an external crystal of `hz`, or an external clock if `bypass` (e.g.,
+    /// the 8 MHz of the ST-LINK on a Nucleo)
+    Hse { hz: u32, bypass: bool },
+}
+
+impl Source {
+    pub fn hz(self) -> u32 {
+        match self {
+            Source::Hsi => HSI_HZ,
+            Source::Hse { hz, .. } => hz,
+        }
+    }
+}
+
+/// The limit broken by a `Request`, or a clock not starting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// HSE out of 4 to 26 MHz
+    Hse,
+    /// SYSCLK above 84 MHz, or not reachable by the PLL
+    Sysclk,
+    /// HCLK above 84 MHz, or not SYSCLK divided by an AHB prescaler
+    Hclk,
+    /// PCLK1 above 42 MHz, or not HCLK divided by an APB prescaler
+    Pclk1,
+    /// PCLK2 above 84 MHz, or not HCLK divided by an APB prescaler
+    Pclk2,
+    /// no PLL giving both SYSCLK and 48 MHz
+    Usb,
+    /// MCO prescaler out of 1 to 5
+    McoPrescaler,
+    /// LSE not ready in time (no crystal)
+    Lse,
+}
+
+/// The wanted clocks, in Hz (`None` for as fast as allowed)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request {
+    pub source: Source,
+    pub sysclk: u32,
+    pub hclk: Option<u32>,
+    pub pclk1: Option<u32>,
+    pub pclk2: Option<u32>,
+    /// 48 MHz on the PLL Q output (USB OTG FS, SDIO)
+    pub usb: bool,
+}
+
+impl Request {
+    pub const fn new(source: Source, sysclk: u32) -> Self {
+        Request {
+            source,
+            sysclk,
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            usb: false,
+        }
+    }
+
+    /// `sysclk` from the HSI
+    pub const fn hsi(sysclk: u32) -> Self {
+        Self::new(Source::Hsi, sysclk)
+    }
+
+    /// `sysclk` from a crystal of `hz`
+    pub const fn hse(hz: u32, sysclk: u32) -> Self {
+        Self::new(Source::Hse { hz, bypass: false }, sysclk)
+    }
+
+    /// `sysclk` from an external clock of `hz`
+    pub const fn hse_bypass(hz: u32, sysclk: u32) -> Self {
+        Self::new(Source::Hse { hz, bypass: true }, sysclk)
+    }
+
+    pub const fn hclk(self, hz: u32) -> Self {
+        Request {
+            hclk: Some(hz),
+            ..self
+        }
+    }
+
+    pub const fn pclk1(self, hz: u32) -> Self {
+        Request {
+            pclk1: Some(hz),
+            ..self
+        }
+    }
+
+    pub const fn pclk2(self, hz: u32) -> Self {
+        Request {
+            pclk2: Some(hz),
+            ..self
+        }
+    }
+
+    /// 48 MHz on the PLL Q output
+    pub const fn usb(self) -> Self {
+        Request { usb: true, ..self }
+    }
+
+    pub fn plan(&self) -> Result<Plan, Error> {
+        plan(self)
+    }
+}
+
+/// Main PLL dividers, SYSCLK = source / `m` * `n` / `p`, RM0368 6.3.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pll {
+    pub m: u8,
+    pub n: u16,
+    pub p: u8,
+    pub q: u8,
+}
+
+impl Pll {
+    /// VCO output, from a source of `src` Hz
+    pub fn vco_hz(self, src: u32) -> u32 {
+        (src as u64 * self.n as u64 / self.m as u64) as u32
+    }
+
+    /// P output (SYSCLK)
+    pub fn p_hz(self, src: u32) -> u32 {
+        self.vco_hz(src) / self.p as u32
+    }
+
+    /// Q output (USB OTG FS, SDIO)
+    pub fn q_hz(self, src: u32) -> u32 {
+        self.vco_hz(src) / self.q as u32
+    }
+}
+
+/// A clock configuration within the limits, from `plan`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plan {
+    pub source: Source,
+    /// `None` for SYSCLK from the source
+    pub pll: Option<Pll>,
+    pub sysclk: u32,
+    pub hclk: u32,
+    pub pclk1: u32,
+    pub pclk2: u32,
+    /// AHB prescaler (SYSCLK / HCLK)
+    pub hpre: u32,
+    /// APB1 prescaler (HCLK / PCLK1)
+    pub ppre1: u32,
+    /// APB2 prescaler (HCLK / PCLK2)
+    pub ppre2: u32,
+    /// flash wait states
+    pub latency: u8,
+}
+
+/// The configuration meeting `req`
+pub fn plan(req: &Request) -> Result<Plan, Error> {
+    let src = req.source.hz();
+    if let Source::Hse { hz, .. } = req.source {
+        if !(HSE_MIN..=HSE_MAX).contains(&hz) {
+            return Err(Error::Hse);
+        }
+    }
+    if req.sysclk == 0 || req.sysclk > SYSCLK_MAX {
+        return Err(Error::Sysclk);
+    }
+
+    let pll = if req.sysclk == src && !req.usb {
+        None
+    } else {
+        Some(pll(src, req.sysclk, req.usb)?)
+    };
+
+    let hpre = prescaler(
+        req.sysclk,
+        req.hclk,
+        SYSCLK_MAX,
+        &AHB_PRESCALERS,
+        Error::Hclk,
+    )?;
+    let hclk = req.sysclk / hpre;
+    let ppre1 = prescaler(hclk, req.pclk1, PCLK1_MAX, &APB_PRESCALERS, Error::Pclk1)?;
+    let ppre2 = prescaler(hclk, req.pclk2, PCLK2_MAX, &APB_PRESCALERS, Error::Pclk2)?;
+
+    Ok(Plan {
+        source: req.source,
+        pll,
+        sysclk: req.sysclk,
+        hclk,
+        pclk1: hclk / ppre1,
+        pclk2: hclk / ppre2,
+        hpre,
+        ppre1,
+        ppre2,
+        latency: ((hclk - 1) / WAIT_STATE_HZ) as u8,
+    })
+}
+
+// the divisor giving `to` (exactly) from `from`, or the smallest one within
+// `max`
+fn prescaler(
+    from: u32,
+    to: Option<u32>,
+    max: u32,
+    prescalers: &[(u32, u32)],
+    err: Error,
+) -> Result<u32, Error> {
+    let found = match to {
+        Some(hz) if hz > max => None,
+        Some(hz) => prescalers
+            .iter()
+            .find(|&&(div, _)| from % div == 0 && from / div == hz),
+        None => prescalers.iter().find(|&&(div, _)| from / div <= max),
+    };
+    found.map(|&(div, _)| div).ok_or(err)
+}
+
+// the PLL giving `sysclk` from `src`, with the lowest VCO output (less power)
+// and the highest VCO input (less jitter), 48 MHz on Q if `usb`
+fn pll(src: u32, sysclk: u32, usb: bool) -> Result<Pll, Error> {
+    let mut found = false;
+    for p in [2, 4, 6, 8] {
+        let vco = sysclk as u64 * p;
+        if !(VCO_OUT_MIN..=VCO_OUT_MAX).contains(&vco) {
+            continue;
+        }
+        for m in 2..=PLLCFGR_PLLM.max() {
+            if src > m * VCO_IN_MAX {
+                continue;
+            }
+            if src < m * VCO_IN_MIN {
+                break;
+            }
+            if vco * m as u64 % src as u64 != 0 {
+                continue;
+            }
+            let n = vco * m as u64 / src as u64;
+            if !(PLLN_MIN..=PLLN_MAX).contains(&n) {
+                continue;
+            }
+            // the Q output is at most 48 MHz
+            let q = vco.div_ceil(USB_HZ).max(2);
+            if q > PLLQ_MAX {
+                continue;
+            }
+            found = true;
+            if !usb || vco == q * USB_HZ {
+                return Ok(Pll {
+                    m: m as u8,
+                    n: n as u16,
+                    p: p as u8,
+                    q: q as u8,
+                });
+            }
+        }
+    }
+    Err(if found { Error::Usb } else { Error::Sysclk })
+}
+
+// the register value of `div`
+fn bits(prescalers: &[(u32, u32)], div: u32) -> u32 {
+    prescalers
+        .iter()
+        .find(|&&(d, _)| d == div)
+        .map_or(0, |&(_, bits)| bits)
+}
+
+impl Plan {
+    /// Clock of the timers on APB1 (TIM2..5), twice PCLK1 if divided
+    pub fn timclk1(&self) -> u32 {
+        timclk(self.pclk1, self.ppre1)
+    }
+
+    /// Clock of the timers on APB2 (TIM1, TIM9..11), twice PCLK2 if divided
+    pub fn timclk2(&self) -> u32 {
+        timclk(self.pclk2, self.ppre2)
+    }
+
+    /// PLL Q output (48 MHz for USB), if the PLL is used
+    pub fn usb(&self) -> Option<u32> {
+        self.pll.map(|pll| pll.q_hz(self.source.hz()))
+    }
+
+    /// `RCC_PLLCFGR` value (0 if the PLL is not used)
+    pub fn pllcfgr(&self) -> u32 {
+        let pll = match self.pll {
+            Some(pll) => pll,
+            None => return 0,
+        };
+        let hse = matches!(self.source, Source::Hse { .. });
+        PLLCFGR_PLLM.val(pll.m as u32).bits()
+            | PLLCFGR_PLLN.val(pll.n as u32).bits()
+            | PLLCFGR_PLLP.val(pll.p as u32 / 2 - 1).bits()
+            | PLLCFGR_PLLSRC.val(hse as u32).bits()
+            | PLLCFGR_PLLQ.val(pll.q as u32).bits()
+    }
+
+    /// `RCC_CFGR` value, the system clock switch and prescalers
+    pub fn cfgr(&self) -> u32 {
+        CFGR_SW.val(self.sw()).bits()
+            | CFGR_HPRE.val(bits(&AHB_PRESCALERS, self.hpre)).bits()
+            | CFGR_PPRE1.val(bits(&APB_PRESCALERS, self.ppre1)).bits()
+            | CFGR_PPRE2.val(bits(&APB_PRESCALERS, self.ppre2)).bits()
+    }
+
+    /// `FLASH_ACR` value, the wait states with the caches and prefetch on
+    pub fn acr(&self) -> u32 {
+        ACR_LATENCY.val(self.latency as u32).bits()
+            | ACR_PRFTEN.mask()
+            | ACR_ICEN.mask()
+            | ACR_DCEN.mask()
+    }
+
+    /// Frequency of `mco` divided by `div` (`None` if not known)
+    pub fn mco_hz(&self, mco: Mco, div: u8) -> Option<u32> {
+        mco_pre(div).ok()?;
+        let hse = match self.source {
+            Source::Hse { hz, .. } => Some(hz),
+            Source::Hsi => None,
+        };
+        let pll = self.pll.map(|pll| pll.p_hz(self.source.hz()));
+        let hz = match mco {
+            Mco::Mco1(Mco1::Hsi) => Some(HSI_HZ),
+            Mco::Mco1(Mco1::Lse) => Some(LSE_HZ),
+            Mco::Mco1(Mco1::Hse) | Mco::Mco2(Mco2::Hse) => hse,
+            Mco::Mco1(Mco1::Pll) | Mco::Mco2(Mco2::Pll) => pll,
+            Mco::Mco2(Mco2::Sysclk) => Some(self.sysclk),
+            Mco::Mco2(Mco2::Plli2s) => None,
+        };
+        hz.map(|hz| hz / div as u32)
+    }
+
+    /// Switches to the planned clocks, from the reset state (HSI, no wait
+    /// states)
+    pub fn apply<B: RegisterBus>(&self, bus: &B) {
+        if let Source::Hse { bypass, .. } = self.source {
+            // HSEBYP is written while the HSE is off
+            if bypass {
+                bus.modify_u32(RCC_CR, 0, CR_HSEBYP.mask());
+            }
+            bus.modify_u32(RCC_CR, 0, CR_HSEON.mask());
+            while bus.read_u32(RCC_CR) & CR_HSERDY.mask() == 0 {}
+        }
+
+        // the wait states before raising the clock
+        bus.write_u32(FLASH_ACR, self.acr());
+
+        if self.pll.is_some() {
+            bus.write_u32(RCC_PLLCFGR, self.pllcfgr());
+            bus.modify_u32(RCC_CR, 0, CR_PLLON.mask());
+            while bus.read_u32(RCC_CR) & CR_PLLRDY.mask() == 0 {}
+        }
+
+        // the prescalers, then the switch
+        let prescalers = CFGR_HPRE.mask() | CFGR_PPRE1.mask() | CFGR_PPRE2.mask();
+        bus.modify_u32(RCC_CFGR, prescalers, self.cfgr() & prescalers);
+        bus.modify_u32(RCC_CFGR, CFGR_SW.mask(), self.cfgr() & CFGR_SW.mask());
+        while (bus.read_u32(RCC_CFGR) & CFGR_SWS.mask()) >> CFGR_SWS.offset() != self.sw() {}
+    }
+
+    fn sw(&self) -> u32 {
+        match (self.pll, self.source) {
+            (Some(_), _) => SW_PLL,
+            (None, Source::Hsi) => SW_HSI,
+            (None, Source::Hse { .. }) => SW_HSE,
+        }
+    }
+}
+
+fn timclk(pclk: u32, ppre: u32) -> u32 {
+    if ppre == 1 {
+        pclk
+    } else {
+        2 * pclk
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SYSCLK {} Hz, HCLK {} Hz, PCLK1 {} Hz, PCLK2 {} Hz, {} wait states",
+            self.sysclk, self.hclk, self.pclk1, self.pclk2, self.latency
+        )?;
+        if let Some(pll) = self.pll {
+            write!(f, ", PLL M {} N {} P {} Q {}", pll.m, pll.n, pll.p, pll.q)?;
+        }
+        Ok(())
+    }
+}
+
+/// MCO1 source, output on PA8
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mco1 {
+    Hsi = 0b00,
+    Lse = 0b01,
+    Hse = 0b10,
+    Pll = 0b11,
+}
+
+/// MCO2 source, output on PC9
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mco2 {
+    Sysclk = 0b00,
+    Plli2s = 0b01,
+    Hse = 0b10,
+    Pll = 0b11,
+}
+
+/// A microcontroller clock output, RM0368 6.2.10
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mco {
+    Mco1(Mco1),
+    Mco2(Mco2),
+}
+
+// MCOxPRE value dividing by `div`
+fn mco_pre(div: u8) -> Result<u32, Error> {
+    match div {
+        1 => Ok(0b000),
+        2..=5 => Ok(0b100 | (div as u32 - 2)),
+        _ => Err(Error::McoPrescaler),
+    }
+}
+
+/// Routes `mco`, divided by `div` (1 to 5), to its pin (PA8 or PC9, in
+/// alternate function 0, at very high speed)
+pub fn mco<B: RegisterBus>(bus: &B, mco: Mco, div: u8) -> Result<(), Error> {
+    let pre = mco_pre(div)?;
+    let (source, prescaler, value, gpio, enable, pin) = match mco {
+        Mco::Mco1(s) => (CFGR_MCO1, CFGR_MCO1PRE, s as u32, GPIOA, AHB1ENR_GPIOAEN, 8),
+        Mco::Mco2(s) => (CFGR_MCO2, CFGR_MCO2PRE, s as u32, GPIOC, AHB1ENR_GPIOCEN, 9),
+    };
+
+    bus.modify_u32(
+        RCC_CFGR,
+        source.mask() | prescaler.mask(),
+        source.val(value).bits() | prescaler.val(pre).bits(),
+    );
+
+    bus.modify_u32(RCC_AHB1ENR, 0, enable.mask());
+    // AF0 (pins 8 and 9 are in AFRH)
+    bus.modify_u32(gpio.reg(offset_of!(Gpio, AFRH)), Gpio::afr(pin).mask(), 0);
+    let field = Gpio::moder(pin);
+    bus.modify_u32(
+        gpio.reg(offset_of!(Gpio, OSPEEDR)),
+        field.mask(),
+        field.val(OSPEEDR_VERY_HIGH).bits(),
+    );
+    bus.modify_u32(
+        gpio.reg(offset_of!(Gpio, MODER)),
+        field.mask(),
+        field.val(MODER_ALTERNATE).bits(),
+    );
+    Ok(())
+}
+
+/// Starts the LSE (the 32.768 kHz crystal, X2 on the Nucleo), waiting until
+/// it is ready (up to 2 s), `Error::Lse` if it does not start
+///
+/// The LSE is in the backup domain, written once the PWR allows it (`DBP`).
+pub fn lse_on<B: RegisterBus>(bus: &B) -> Result<(), Error> {
+    bus.modify_u32(RCC_APB1ENR, 0, APB1ENR_PWREN.mask());
+    bus.modify_u32(PWR_CR, 0, PWR_CR_DBP.mask());
+    bus.modify_u32(RCC_BDCR, 0, BDCR_LSEON.mask());
+    if (0..LSE_POLL_LIMIT).any(|_| bus.read_u32(RCC_BDCR) & BDCR_LSERDY.mask() != 0) {
+        Ok(())
+    } else {
+        Err(Error::Lse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Access::*, MemoryBus};
+
+    const MHZ: u32 = 1_000_000;
+
+    const fn pll(m: u8, n: u16, p: u8, q: u8) -> Option<Pll> {
+        Some(Pll { m, n, p, q })
+    }
+
+    // request, PLL (M, N, P, Q), SYSCLK/HCLK/PCLK1/PCLK2 (MHz), wait states
+    #[rustfmt::skip]
+    const KNOWN_GOOD: &[(Request, Option<Pll>, [u32; 4], u8)] = &[
+        // reset state
+        (Request::hsi(16 * MHZ),                    None,                 [16, 16, 16, 16], 0),
+        (Request::hse(8 * MHZ, 8 * MHZ),            None,                 [8, 8, 8, 8], 0),
+        // `bare7.rs`, the full speed of the F401
+        (Request::hsi(84 * MHZ).pclk1(42 * MHZ).pclk2(84 * MHZ),
+                                                    pll(10, 210, 4, 7),  [84, 84, 42, 84], 2),
+        (Request::hsi(84 * MHZ).usb(),              pll(10, 210, 4, 7),  [84, 84, 42, 84], 2),
+        (Request::hse(8 * MHZ, 84 * MHZ),           pll(5, 210, 4, 7),   [84, 84, 42, 84], 2),
+        (Request::hse(25 * MHZ, 84 * MHZ),          pll(25, 336, 4, 7),  [84, 84, 42, 84], 2),
+        (Request::hsi(84 * MHZ).hclk(42 * MHZ).pclk1(21 * MHZ),
+                                                    pll(10, 210, 4, 7),  [84, 42, 21, 42], 1),
+        // `bare6.rs`
+        (Request::hsi(64 * MHZ).pclk1(32 * MHZ),    pll(12, 192, 4, 6),  [64, 64, 32, 64], 2),
+        (Request::hsi(64 * MHZ).usb(),              pll(8, 192, 6, 8),   [64, 64, 32, 64], 2),
+        // the ST-LINK clock of a Nucleo
+        (Request::hse_bypass(8 * MHZ, 48 * MHZ).usb(),
+                                                    pll(8, 192, 4, 4),   [48, 48, 24, 48], 1),
+        (Request::hsi(50 * MHZ),                    pll(16, 200, 4, 5),  [50, 50, 25, 50], 1),
+    ];
+
+    #[test]
+    fn known_good() {
+        for (req, pll, clocks, latency) in KNOWN_GOOD {
+            let plan = req.plan().unwrap();
+            assert_eq!(plan.pll, *pll, "{:?}", req);
+            assert_eq!(
+                [plan.sysclk, plan.hclk, plan.pclk1, plan.pclk2],
+                clocks.map(|mhz| mhz * MHZ),
+                "{:?}",
+                req
+            );
+            assert_eq!(plan.latency, *latency, "{:?}", req);
+
+            // within the limits
+            let src = req.source.hz();
+            if let Some(pll) = plan.pll {
+                assert_eq!(pll.p_hz(src), plan.sysclk);
+                assert!((MHZ..=2 * MHZ).contains(&(src / pll.m as u32)));
+                assert!((192 * MHZ..=432 * MHZ).contains(&pll.vco_hz(src)));
+                assert!(pll.q_hz(src) <= 48 * MHZ);
+            }
+            if req.usb {
+                assert_eq!(plan.usb(), Some(48 * MHZ));
+            }
+            assert!(plan.pclk1 <= PCLK1_MAX);
+        }
+    }
+
+    #[test]
+    fn broken_limits() {
+        #[rustfmt::skip]
+        let broken = [
+            // `bare7.rs`, APB1 runs at most at 42 MHz
+            (Request::hsi(64 * MHZ).pclk1(64 * MHZ),                    Error::Pclk1),
+            // 84 MHz is not divided down to 64 MHz
+            (Request::hsi(84 * MHZ).pclk1(42 * MHZ).pclk2(64 * MHZ),    Error::Pclk2),
+            (Request::hsi(84 * MHZ).hclk(50 * MHZ),                     Error::Hclk),
+            (Request::hsi(100 * MHZ),                                   Error::Sysclk),
+            (Request::hsi(84 * MHZ - 1),                                Error::Sysclk),
+            (Request::hse(30 * MHZ, 84 * MHZ),                          Error::Hse),
+            (Request::hsi(50 * MHZ).usb(),                              Error::Usb),
+        ];
+        for (req, err) in broken.iter() {
+            assert_eq!(req.plan(), Err(*err), "{:?}", req);
+        }
+    }
+
+    #[test]
+    fn timer_clocks() {
+        let plan = Request::hsi(84 * MHZ).plan().unwrap();
+        // APB1 divided by 2, the timers run at twice PCLK1
+        assert_eq!(plan.timclk1(), 84 * MHZ);
+        assert_eq!(plan.timclk2(), 84 * MHZ);
+
+        let plan = Request::hsi(16 * MHZ).plan().unwrap();
+        assert_eq!(plan.timclk1(), 16 * MHZ);
+        assert_eq!(
+            plan.to_string(),
+            "SYSCLK 16000000 Hz, HCLK 16000000 Hz, PCLK1 16000000 Hz, PCLK2 16000000 Hz, \
+             0 wait states"
+        );
+    }
+
+    #[test]
+    fn apply() {
+        let bus = MemoryBus::new();
+        // the clocks are ready, SYSCLK switched to the PLL
+        bus.preset(RCC_CR, CR_HSERDY.mask() | CR_PLLRDY.mask());
+        bus.preset(RCC_CFGR, CFGR_SWS.val(SW_PLL).bits());
+
+        let plan = Request::hse_bypass(8 * MHZ, 48 * MHZ).usb().plan().unwrap();
+        plan.apply(&bus);
+
+        assert_eq!(bus.peek(FLASH_ACR), 0x701);
+        assert_eq!(bus.peek(RCC_PLLCFGR), 0x0441_3008);
+        assert_eq!(bus.peek(RCC_CR), 0x0307_0000);
+        // APB1 by 2, SYSCLK from the PLL
+        assert_eq!(bus.peek(RCC_CFGR), 0x100A);
+
+        // the wait states are set before the PLL is on, the switch is last
+        let accesses = bus.accesses();
+        let position = |access| accesses.iter().position(|a| *a == access).unwrap();
+        assert!(position(Write(FLASH_ACR, 0x701)) < position(Write(RCC_PLLCFGR, 0x0441_3008)));
+        assert_eq!(accesses[accesses.len() - 2], Write(RCC_CFGR, 0x100A));
+    }
+
+    #[test]
+    fn mco2_sysclk() {
+        let bus = MemoryBus::new();
+        mco(&bus, Mco::Mco2(Mco2::Sysclk), 4).unwrap();
+
+        // as `clock_out` of `bare6.rs`
+        assert_eq!(bus.peek(RCC_CFGR), 0b110 << 27);
+        assert_eq!(bus.peek(RCC_AHB1ENR), 1 << 2);
+        assert_eq!(bus.peek(GPIOC.reg(offset_of!(Gpio, MODER))), 0b10 << 18);
+        assert_eq!(bus.peek(GPIOC.reg(offset_of!(Gpio, OSPEEDR))), 0b11 << 18);
+
+        let plan = Request::hsi(84 * MHZ).plan().unwrap();
+        assert_eq!(plan.mco_hz(Mco::Mco2(Mco2::Sysclk), 4), Some(21 * MHZ));
+        assert_eq!(plan.mco_hz(Mco::Mco1(Mco1::Hse), 1), None);
+        assert_eq!(mco(&bus, Mco::Mco1(Mco1::Hsi), 6), Err(Error::McoPrescaler));
+    }
+
+    #[test]
+    fn lse() {
+        let bus = MemoryBus::new();
+        bus.preset(RCC_BDCR, BDCR_LSERDY.mask());
+        assert_eq!(lse_on(&bus), Ok(()));
+        assert_eq!(bus.peek(RCC_APB1ENR), 1 << 28);
+        assert_eq!(bus.peek(PWR_CR), 1 << 8);
+        assert_eq!(bus.peek(RCC_BDCR), 0b11);
+        // backup domain access, before the LSE is turned on
+        assert_eq!(bus.accesses()[3], Write(PWR_CR, 1 << 8));
+    }
+}
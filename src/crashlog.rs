@@ -0,0 +1,393 @@
+//! Crash log, kept in `.noinit` RAM over a reset
+//!
+//! A panic (or HardFault) handler records the crash (the panic message, or the
+//! fault registers, and a snapshot of the stack) before resetting the MCU
+//! (`SCB::sys_reset`), and the next boot reports it. The log also counts the
+//! boots.
+//!
+//! The log is placed in the `.noinit` section (`noinit` feature, see
+//! `build.rs`), which the startup code leaves as is. After a power-on its
+//! content is random, so the log holds a magic word and a CRC-32 of its
+//! content, and is reset if either does not match.
+//!
+//! ``` ignore
+//! #[link_section = ".noinit"]
+//! static mut LOG: MaybeUninit<CrashLog> = MaybeUninit::uninit();
+//!
+//! let boot = log().boot();
+//! if let Some(crash) = boot.crash {
+//!     iprintln!(stim, "{}", crash);
+//! }
+//! ```
+//!
+//! Any bit pattern is a `CrashLog` (all fields are integers).
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+use crate::fault::{Registers, Report, Stacked};
+
+/// Max number of bytes of the panic message
+pub const MESSAGE_SIZE: usize = 128;
+/// Max number of words of the stack snapshot
+pub const STACK_WORDS: usize = 32;
+
+/// "CRSH"
+const MAGIC: u32 = 0x4352_5348;
+
+/// A recorded crash, a panic or a fault
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Crash {
+    /// zero for a panic
+    pub fault: Registers,
+    pub stacked: Stacked,
+    message_len: u32,
+    message: [u8; MESSAGE_SIZE],
+    stack_len: u32,
+    stack: [u32; STACK_WORDS],
+}
+
+impl Crash {
+    const fn new() -> Self {
+        Crash {
+            fault: Registers {
+                cfsr: 0,
+                hfsr: 0,
+                mmfar: 0,
+                bfar: 0,
+            },
+            stacked: Stacked {
+                pc: 0,
+                lr: 0,
+                xpsr: 0,
+            },
+            message_len: 0,
+            message: [0; MESSAGE_SIZE],
+            stack_len: 0,
+            stack: [0; STACK_WORDS],
+        }
+    }
+
+    pub fn is_fault(&self) -> bool {
+        self.fault != Registers::default()
+    }
+
+    /// The panic message (truncated to `MESSAGE_SIZE` bytes)
+    pub fn message(&self) -> &str {
+        let len = (self.message_len as usize).min(MESSAGE_SIZE);
+        // the message may be cut in the middle of a character
+        match core::str::from_utf8(&self.message[..len]) {
+            Ok(s) => s,
+            Err(e) => core::str::from_utf8(&self.message[..e.valid_up_to()]).unwrap(),
+        }
+    }
+
+    /// The stack snapshot, from the stack pointer up
+    pub fn stack(&self) -> &[u32] {
+        &self.stack[..(self.stack_len as usize).min(STACK_WORDS)]
+    }
+
+    fn set_stack(&mut self, stack: &[u32]) {
+        let len = stack.len().min(STACK_WORDS);
+        self.stack[..len].copy_from_slice(&stack[..len]);
+        self.stack_len = len as u32;
+    }
+}
+
+impl fmt::Debug for Crash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Crash")
+            .field("fault", &self.fault)
+            .field("stacked", &self.stacked)
+            .field("message", &self.message())
+            .field("stack", &self.stack())
+            .finish()
+    }
+}
+
+impl fmt::Display for Crash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_fault() {
+            writeln!(f, "{}", Report::new(self.fault, self.stacked))?;
+        } else {
+            writeln!(f, "{}", self.message())?;
+        }
+        write!(f, "stack")?;
+        for (i, word) in self.stack().iter().enumerate() {
+            if i % 4 == 0 {
+                write!(f, "\n ")?;
+            }
+            write!(f, " 0x{:08x}", word)?;
+        }
+        Ok(())
+    }
+}
+
+// appends to the message, cut when full
+impl Write for Crash {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.message_len as usize;
+        let len = s.len().min(MESSAGE_SIZE - start);
+        self.message[start..start + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.message_len += len as u32;
+        Ok(())
+    }
+}
+
+/// Reported by `CrashLog::boot`
+#[derive(Debug, Clone, Copy)]
+pub struct Boot {
+    /// number of boots since the log was reset (1 for the first)
+    pub count: u32,
+    /// crash of the previous run, if any
+    pub crash: Option<Crash>,
+}
+
+#[repr(C)]
+pub struct CrashLog {
+    magic: u32,
+    boots: u32,
+    // a crash is recorded, not yet reported
+    crashed: u32,
+    crash: Crash,
+    crc: u32,
+}
+
+impl CrashLog {
+    /// An empty log
+    pub const fn new() -> Self {
+        CrashLog {
+            magic: MAGIC,
+            boots: 0,
+            crashed: 0,
+            crash: Crash::new(),
+            crc: 0,
+        }
+    }
+
+    /// The magic word and CRC match
+    pub fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.crc == self.checksum()
+    }
+
+    /// To be called once at boot, counts the boot and takes the recorded
+    /// crash (if any). An invalid log is reset.
+    pub fn boot(&mut self) -> Boot {
+        self.validate();
+        self.boots = self.boots.wrapping_add(1);
+        let crash = if self.crashed != 0 {
+            Some(self.crash)
+        } else {
+            None
+        };
+        self.crashed = 0;
+        self.seal();
+        Boot {
+            count: self.boots,
+            crash,
+        }
+    }
+
+    /// Records a panic, from the panic handler
+    pub fn record_panic(&mut self, info: &PanicInfo, stack: &[u32]) {
+        self.record(stack, |crash| {
+            let _ = write!(crash, "{}", info);
+        });
+    }
+
+    /// Records a fault, from the HardFault handler
+    pub fn record_fault(&mut self, report: &Report, stack: &[u32]) {
+        self.record(stack, |crash| {
+            crash.fault = report.regs;
+            crash.stacked = report.stacked;
+        });
+    }
+
+    // the first crash is kept, a later one is likely a consequence of it
+    fn record(&mut self, stack: &[u32], f: impl FnOnce(&mut Crash)) {
+        self.validate();
+        if self.crashed != 0 {
+            return;
+        }
+        self.crash = Crash::new();
+        f(&mut self.crash);
+        self.crash.set_stack(stack);
+        self.crashed = 1;
+        self.seal();
+    }
+
+    fn validate(&mut self) {
+        if !self.is_valid() {
+            *self = CrashLog::new();
+        }
+    }
+
+    fn seal(&mut self) {
+        self.crc = self.checksum();
+    }
+
+    fn checksum(&self) -> u32 {
+        let c = &self.crash;
+        let mut crc = Crc::new();
+        for &word in &[
+            self.magic,
+            self.boots,
+            self.crashed,
+            c.fault.cfsr,
+            c.fault.hfsr,
+            c.fault.mmfar,
+            c.fault.bfar,
+            c.stacked.pc,
+            c.stacked.lr,
+            c.stacked.xpsr,
+            c.message_len,
+            c.stack_len,
+        ] {
+            crc.word(word);
+        }
+        crc.bytes(&c.message);
+        for &word in c.stack.iter() {
+            crc.word(word);
+        }
+        crc.finish()
+    }
+}
+
+impl Default for CrashLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CRC-32 (IEEE 802.3, as `crc32` of zlib), bitwise
+struct Crc(u32);
+
+impl Crc {
+    fn new() -> Self {
+        Crc(!0)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    // little endian, as in memory
+    fn word(&mut self, word: u32) {
+        self.bytes(&word.to_le_bytes());
+    }
+
+    fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // random content, as after a power-on
+    fn garbage() -> CrashLog {
+        let mut log = CrashLog::new();
+        log.magic = 0xDEAD_BEEF;
+        log.boots = 1234;
+        log.crashed = 1;
+        log
+    }
+
+    fn fault() -> Report {
+        Report::new(
+            Registers {
+                cfsr: 1 << 15 | 1 << 9,
+                hfsr: 1 << 30,
+                mmfar: 0,
+                bfar: 0x2FFF_FFFF,
+            },
+            Stacked {
+                pc: 0x0800_0524,
+                lr: 0x0800_0519,
+                xpsr: 0x6100_0000,
+            },
+        )
+    }
+
+    #[test]
+    fn crc32() {
+        let mut crc = Crc::new();
+        crc.bytes(b"123456789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn power_on() {
+        let mut log = garbage();
+        assert!(!log.is_valid());
+
+        let boot = log.boot();
+        assert_eq!(boot.count, 1);
+        assert!(boot.crash.is_none());
+        assert!(log.is_valid());
+        assert_eq!(log.boot().count, 2);
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut log = CrashLog::new();
+        log.boot();
+        log.record_fault(&fault(), &[1, 2, 3]);
+
+        // a single bit flipped
+        log.crash.stack[1] ^= 1 << 7;
+        assert!(!log.is_valid());
+        let boot = log.boot();
+        assert_eq!(boot.count, 1);
+        assert!(boot.crash.is_none());
+    }
+
+    #[test]
+    fn fault_reported_once() {
+        let mut log = CrashLog::new();
+        log.boot();
+        log.record_fault(&fault(), &[0x2000_0000, 0x0800_0519]);
+
+        let crash = log.boot().crash.unwrap();
+        assert!(crash.is_fault());
+        assert_eq!(crash.fault, fault().regs);
+        assert_eq!(crash.stack(), &[0x2000_0000, 0x0800_0519]);
+        assert_eq!(
+            crash.to_string(),
+            format!("{}\nstack\n  0x20000000 0x08000519", fault())
+        );
+
+        assert!(log.boot().crash.is_none());
+    }
+
+    #[test]
+    fn first_crash_kept() {
+        let mut log = CrashLog::new();
+        log.record_fault(&fault(), &[]);
+        log.record_fault(&Report::new(Registers::default(), Stacked::default()), &[1]);
+        let crash = log.boot().crash.unwrap();
+        assert_eq!(crash.fault, fault().regs);
+        assert!(crash.stack().is_empty());
+    }
+
+    #[test]
+    fn message_truncated() {
+        let mut crash = Crash::new();
+        let _ = write!(crash, "{}é", "x".repeat(MESSAGE_SIZE - 1));
+        assert_eq!(crash.message_len as usize, MESSAGE_SIZE);
+        // the cut character is left out
+        assert_eq!(crash.message(), "x".repeat(MESSAGE_SIZE - 1));
+        assert!(!crash.is_fault());
+
+        crash.set_stack(&[0; STACK_WORDS + 1]);
+        assert_eq!(crash.stack().len(), STACK_WORDS);
+    }
+}
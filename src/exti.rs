@@ -0,0 +1,442 @@
+//! External interrupts (EXTI) from GPIO pins, RM0368 10
+//!
+//! `device.rs` pends `EXTI0` from software. Here a pin raises it: pin `n` of
+//! any port is routed to EXTI line `n` by SYSCFG (RM0368 7.2.3), one port per
+//! line, and the line triggers on the rising, falling or both edges.
+//!
+//! Lines 0 to 4 have a vector each, lines 5 to 9 share `EXTI9_5`, and lines
+//! 10 to 15 share `EXTI15_10` (`Vector`), so the handler of a shared vector
+//! asks which of its lines are pending:
+//!
+//! ``` ignore
+//! exti.listen(PC13, Edge::Falling);
+//!
+//! #[task(binds = EXTI15_10, resources = [EXTI])]
+//! fn exti15_10(cx: exti15_10::Context) {
+//!     for line in cx.resources.EXTI.take_pending(Vector::Exti15_10) { .. }
+//! }
+//! ```
+//!
+//! A mechanical button bounces, raising a burst of edges for each press. The
+//! `Debounce` takes samples of the pin level, e.g., from a task scheduled a
+//! few milliseconds after the first edge (with the line masked in between).
+
+use core::mem::offset_of;
+
+use crate::bus::RegisterBus;
+use crate::regs::{
+    Exti as ExtiRegs, Gpio, Rcc, Syscfg, APB2ENR_SYSCFGEN, EXTI, GPIOA, GPIOB, GPIOC, GPIOD, GPIOE,
+    GPIOH, MODER_INPUT, RCC, SYSCFG,
+};
+
+#[rustfmt::skip]
+pub mod address {
+    use super::*;
+
+    pub const RCC_AHB1ENR: u32      = RCC.reg(offset_of!(Rcc, AHB1ENR));
+    pub const RCC_APB2ENR: u32      = RCC.reg(offset_of!(Rcc, APB2ENR));
+    pub const SYSCFG_EXTICR1: u32   = SYSCFG.reg(offset_of!(Syscfg, EXTICR));
+    pub const EXTI_IMR: u32         = EXTI.reg(offset_of!(ExtiRegs, IMR));
+    pub const EXTI_RTSR: u32        = EXTI.reg(offset_of!(ExtiRegs, RTSR));
+    pub const EXTI_FTSR: u32        = EXTI.reg(offset_of!(ExtiRegs, FTSR));
+    pub const EXTI_SWIER: u32       = EXTI.reg(offset_of!(ExtiRegs, SWIER));
+    pub const EXTI_PR: u32          = EXTI.reg(offset_of!(ExtiRegs, PR));
+}
+
+use address::*;
+
+/// A GPIO port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+}
+
+impl Port {
+    /// The `EXTICR` code of the port, also its `GPIOxEN` bit in `AHB1ENR`
+    pub const fn index(self) -> u8 {
+        match self {
+            Port::A => 0,
+            Port::B => 1,
+            Port::C => 2,
+            Port::D => 3,
+            Port::E => 4,
+            Port::H => 7,
+        }
+    }
+
+    /// Address of the register at `offset` of the port
+    pub const fn reg(self, offset: usize) -> u32 {
+        match self {
+            Port::A => GPIOA.reg(offset),
+            Port::B => GPIOB.reg(offset),
+            Port::C => GPIOC.reg(offset),
+            Port::D => GPIOD.reg(offset),
+            Port::E => GPIOE.reg(offset),
+            Port::H => GPIOH.reg(offset),
+        }
+    }
+}
+
+/// A GPIO pin, routed to the EXTI line of its number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pin {
+    pub port: Port,
+    pub pin: u8,
+}
+
+impl Pin {
+    pub const fn new(port: Port, pin: u8) -> Self {
+        assert!(pin < 16, "pin out of range");
+        Pin { port, pin }
+    }
+
+    pub const fn line(self) -> u8 {
+        self.pin
+    }
+
+    const fn mask(self) -> u32 {
+        1 << self.pin
+    }
+}
+
+/// The user button (B1) of the Nucleo, low when pressed
+pub const PC13: Pin = Pin::new(Port::C, 13);
+
+/// Trigger of a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// Pull-up/pull-down of an input pin (`PUPDR`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pull {
+    None = 0b00,
+    Up = 0b01,
+    Down = 0b10,
+}
+
+/// The interrupt vectors of the GPIO lines (0..=15)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vector {
+    Exti0,
+    Exti1,
+    Exti2,
+    Exti3,
+    Exti4,
+    Exti9_5,
+    Exti15_10,
+}
+
+impl Vector {
+    /// The vector raised by `line`
+    pub const fn of(line: u8) -> Vector {
+        match line {
+            0 => Vector::Exti0,
+            1 => Vector::Exti1,
+            2 => Vector::Exti2,
+            3 => Vector::Exti3,
+            4 => Vector::Exti4,
+            5..=9 => Vector::Exti9_5,
+            _ => Vector::Exti15_10,
+        }
+    }
+
+    /// The lines sharing the vector, bit n for line n
+    pub const fn lines(self) -> u32 {
+        match self {
+            Vector::Exti0 => 1 << 0,
+            Vector::Exti1 => 1 << 1,
+            Vector::Exti2 => 1 << 2,
+            Vector::Exti3 => 1 << 3,
+            Vector::Exti4 => 1 << 4,
+            Vector::Exti9_5 => 0b11111 << 5,
+            Vector::Exti15_10 => 0b111111 << 10,
+        }
+    }
+}
+
+/// Pending lines, iterated in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lines(pub u32);
+
+impl Iterator for Lines {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.0 == 0 {
+            return None;
+        }
+        let line = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        Some(line)
+    }
+}
+
+/// EXTI and SYSCFG, over a `RegisterBus`
+pub struct Exti<B: RegisterBus> {
+    bus: B,
+}
+
+impl<B: RegisterBus> Exti<B> {
+    /// Powers on SYSCFG (for routing pins to lines)
+    pub fn new(bus: B) -> Self {
+        bus.modify_u32(RCC_APB2ENR, 0, APB2ENR_SYSCFGEN.mask());
+        Exti { bus }
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    /// Powers on the port of `pin`, and configures it as input
+    pub fn input(&self, pin: Pin, pull: Pull) {
+        let bus = &self.bus;
+        bus.modify_u32(RCC_AHB1ENR, 0, 1 << pin.port.index());
+        let field = Gpio::moder(pin.pin);
+        let moder = pin.port.reg(offset_of!(Gpio, MODER));
+        bus.modify_u32(moder, field.mask(), field.val(MODER_INPUT).bits());
+        let pupdr = pin.port.reg(offset_of!(Gpio, PUPDR));
+        bus.modify_u32(pupdr, field.mask(), field.val(pull as u32).bits());
+    }
+
+    /// Routes `pin` to its line (replacing the pin of another port), triggered
+    /// on `edge`, and unmasks the line
+    pub fn listen(&self, pin: Pin, edge: Edge) {
+        let bus = &self.bus;
+        let line = pin.line();
+        let exticr = SYSCFG_EXTICR1 + 4 * (line as u32 / 4);
+        let field = Syscfg::exticr(line);
+        bus.modify_u32(
+            exticr,
+            field.mask(),
+            field.val(pin.port.index() as u32).bits(),
+        );
+
+        let (rising, falling) = match edge {
+            Edge::Rising => (pin.mask(), 0),
+            Edge::Falling => (0, pin.mask()),
+            Edge::Both => (pin.mask(), pin.mask()),
+        };
+        bus.modify_u32(EXTI_RTSR, pin.mask(), rising);
+        bus.modify_u32(EXTI_FTSR, pin.mask(), falling);
+        // a stale edge (e.g., from before the routing) is not reported
+        bus.write_u32(EXTI_PR, pin.mask());
+        self.unmask(line);
+    }
+
+    /// Masks the line, and clears the trigger
+    pub fn unlisten(&self, line: u8) {
+        self.mask(line);
+        self.bus.modify_u32(EXTI_RTSR, 1 << line, 0);
+        self.bus.modify_u32(EXTI_FTSR, 1 << line, 0);
+    }
+
+    /// Stops the line from raising its interrupt (edges are still latched)
+    pub fn mask(&self, line: u8) {
+        self.bus.modify_u32(EXTI_IMR, 1 << line, 0);
+    }
+
+    pub fn unmask(&self, line: u8) {
+        self.bus.modify_u32(EXTI_IMR, 0, 1 << line);
+    }
+
+    /// Raises `line` from software (as `NVIC::pend` in `device.rs`, but
+    /// through the EXTI)
+    pub fn trigger(&self, line: u8) {
+        self.bus.write_u32(EXTI_SWIER, 1 << line);
+    }
+
+    /// The unmasked pending lines of `vector`
+    pub fn pending(&self, vector: Vector) -> Lines {
+        let pr = self.bus.read_u32(EXTI_PR);
+        let imr = self.bus.read_u32(EXTI_IMR);
+        Lines(pr & imr & vector.lines())
+    }
+
+    /// Clears the pending `lines` (else the interrupt is raised again)
+    pub fn clear(&self, lines: Lines) {
+        // write 1 to clear, RM0368 10.3.6
+        if lines.0 != 0 {
+            self.bus.write_u32(EXTI_PR, lines.0);
+        }
+    }
+
+    /// The unmasked pending lines of `vector`, cleared
+    pub fn take_pending(&self, vector: Vector) -> Lines {
+        let lines = self.pending(vector);
+        self.clear(lines);
+        lines
+    }
+
+    /// The level of `pin` (`IDR`)
+    pub fn is_high(&self, pin: Pin) -> bool {
+        self.bus.read_u32(pin.port.reg(offset_of!(Gpio, IDR))) & pin.mask() != 0
+    }
+}
+
+/// A debounced button event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Pressed,
+    Released,
+}
+
+/// Debounces the level of a button, sampled at a fixed period
+///
+/// The level is taken when `samples` consecutive samples agree.
+pub struct Debounce {
+    // pressed at the low level
+    active_low: bool,
+    samples: u8,
+    // consecutive samples of a level other than the stable one
+    count: u8,
+    pressed: bool,
+}
+
+impl Debounce {
+    /// Released, for a button pressed at the low (`active_low`) or high level
+    pub const fn new(active_low: bool, samples: u8) -> Self {
+        Debounce {
+            active_low,
+            samples,
+            count: 0,
+            pressed: false,
+        }
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Takes a sample of the pin level, the event if the debounced state
+    /// changed
+    pub fn sample(&mut self, high: bool) -> Option<Event> {
+        let pressed = high != self.active_low;
+        if pressed == self.pressed {
+            self.count = 0;
+            return None;
+        }
+        self.count += 1;
+        if self.count < self.samples {
+            return None;
+        }
+        self.count = 0;
+        self.pressed = pressed;
+        Some(if pressed {
+            Event::Pressed
+        } else {
+            Event::Released
+        })
+    }
+
+    /// Whether a sample changes the level being settled (keep sampling)
+    pub fn is_settling(&self) -> bool {
+        self.count != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Access::*, MemoryBus};
+
+    #[test]
+    fn addresses() {
+        assert_eq!(SYSCFG_EXTICR1, 0x4001_3808);
+        assert_eq!(EXTI_IMR, 0x4001_3C00);
+        assert_eq!(EXTI_PR, 0x4001_3C14);
+        assert_eq!(Port::C.reg(offset_of!(Gpio, IDR)), 0x4002_0810);
+    }
+
+    #[test]
+    fn vectors() {
+        assert_eq!(Vector::of(0), Vector::Exti0);
+        assert_eq!(Vector::of(7), Vector::Exti9_5);
+        assert_eq!(Vector::of(13), Vector::Exti15_10);
+        let all = (0..=15).fold(0, |all, line| all | Vector::of(line).lines());
+        assert_eq!(all, 0xFFFF);
+        for line in 0..=15 {
+            assert_ne!(Vector::of(line).lines() & 1 << line, 0);
+        }
+    }
+
+    #[test]
+    fn listen_pc13() {
+        let exti = Exti::new(MemoryBus::new());
+        let bus = exti.bus();
+        // PA13 (SWDIO) routed before
+        bus.preset(SYSCFG_EXTICR1 + 12, 0x0000);
+        bus.preset(EXTI_RTSR, 1 << 13);
+        exti.listen(PC13, Edge::Falling);
+
+        assert_eq!(bus.peek(RCC_APB2ENR), 1 << 14);
+        // EXTICR4, EXTI13 = 0b0010 (port C)
+        assert_eq!(bus.peek(SYSCFG_EXTICR1 + 12), 0x0020);
+        assert_eq!(bus.peek(EXTI_RTSR), 0);
+        assert_eq!(bus.peek(EXTI_FTSR), 1 << 13);
+        assert_eq!(bus.peek(EXTI_IMR), 1 << 13);
+        assert!(bus.accesses().contains(&Write(EXTI_PR, 1 << 13)));
+    }
+
+    #[test]
+    fn input() {
+        let exti = Exti::new(MemoryBus::new());
+        let bus = exti.bus();
+        let moder = Port::C.reg(offset_of!(Gpio, MODER));
+        bus.preset(moder, 0xFFFF_FFFF);
+        exti.input(PC13, Pull::Up);
+        assert_eq!(bus.peek(RCC_AHB1ENR), 1 << 2);
+        assert_eq!(bus.peek(moder), !(0b11 << 26));
+        assert_eq!(bus.peek(Port::C.reg(offset_of!(Gpio, PUPDR))), 0b01 << 26);
+    }
+
+    #[test]
+    fn shared_vector() {
+        let exti = Exti::new(MemoryBus::new());
+        let bus = exti.bus();
+        // lines 6, 8 (of 9_5) and 13 pending, 8 masked
+        bus.preset(EXTI_PR, 1 << 6 | 1 << 8 | 1 << 13);
+        bus.preset(EXTI_IMR, 1 << 6 | 1 << 13);
+
+        assert_eq!(exti.pending(Vector::Exti15_10).next(), Some(13));
+        assert_eq!(exti.pending(Vector::Exti0).next(), None);
+
+        let lines = exti.take_pending(Vector::Exti9_5);
+        assert!(lines.eq([6].iter().cloned()));
+        // (the `MemoryBus` stores the write, PR clears the written 1s)
+        assert_eq!(bus.accesses().last(), Some(&Write(EXTI_PR, 1 << 6)));
+    }
+
+    #[test]
+    fn lines() {
+        let lines = Lines(1 << 15 | 1 << 10 | 1 << 11);
+        assert!(lines.eq([10, 11, 15].iter().cloned()));
+    }
+
+    #[test]
+    fn debounce() {
+        // active low, as PC13, 3 samples
+        let mut button = Debounce::new(true, 3);
+        // bouncing, then pressed (low)
+        let levels = [false, true, false, false, false, false];
+        let events: [Option<Event>; 6] = [None, None, None, None, Some(Event::Pressed), None];
+        for (&level, &event) in levels.iter().zip(events.iter()) {
+            assert_eq!(button.sample(level), event);
+        }
+        assert!(button.is_pressed());
+
+        assert_eq!(button.sample(true), None);
+        assert!(button.is_settling());
+        assert_eq!(button.sample(true), None);
+        assert_eq!(button.sample(true), Some(Event::Released));
+        assert!(!button.is_settling());
+    }
+}
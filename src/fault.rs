@@ -0,0 +1,447 @@
+//! HardFault analysis, decoding the fault status registers
+//!
+//! In `crash.rs` the `HardFault` handler only prints the `ExceptionFrame`, and
+//! the cause is found by hand (in `gdb`). Here the System Control Block fault
+//! registers are read (Cortex-M4 Devices Generic User Guide, 4.3.10 - 4.3.13):
+//! - `CFSR`, configurable fault status (MemManage, BusFault and UsageFault)
+//! - `HFSR`, hard fault status
+//! - `MMFAR` and `BFAR`, the faulting address (if valid)
+//!
+//! and each status bit is decoded into a `Cause`. Only `Registers::read`
+//! touches the hardware (over a `RegisterBus`), the rest is plain logic.
+//!
+//! ``` ignore
+//! #[exception]
+//! fn HardFault(ef: &ExceptionFrame) -> ! {
+//!     let regs = Registers::read(&unsafe { Mmio::new() });
+//!     panic!("{}", Report::new(regs, ef.into()));
+//! }
+//! ```
+
+use core::fmt;
+
+use crate::bus::RegisterBus;
+
+#[rustfmt::skip]
+pub mod address {
+    pub const CFSR: u32     = 0xE000_ED28;
+    pub const HFSR: u32     = 0xE000_ED2C;
+    pub const MMFAR: u32    = 0xE000_ED34;
+    pub const BFAR: u32     = 0xE000_ED38;
+}
+
+/// The fault status and address registers
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+}
+
+impl Registers {
+    pub fn read<B: RegisterBus>(bus: &B) -> Self {
+        Registers {
+            cfsr: bus.read_u32(address::CFSR),
+            hfsr: bus.read_u32(address::HFSR),
+            mmfar: bus.read_u32(address::MMFAR),
+            bfar: bus.read_u32(address::BFAR),
+        }
+    }
+
+    /// Clears the status bits (write one to clear), e.g., after a fault has
+    /// been handled without reset
+    pub fn clear<B: RegisterBus>(&self, bus: &B) {
+        bus.write_u32(address::CFSR, self.cfsr);
+        bus.write_u32(address::HFSR, self.hfsr);
+    }
+
+    /// The causes of the fault, in bit order (`CFSR` then `HFSR`)
+    pub fn causes(&self) -> impl Iterator<Item = Cause> + '_ {
+        Cause::ALL.iter().cloned().filter(move |c| self.is_set(*c))
+    }
+
+    pub fn is_set(&self, cause: Cause) -> bool {
+        let (reg, bit) = cause.bit();
+        let reg = if reg == Reg::Cfsr {
+            self.cfsr
+        } else {
+            self.hfsr
+        };
+        reg & 1 << bit != 0
+    }
+
+    /// The fault handler that would have run, were it enabled (in `SHCSR`),
+    /// else `HardFault`
+    pub fn kind(&self) -> Kind {
+        if self.cfsr & 0xFF != 0 {
+            Kind::MemManage
+        } else if self.cfsr & 0xFF00 != 0 {
+            Kind::BusFault
+        } else if self.cfsr & 0xFFFF_0000 != 0 {
+            Kind::UsageFault
+        } else if self.is_set(Cause::VectTbl) {
+            Kind::VectorTable
+        } else if self.is_set(Cause::DebugEvt) {
+            Kind::Debug
+        } else {
+            Kind::Unknown
+        }
+    }
+
+    /// `HardFault` escalated from a configurable fault (disabled, or raised
+    /// at a priority that could not preempt)
+    pub fn is_forced(&self) -> bool {
+        self.is_set(Cause::Forced)
+    }
+
+    /// The data address accessed, if valid (`MMARVALID`/`BFARVALID`)
+    pub fn address(&self) -> Option<u32> {
+        if self.is_set(Cause::MmarValid) {
+            Some(self.mmfar)
+        } else if self.is_set(Cause::BfarValid) {
+            Some(self.bfar)
+        } else {
+            None
+        }
+    }
+
+    /// `false` if the fault occurred while stacking/unstacking, the stacked
+    /// frame (`Stacked`) cannot be trusted
+    pub fn frame_valid(&self) -> bool {
+        ![
+            Cause::MStkErr,
+            Cause::MUnstkErr,
+            Cause::StkErr,
+            Cause::UnstkErr,
+        ]
+        .iter()
+        .any(|c| self.is_set(*c))
+    }
+}
+
+/// Fault class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    MemManage,
+    BusFault,
+    UsageFault,
+    /// bus fault on a vector table read
+    VectorTable,
+    /// breakpoint, with no debugger attached
+    Debug,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reg {
+    Cfsr,
+    Hfsr,
+}
+
+/// A fault status bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    // MMFSR, CFSR[7:0]
+    IaccViol,
+    DaccViol,
+    MUnstkErr,
+    MStkErr,
+    MLspErr,
+    MmarValid,
+    // BFSR, CFSR[15:8]
+    IBusErr,
+    PreciseErr,
+    ImpreciseErr,
+    UnstkErr,
+    StkErr,
+    LspErr,
+    BfarValid,
+    // UFSR, CFSR[31:16]
+    UndefInstr,
+    InvState,
+    InvPc,
+    NoCp,
+    Unaligned,
+    DivByZero,
+    // HFSR
+    VectTbl,
+    Forced,
+    DebugEvt,
+}
+
+impl Cause {
+    pub const ALL: [Cause; 22] = [
+        Cause::IaccViol,
+        Cause::DaccViol,
+        Cause::MUnstkErr,
+        Cause::MStkErr,
+        Cause::MLspErr,
+        Cause::MmarValid,
+        Cause::IBusErr,
+        Cause::PreciseErr,
+        Cause::ImpreciseErr,
+        Cause::UnstkErr,
+        Cause::StkErr,
+        Cause::LspErr,
+        Cause::BfarValid,
+        Cause::UndefInstr,
+        Cause::InvState,
+        Cause::InvPc,
+        Cause::NoCp,
+        Cause::Unaligned,
+        Cause::DivByZero,
+        Cause::VectTbl,
+        Cause::Forced,
+        Cause::DebugEvt,
+    ];
+
+    #[rustfmt::skip]
+    fn bit(self) -> (Reg, u32) {
+        match self {
+            Cause::IaccViol     => (Reg::Cfsr, 0),
+            Cause::DaccViol     => (Reg::Cfsr, 1),
+            Cause::MUnstkErr    => (Reg::Cfsr, 3),
+            Cause::MStkErr      => (Reg::Cfsr, 4),
+            Cause::MLspErr      => (Reg::Cfsr, 5),
+            Cause::MmarValid    => (Reg::Cfsr, 7),
+            Cause::IBusErr      => (Reg::Cfsr, 8),
+            Cause::PreciseErr   => (Reg::Cfsr, 9),
+            Cause::ImpreciseErr => (Reg::Cfsr, 10),
+            Cause::UnstkErr     => (Reg::Cfsr, 11),
+            Cause::StkErr       => (Reg::Cfsr, 12),
+            Cause::LspErr       => (Reg::Cfsr, 13),
+            Cause::BfarValid    => (Reg::Cfsr, 15),
+            Cause::UndefInstr   => (Reg::Cfsr, 16),
+            Cause::InvState     => (Reg::Cfsr, 17),
+            Cause::InvPc        => (Reg::Cfsr, 18),
+            Cause::NoCp         => (Reg::Cfsr, 19),
+            Cause::Unaligned    => (Reg::Cfsr, 24),
+            Cause::DivByZero    => (Reg::Cfsr, 25),
+            Cause::VectTbl      => (Reg::Hfsr, 1),
+            Cause::Forced       => (Reg::Hfsr, 30),
+            Cause::DebugEvt     => (Reg::Hfsr, 31),
+        }
+    }
+
+    /// The bit name, as in the user guide
+    #[rustfmt::skip]
+    pub fn name(self) -> &'static str {
+        match self {
+            Cause::IaccViol     => "IACCVIOL",
+            Cause::DaccViol     => "DACCVIOL",
+            Cause::MUnstkErr    => "MUNSTKERR",
+            Cause::MStkErr      => "MSTKERR",
+            Cause::MLspErr      => "MLSPERR",
+            Cause::MmarValid    => "MMARVALID",
+            Cause::IBusErr      => "IBUSERR",
+            Cause::PreciseErr   => "PRECISERR",
+            Cause::ImpreciseErr => "IMPRECISERR",
+            Cause::UnstkErr     => "UNSTKERR",
+            Cause::StkErr       => "STKERR",
+            Cause::LspErr       => "LSPERR",
+            Cause::BfarValid    => "BFARVALID",
+            Cause::UndefInstr   => "UNDEFINSTR",
+            Cause::InvState     => "INVSTATE",
+            Cause::InvPc        => "INVPC",
+            Cause::NoCp         => "NOCP",
+            Cause::Unaligned    => "UNALIGNED",
+            Cause::DivByZero    => "DIVBYZERO",
+            Cause::VectTbl      => "VECTTBL",
+            Cause::Forced       => "FORCED",
+            Cause::DebugEvt     => "DEBUGEVT",
+        }
+    }
+
+    #[rustfmt::skip]
+    pub fn description(self) -> &'static str {
+        match self {
+            Cause::IaccViol     => "instruction fetch from a protected (MPU) or XN region",
+            Cause::DaccViol     => "data access to a protected (MPU) region",
+            Cause::MUnstkErr    => "MemManage fault on unstacking, return from exception",
+            Cause::MStkErr      => "MemManage fault on stacking, exception entry",
+            Cause::MLspErr      => "MemManage fault on lazy FPU state preservation",
+            Cause::MmarValid    => "MMFAR holds the faulting address",
+            Cause::IBusErr      => "bus error on instruction fetch",
+            Cause::PreciseErr   => "precise data bus error (at the stacked PC)",
+            Cause::ImpreciseErr => "imprecise data bus error (after the stacked PC)",
+            Cause::UnstkErr     => "bus fault on unstacking, return from exception",
+            Cause::StkErr       => "bus fault on stacking, exception entry",
+            Cause::LspErr       => "bus fault on lazy FPU state preservation",
+            Cause::BfarValid    => "BFAR holds the faulting address",
+            Cause::UndefInstr   => "undefined instruction",
+            Cause::InvState     => "invalid state, e.g., a branch to an even (ARM) address",
+            Cause::InvPc        => "invalid EXC_RETURN on exception return",
+            Cause::NoCp         => "coprocessor access, e.g., FPU not enabled",
+            Cause::Unaligned    => "unaligned access (UNALIGN_TRP)",
+            Cause::DivByZero    => "division by zero (DIV_0_TRP)",
+            Cause::VectTbl      => "bus fault on a vector table read",
+            Cause::Forced       => "escalated from a configurable fault",
+            Cause::DebugEvt     => "debug event (breakpoint) with no debugger",
+        }
+    }
+}
+
+/// The registers stacked on exception entry, of interest for a fault
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stacked {
+    pub pc: u32,
+    pub lr: u32,
+    pub xpsr: u32,
+}
+
+impl From<&cortex_m_rt::ExceptionFrame> for Stacked {
+    fn from(ef: &cortex_m_rt::ExceptionFrame) -> Self {
+        Stacked {
+            pc: ef.pc,
+            lr: ef.lr,
+            xpsr: ef.xpsr,
+        }
+    }
+}
+
+/// A decoded fault, `Display` gives a report of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub regs: Registers,
+    pub stacked: Stacked,
+}
+
+impl Report {
+    pub fn new(regs: Registers, stacked: Stacked) -> Self {
+        Report { regs, stacked }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let regs = &self.regs;
+        write!(f, "HardFault: {:?}", regs.kind())?;
+        if regs.is_forced() {
+            write!(f, " (forced)")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "  CFSR  0x{:08x}, HFSR 0x{:08x}", regs.cfsr, regs.hfsr)?;
+        for cause in regs.causes() {
+            writeln!(f, "  {:<11} {}", cause.name(), cause.description())?;
+        }
+        if let Some(addr) = regs.address() {
+            writeln!(f, "  address 0x{:08x}", addr)?;
+        }
+        let s = &self.stacked;
+        write!(
+            f,
+            "  PC 0x{:08x}, LR 0x{:08x}, xPSR 0x{:08x}",
+            s.pc, s.lr, s.xpsr
+        )?;
+        if !regs.frame_valid() {
+            write!(f, " (stacking failed, not valid)")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemoryBus;
+
+    // the read of `crash.rs` (0x2FFF_FFFF, outside of RAM)
+    const CRASH: Registers = Registers {
+        cfsr: 1 << 15 | 1 << 9,
+        hfsr: 1 << 30,
+        mmfar: 0,
+        bfar: 0x2FFF_FFFF,
+    };
+
+    #[test]
+    fn read_and_clear() {
+        let bus = MemoryBus::new();
+        bus.preset(address::CFSR, CRASH.cfsr);
+        bus.preset(address::HFSR, CRASH.hfsr);
+        bus.preset(address::BFAR, CRASH.bfar);
+
+        let regs = Registers::read(&bus);
+        assert_eq!(regs, CRASH);
+        regs.clear(&bus);
+        // write one to clear, the bits just read
+        assert_eq!(bus.peek(address::CFSR), CRASH.cfsr);
+    }
+
+    #[test]
+    fn precise_bus_fault() {
+        let causes: Vec<_> = CRASH.causes().collect();
+        assert_eq!(causes, [Cause::PreciseErr, Cause::BfarValid, Cause::Forced]);
+        assert_eq!(CRASH.kind(), Kind::BusFault);
+        assert!(CRASH.is_forced());
+        assert_eq!(CRASH.address(), Some(0x2FFF_FFFF));
+        assert!(CRASH.frame_valid());
+    }
+
+    #[test]
+    fn address_not_valid() {
+        // imprecise, BFAR not valid
+        let regs = Registers {
+            cfsr: 1 << 10,
+            bfar: 0x2FFF_FFFF,
+            ..Registers::default()
+        };
+        assert_eq!(regs.address(), None);
+
+        let regs = Registers {
+            cfsr: 1 << 7 | 1 << 1,
+            mmfar: 0x1000,
+            ..Registers::default()
+        };
+        assert_eq!(regs.kind(), Kind::MemManage);
+        assert_eq!(regs.address(), Some(0x1000));
+    }
+
+    #[test]
+    fn usage_faults() {
+        let regs = Registers {
+            cfsr: 1 << 25 | 1 << 17,
+            ..Registers::default()
+        };
+        assert_eq!(regs.kind(), Kind::UsageFault);
+        let causes: Vec<_> = regs.causes().collect();
+        assert_eq!(causes, [Cause::InvState, Cause::DivByZero]);
+
+        let regs = Registers {
+            hfsr: 1 << 1,
+            ..Registers::default()
+        };
+        assert_eq!(regs.kind(), Kind::VectorTable);
+        assert_eq!(Registers::default().kind(), Kind::Unknown);
+    }
+
+    #[test]
+    fn stacking_fault() {
+        let regs = Registers {
+            cfsr: 1 << 12,
+            ..Registers::default()
+        };
+        assert!(!regs.frame_valid());
+    }
+
+    #[test]
+    fn report() {
+        let stacked = Stacked {
+            pc: 0x0800_0524,
+            lr: 0x0800_0519,
+            xpsr: 0x6100_0000,
+        };
+        assert_eq!(
+            Report::new(CRASH, stacked).to_string(),
+            "HardFault: BusFault (forced)\n\
+             \x20 CFSR  0x00008200, HFSR 0x40000000\n\
+             \x20 PRECISERR   precise data bus error (at the stacked PC)\n\
+             \x20 BFARVALID   BFAR holds the faulting address\n\
+             \x20 FORCED      escalated from a configurable fault\n\
+             \x20 address 0x2fffffff\n\
+             \x20 PC 0x08000524, LR 0x08000519, xPSR 0x61000000"
+        );
+    }
+}
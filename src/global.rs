@@ -0,0 +1,387 @@
+//! Global state, shared safely by `main` and the exception/interrupt handlers
+//!
+//! `bare0.rs` kept its state in `static mut` variables, accessed by `unsafe`
+//! code (nothing stops a handler from changing them half way through a read
+//! modify write of `main`). The cells here are `static` (not `mut`), with safe
+//! access:
+//!
+//! - `Mutex<T>`, any value, borrowed in a critical section (interrupts
+//!   disabled), i.e., the `Mutex<RefCell<T>>` of `cortex-m`
+//! - `VolatileGlobal<T>`, an integer, read and written atomically (never
+//!   cached in a register, never torn)
+//! - `Singleton<T>`, a value set once (e.g., by `main` at startup), then read
+//!   by everyone
+//! - `Handoff<T>`, a value (e.g., a peripheral) moved from `main` to the one
+//!   handler owning it from then on
+//!
+//! ``` ignore
+//! static TICKS: VolatileGlobal<u32> = VolatileGlobal::new(0);
+//! static LOG: Mutex<[u32; 4]> = Mutex::new([0; 4]);
+//!
+//! #[exception]
+//! fn SysTick() {
+//!     let ticks = TICKS.fetch_add(1);
+//!     LOG.lock(|log| log[ticks as usize % 4] = ticks);
+//! }
+//! ```
+//!
+//! (RTFM resources give the same guarantees, checked at compile time, and
+//! without disabling all interrupts.)
+
+use core::cell::{RefCell, RefMut, UnsafeCell};
+use core::mem::{self, MaybeUninit};
+use core::sync::atomic::{AtomicI32, AtomicU16, AtomicU32, AtomicU8, AtomicUsize, Ordering};
+
+use cortex_m::interrupt::{self, CriticalSection};
+
+/// A value borrowed (mutably) in a critical section
+pub struct Mutex<T>(interrupt::Mutex<RefCell<T>>);
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex(interrupt::Mutex::new(RefCell::new(value)))
+    }
+
+    /// Borrows the value for the duration of the critical section `cs`
+    ///
+    /// # Panics
+    ///
+    /// If the value is already borrowed (in the same critical section).
+    pub fn borrow<'cs>(&'cs self, cs: &'cs CriticalSection) -> RefMut<'cs, T> {
+        self.0.borrow(cs).borrow_mut()
+    }
+
+    /// Calls `f` with the value, in a critical section
+    pub fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        interrupt::free(|cs| f(&mut self.borrow(cs)))
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// An integer type of the same size and alignment as its atomic counterpart
+pub trait Primitive: sealed::Sealed + Copy {}
+
+/// An integer, read and written atomically
+pub struct VolatileGlobal<T: Primitive>(UnsafeCell<T>);
+
+// only accessed as the atomic counterpart of `T`
+unsafe impl<T: Primitive> Sync for VolatileGlobal<T> {}
+
+impl<T: Primitive> VolatileGlobal<T> {
+    pub const fn new(value: T) -> Self {
+        VolatileGlobal(UnsafeCell::new(value))
+    }
+}
+
+macro_rules! volatile_global {
+    ($($t:ty: $atomic:ty),*) => {$(
+        impl sealed::Sealed for $t {}
+
+        impl Primitive for $t {}
+
+        impl VolatileGlobal<$t> {
+            fn atomic(&self) -> &$atomic {
+                unsafe { &*(self.0.get() as *const $atomic) }
+            }
+
+            pub fn get(&self) -> $t {
+                self.atomic().load(Ordering::SeqCst)
+            }
+
+            pub fn set(&self, value: $t) {
+                self.atomic().store(value, Ordering::SeqCst)
+            }
+
+            /// Sets `value`, returns the previous value
+            pub fn swap(&self, value: $t) -> $t {
+                self.atomic().swap(value, Ordering::SeqCst)
+            }
+
+            /// Adds `value` (wrapping), returns the previous value
+            pub fn fetch_add(&self, value: $t) -> $t {
+                self.atomic().fetch_add(value, Ordering::SeqCst)
+            }
+
+            /// Replaces the value by `f(value)`, returns the previous value
+            ///
+            /// `f` may be called more than once, if the value is changed (by a
+            /// handler) in between.
+            pub fn update(&self, mut f: impl FnMut($t) -> $t) -> $t {
+                let atomic = self.atomic();
+                let mut old = atomic.load(Ordering::SeqCst);
+                loop {
+                    let new = f(old);
+                    let seq = Ordering::SeqCst;
+                    match atomic.compare_exchange_weak(old, new, seq, seq) {
+                        Ok(old) => return old,
+                        Err(now) => old = now,
+                    }
+                }
+            }
+        }
+    )*};
+}
+
+volatile_global!(u8: AtomicU8, u16: AtomicU16, u32: AtomicU32, i32: AtomicI32, usize: AtomicUsize);
+
+const EMPTY: u8 = 0;
+const BUSY: u8 = 1;
+const READY: u8 = 2;
+
+/// A value set once, then shared
+pub struct Singleton<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// the value is written once (by whoever wins `EMPTY` -> `BUSY`), and only
+// shared after `READY`
+unsafe impl<T: Send + Sync> Sync for Singleton<T> {}
+
+impl<T> Default for Singleton<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Singleton<T> {
+    /// Not yet set
+    pub const fn new() -> Self {
+        Singleton {
+            state: AtomicU8::new(EMPTY),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Sets the value, or gives it back if already set (or being set, by the
+    /// context this one preempted)
+    pub fn try_init(&self, value: T) -> Result<&T, T> {
+        if self
+            .state
+            .compare_exchange(EMPTY, BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(value);
+        }
+        unsafe { (*self.value.get()).as_mut_ptr().write(value) };
+        self.state.store(READY, Ordering::Release);
+        Ok(unsafe { &*(*self.value.get()).as_ptr() })
+    }
+
+    /// Sets the value
+    ///
+    /// # Panics
+    ///
+    /// If already set.
+    pub fn init(&self, value: T) -> &T {
+        match self.try_init(value) {
+            Ok(value) => value,
+            Err(_) => panic!("singleton already initialized"),
+        }
+    }
+
+    /// The value, if set
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == READY {
+            Some(unsafe { &*(*self.value.get()).as_ptr() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Drop for Singleton<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == READY {
+            unsafe { self.value.get_mut().as_mut_ptr().drop_in_place() };
+        }
+    }
+}
+
+enum Slot<T> {
+    Empty,
+    Given(T),
+    Taken,
+}
+
+/// A value given by one context, and taken (once) by another
+///
+/// E.g., the `ITM` handed from `main` to `SysTick` (`exception_itm.rs`), in
+/// place of `Peripherals::steal`:
+///
+/// ``` ignore
+/// static HANDOFF: Handoff<ITM> = Handoff::new();
+///
+/// // main
+/// HANDOFF.give(p.ITM);
+///
+/// #[exception]
+/// fn SysTick() {
+///     // a handler local, kept from one run to the next
+///     static mut ITM: Option<ITM> = None;
+///     let itm = ITM.get_or_insert_with(|| HANDOFF.take());
+/// }
+/// ```
+pub struct Handoff<T>(Mutex<Slot<T>>);
+
+impl<T> Default for Handoff<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Handoff<T> {
+    /// Not yet given
+    pub const fn new() -> Self {
+        Handoff(Mutex::new(Slot::Empty))
+    }
+
+    /// Gives the value, in a critical section
+    ///
+    /// # Panics
+    ///
+    /// If given before.
+    pub fn give(&self, value: T) {
+        interrupt::free(|cs| self.give_in(cs, value))
+    }
+
+    /// `give`, in the critical section `cs`
+    pub fn give_in(&self, cs: &CriticalSection, value: T) {
+        let mut slot = self.0.borrow(cs);
+        match *slot {
+            Slot::Empty => *slot = Slot::Given(value),
+            _ => panic!("handoff already given"),
+        }
+    }
+
+    /// Takes the value, in a critical section
+    ///
+    /// # Panics
+    ///
+    /// If not given yet, or already taken.
+    pub fn take(&self) -> T {
+        interrupt::free(|cs| self.take_in(cs))
+    }
+
+    /// `take`, in the critical section `cs`
+    pub fn take_in(&self, cs: &CriticalSection) -> T {
+        match self.try_take_in(cs) {
+            Some(value) => value,
+            None => panic!("handoff not given"),
+        }
+    }
+
+    /// Takes the value, `None` if not given yet (e.g., a handler running before
+    /// `main` gives it)
+    ///
+    /// # Panics
+    ///
+    /// If already taken.
+    pub fn try_take_in(&self, cs: &CriticalSection) -> Option<T> {
+        let mut slot = self.0.borrow(cs);
+        match mem::replace(&mut *slot, Slot::Taken) {
+            Slot::Given(value) => Some(value),
+            Slot::Empty => {
+                *slot = Slot::Empty;
+                None
+            }
+            Slot::Taken => panic!("handoff already taken"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutex() {
+        static M: Mutex<[u32; 2]> = Mutex::new([0; 2]);
+        // on the host there are no interrupts to disable (`lock` is target only)
+        let cs = unsafe { CriticalSection::new() };
+        M.borrow(&cs)[1] = 7;
+        assert_eq!(*M.borrow(&cs), [0, 7]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn mutex_borrowed_twice() {
+        let m = Mutex::new(0);
+        let cs = unsafe { CriticalSection::new() };
+        let _a = m.borrow(&cs);
+        let _b = m.borrow(&cs);
+    }
+
+    #[test]
+    fn volatile_global() {
+        // `bare0.rs`, `X` wraps
+        static X: VolatileGlobal<u32> = VolatileGlobal::new(u32::MAX);
+        assert_eq!(X.fetch_add(1), u32::MAX);
+        assert_eq!(X.get(), 0);
+        X.set(10);
+        assert_eq!(X.swap(3), 10);
+        assert_eq!(X.update(|x| x * 2), 3);
+        assert_eq!(X.get(), 6);
+
+        let y = VolatileGlobal::<i32>::new(-1);
+        y.fetch_add(2);
+        assert_eq!(y.get(), 1);
+    }
+
+    #[test]
+    fn singleton() {
+        static S: Singleton<&str> = Singleton::new();
+        assert_eq!(S.get(), None);
+        assert_eq!(S.init("clock"), &"clock");
+        assert_eq!(S.get(), Some(&"clock"));
+        assert_eq!(S.try_init("again"), Err("again"));
+    }
+
+    #[test]
+    #[should_panic(expected = "singleton already initialized")]
+    fn singleton_twice() {
+        let s = Singleton::new();
+        s.init(1);
+        s.init(2);
+    }
+
+    #[test]
+    fn handoff() {
+        struct Itm;
+        static HANDOFF: Handoff<Itm> = Handoff::new();
+        let cs = unsafe { CriticalSection::new() };
+        assert!(HANDOFF.try_take_in(&cs).is_none());
+        HANDOFF.give_in(&cs, Itm);
+        let Itm = HANDOFF.take_in(&cs);
+    }
+
+    #[test]
+    #[should_panic(expected = "handoff already taken")]
+    fn handoff_taken_twice() {
+        let handoff = Handoff::new();
+        let cs = unsafe { CriticalSection::new() };
+        handoff.give_in(&cs, 1);
+        handoff.take_in(&cs);
+        handoff.try_take_in(&cs);
+    }
+
+    #[test]
+    #[should_panic(expected = "handoff already given")]
+    fn handoff_given_twice() {
+        let handoff = Handoff::new();
+        let cs = unsafe { CriticalSection::new() };
+        handoff.give_in(&cs, 1);
+        handoff.give_in(&cs, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "handoff not given")]
+    fn handoff_not_given() {
+        let handoff = Handoff::<u32>::new();
+        let cs = unsafe { CriticalSection::new() };
+        handoff.take_in(&cs);
+    }
+}
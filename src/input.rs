@@ -0,0 +1,434 @@
+//! Button gestures from timestamped edges
+//!
+//! The EXTI handler of a button (`app::exti`) only records the edge, i.e., the
+//! time (a CYCCNT `Instant`) and the new level of the pin. The gestures are
+//! told apart later, by `poll`, from a single task scheduled at a fixed period
+//! for all buttons (`rtfm_input.rs`):
+//!
+//! - `Press`/`Release`, once the level has been stable for `debounce`
+//! - `Click`, a short press, not followed by another press within
+//!   `double_click` of the release (else `DoubleClick`, at the second press)
+//! - `LongPress`, held for `long_press`
+//! - `Repeat(n)`, held on after the `LongPress`, every `repeat`
+//!
+//! Each edge first brings the state machine up to its time, so a late poll
+//! reports the gestures in between (e.g., both presses of a `DoubleClick`).
+//! The state machine is pure logic, driven by the edges and the time of the
+//! polls, so it is tested on synthetic timelines.
+
+use core::mem;
+
+use heapless::{consts::U8, Vec};
+
+use crate::time::{Duration, Hertz, Instant};
+
+/// A debounced button event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Press,
+    Release,
+    Click,
+    DoubleClick,
+    LongPress,
+    /// The `n`th repeat (from 1) of a held button
+    Repeat(u16),
+}
+
+/// The events of a `poll`, in order
+pub type Events = Vec<Event, U8>;
+
+/// Timing of a button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Pressed at the low level (the Nucleo user button, B1)
+    pub active_low: bool,
+    /// Time the level must be stable
+    pub debounce: Duration,
+    /// Time held for a `LongPress`, shorter is a `Click`
+    pub long_press: Duration,
+    /// Longest time from a release to the next press of a `DoubleClick`, `None`
+    /// reports each `Click` at the release (without waiting)
+    pub double_click: Option<Duration>,
+    /// Period of `Repeat` after the `LongPress`, `None` for no repeat
+    pub repeat: Option<Duration>,
+}
+
+impl Default for Config {
+    /// Active low, 20ms debounce, 800ms long press, 300ms double click and
+    /// 200ms repeat
+    fn default() -> Self {
+        Config {
+            active_low: true,
+            debounce: Duration::from_millis(20),
+            long_press: Duration::from_millis(800),
+            double_click: Some(Duration::from_millis(300)),
+            repeat: Some(Duration::from_millis(200)),
+        }
+    }
+}
+
+// `Config` in cycles
+struct Cycles {
+    debounce: u32,
+    long_press: u32,
+    double_click: Option<u32>,
+    repeat: Option<u32>,
+}
+
+fn cycles(d: Duration, clock: Hertz) -> u32 {
+    let cycles = d.cycles_at(clock);
+    assert!(cycles <= i32::MAX as u64, "duration out of range");
+    cycles as u32
+}
+
+/// The gesture state machine of a button
+pub struct Button {
+    active_low: bool,
+    cycles: Cycles,
+    // the level at the last edge, and its time
+    level: bool,
+    edge: Instant,
+    // debounced
+    pressed: bool,
+    // cycles held (saturating), up to `held_at`
+    held: u32,
+    held_at: Instant,
+    // `LongPress` reported, then the number of `Repeat`s
+    long: bool,
+    repeats: u16,
+    // a `Click` waiting for the `double_click` to pass, from the release
+    click: Option<Instant>,
+    // the press is the second of a `DoubleClick` (no `Click` at the release)
+    second: bool,
+    // reported by the next `poll`
+    events: Events,
+}
+
+impl Button {
+    /// A released button, its timing converted to cycles of `clock` (CYCCNT
+    /// runs at SYSCLK)
+    ///
+    /// # Panics
+    ///
+    /// If a duration exceeds 2^31 cycles (the order of `Instant`s).
+    pub fn new(config: Config, clock: Hertz) -> Self {
+        let released = config.active_low;
+        Button {
+            active_low: config.active_low,
+            cycles: Cycles {
+                debounce: cycles(config.debounce, clock),
+                long_press: cycles(config.long_press, clock),
+                double_click: config.double_click.map(|d| cycles(d, clock)),
+                repeat: config.repeat.map(|d| cycles(d, clock)),
+            },
+            level: released,
+            edge: Instant::from_cycles(0),
+            pressed: false,
+            held: 0,
+            held_at: Instant::from_cycles(0),
+            long: false,
+            repeats: 0,
+            click: None,
+            second: false,
+            events: Events::new(),
+        }
+    }
+
+    /// Records an edge, at `at` to the level `high` (the pin level read in the
+    /// EXTI handler)
+    ///
+    /// The events up to the edge are kept for the next `poll`, so no gesture is
+    /// lost between two polls.
+    pub fn edge(&mut self, at: Instant, high: bool) {
+        self.update(at);
+        if high != self.level {
+            self.level = high;
+            self.edge = at;
+        }
+    }
+
+    /// The debounced state
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// The events up to `now`
+    pub fn poll(&mut self, now: Instant) -> Events {
+        self.update(now);
+        mem::replace(&mut self.events, Events::new())
+    }
+
+    fn update(&mut self, now: Instant) {
+        let mut events = mem::replace(&mut self.events, Events::new());
+        let mut emit = |event| {
+            // (a full queue, polled too late, drops the newest events)
+            events.push(event).ok();
+        };
+
+        let pressed = self.level != self.active_low;
+        if pressed != self.pressed && self.edge > now {
+            // an edge recorded after `now` was read, handled by the next poll
+            self.events = events;
+            return;
+        }
+        if pressed != self.pressed && now.cycles_since(self.edge) >= self.cycles.debounce {
+            // the level has been stable since the edge
+            self.pressed = pressed;
+            if pressed {
+                self.press(self.edge, &mut emit);
+            } else {
+                self.release(self.edge, &mut emit);
+            }
+        }
+
+        if self.pressed {
+            // held until the release edge, if not yet debounced
+            let until = if pressed { now } else { self.edge };
+            if until > self.held_at {
+                // (polled more often than every 2^31 cycles, held for longer)
+                let more = until.cycles_since(self.held_at);
+                self.held = self.held.saturating_add(more);
+                self.held_at = until;
+            }
+            let held = self.held;
+            if !self.long && held >= self.cycles.long_press {
+                self.long = true;
+                emit(Event::LongPress);
+            }
+            if let (true, Some(repeat)) = (self.long, self.cycles.repeat) {
+                // a late poll skips repeats
+                let n = ((held - self.cycles.long_press) / repeat).min(u16::MAX as u32) as u16;
+                if n > self.repeats {
+                    self.repeats = n;
+                    emit(Event::Repeat(n));
+                }
+            }
+        } else {
+            self.expire(now, &mut emit);
+        }
+        self.events = events;
+    }
+
+    fn press(&mut self, at: Instant, emit: &mut impl FnMut(Event)) {
+        // a click released long enough before this press
+        self.expire(at, emit);
+        emit(Event::Press);
+        self.held = 0;
+        self.held_at = at;
+        self.long = false;
+        self.repeats = 0;
+        self.second = self.click.take().is_some();
+        if self.second {
+            emit(Event::DoubleClick);
+        }
+    }
+
+    fn release(&mut self, at: Instant, emit: &mut impl FnMut(Event)) {
+        emit(Event::Release);
+        if self.long || self.second {
+            return;
+        }
+        if self.cycles.double_click.is_some() {
+            self.click = Some(at);
+        } else {
+            emit(Event::Click);
+        }
+    }
+
+    // the `Click` waiting since before `now`, if no press can follow
+    fn expire(&mut self, now: Instant, emit: &mut impl FnMut(Event)) {
+        if let (Some(released), Some(double)) = (self.click, self.cycles.double_click) {
+            if now.cycles_since(released) >= double {
+                self.click = None;
+                emit(Event::Click);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1 cycle per ms, times in ms
+    const CLOCK: Hertz = Hertz(1_000);
+
+    fn at(ms: u32) -> Instant {
+        Instant::from_cycles(ms)
+    }
+
+    // edges `(ms, high)`, polled every 5ms up to `end`, the events with the
+    // time of the poll reporting them
+    fn run(config: Config, edges: &[(u32, bool)], end: u32) -> std::vec::Vec<(u32, Event)> {
+        let mut button = Button::new(config, CLOCK);
+        let mut edges = edges.iter().peekable();
+        let mut events = std::vec::Vec::new();
+        for now in (0..=end).step_by(5) {
+            while let Some(&&(t, high)) = edges.peek() {
+                if t > now {
+                    break;
+                }
+                button.edge(at(t), high);
+                edges.next();
+            }
+            events.extend(button.poll(at(now)).iter().map(|&e| (now, e)));
+        }
+        events
+    }
+
+    // a press from `down` to `up` (ms), bouncing for 3ms at each edge
+    fn press(down: u32, up: u32) -> [(u32, bool); 8] {
+        [
+            (down, false),
+            (down + 1, true),
+            (down + 2, false),
+            (down + 3, false),
+            (up, true),
+            (up + 1, false),
+            (up + 2, true),
+            (up + 3, true),
+        ]
+    }
+
+    #[test]
+    fn click() {
+        let events = run(Config::default(), &press(100, 200), 600);
+        assert_eq!(
+            events,
+            [
+                // stable from 102 (the last edge) + 20ms
+                (125, Event::Press),
+                (225, Event::Release),
+                // 202 + 300ms without a press
+                (505, Event::Click),
+            ]
+        );
+    }
+
+    #[test]
+    fn click_without_double_click() {
+        let config = Config {
+            double_click: None,
+            ..Config::default()
+        };
+        let events = run(config, &press(100, 200), 600);
+        let events: std::vec::Vec<_> = events.iter().map(|&(_, e)| e).collect();
+        assert_eq!(events, [Event::Press, Event::Release, Event::Click]);
+    }
+
+    #[test]
+    fn double_click() {
+        let mut edges = press(100, 200).to_vec();
+        edges.extend_from_slice(&press(400, 500));
+        let events = run(Config::default(), &edges, 1000);
+        assert_eq!(
+            events,
+            [
+                (125, Event::Press),
+                (225, Event::Release),
+                (425, Event::Press),
+                (425, Event::DoubleClick),
+                (525, Event::Release),
+            ]
+        );
+    }
+
+    #[test]
+    fn two_clicks() {
+        // the second press after the double click time
+        let mut edges = press(100, 200).to_vec();
+        edges.extend_from_slice(&press(600, 700));
+        let events = run(Config::default(), &edges, 1100);
+        let clicks = events.iter().filter(|&&(_, e)| e == Event::Click);
+        assert_eq!(clicks.count(), 2);
+        assert!(!events.iter().any(|&(_, e)| e == Event::DoubleClick));
+    }
+
+    #[test]
+    fn long_press_and_repeat() {
+        let events = run(Config::default(), &press(100, 1450), 2000);
+        assert_eq!(
+            events,
+            [
+                (125, Event::Press),
+                // 102 + 800ms
+                (905, Event::LongPress),
+                (1105, Event::Repeat(1)),
+                (1305, Event::Repeat(2)),
+                // no click after a long press
+                (1475, Event::Release),
+            ]
+        );
+    }
+
+    #[test]
+    fn glitch() {
+        // shorter than the debounce time
+        let events = run(Config::default(), &[(100, false), (110, true)], 500);
+        assert_eq!(events, []);
+    }
+
+    #[test]
+    fn late_poll() {
+        // the release and second press of a double click, between two polls
+        let mut button = Button::new(Config::default(), CLOCK);
+        button.edge(at(100), false);
+        assert_eq!(button.poll(at(130)), [Event::Press]);
+        button.edge(at(200), true);
+        button.edge(at(350), false);
+        assert_eq!(
+            button.poll(at(900)),
+            [Event::Release, Event::Press, Event::DoubleClick]
+        );
+        // held from 350, repeats skipped
+        assert_eq!(button.poll(at(2000)), [Event::LongPress, Event::Repeat(4)]);
+    }
+
+    #[test]
+    fn edge_after_poll() {
+        // an edge recorded between reading the time and the poll
+        let mut button = Button::new(Config::default(), CLOCK);
+        button.edge(at(105), false);
+        assert_eq!(button.poll(at(100)), []);
+        assert_eq!(button.poll(at(130)), [Event::Press]);
+    }
+
+    #[test]
+    fn held_past_wraparound() {
+        // a repeat every 4_000 s, polled every 2^30 cycles (ms)
+        let config = Config {
+            repeat: Some(Duration::from_secs(4_000)),
+            ..Config::default()
+        };
+        let mut button = Button::new(config, CLOCK);
+        let t = |n: u32| at(n.wrapping_mul(1 << 30));
+        button.edge(t(0), false);
+        let mut events = std::vec::Vec::new();
+        for n in 1..=5 {
+            events.extend(button.poll(t(n)));
+        }
+        // held for 2^32 cycles and more, counted as 2^32 - 1
+        assert_eq!(
+            events,
+            [
+                Event::Press,
+                Event::LongPress,
+                Event::Repeat(268),
+                Event::Repeat(536),
+                Event::Repeat(805),
+                Event::Repeat(1073),
+            ]
+        );
+        assert!(button.is_pressed());
+        button.edge(t(5), true);
+        assert_eq!(button.poll(at(t(5).cycles() + 30)), [Event::Release]);
+    }
+
+    #[test]
+    fn wraparound() {
+        let mut button = Button::new(Config::default(), CLOCK);
+        button.edge(at(u32::MAX - 5), false);
+        assert_eq!(button.poll(at(30)), [Event::Press]);
+        assert!(button.is_pressed());
+    }
+}
@@ -0,0 +1,35 @@
+//! Library support for the examples
+//!
+//! The modules are `no_std` and keep the hardware access apart from the logic,
+//! so that the logic can be tested on the host.
+//!
+//! > cargo test --lib --target x86_64-unknown-linux-gnu
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod adc;
+pub mod bench;
+pub mod blinker;
+pub mod blinky;
+pub mod bus;
+pub mod capture;
+pub mod cli;
+pub mod clocks;
+pub mod crashlog;
+pub mod exti;
+pub mod fault;
+pub mod global;
+pub mod input;
+// the panic handler, not on the host (`std` provides one)
+#[cfg(not(test))]
+pub mod panic;
+pub mod pwm;
+pub mod regs;
+pub mod reset;
+pub mod time;
+pub mod trace;
+pub mod usart;
+pub mod usart_dma;
+pub mod vectors;
+pub mod volatile;
+pub mod watchdog;
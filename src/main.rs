@@ -3,7 +3,7 @@
 #![no_main]
 #![no_std]
 
-extern crate panic_halt;
+use app as _;
 
 use cortex_m_rt::entry;
 use cortex_m_semihosting::hprintln;
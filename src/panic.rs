@@ -0,0 +1,106 @@
+//! The panic handler, its behavior selected by cargo features
+//!
+//! In place of linking one of the `panic-halt`, `panic-semihosting` and
+//! `panic-itm` crates (editing the example, and `Cargo.toml`), the behavior is
+//! chosen when building:
+//!
+//! > cargo run --example panic --features panic-semihosting
+//!
+//! Features can be combined, the panic is then reported, in order:
+//! - `panic-log`, in the crash log (`.noinit` RAM, see `crashlog.rs`)
+//! - `panic-itm`, over ITM (stimulus port 0)
+//! - `panic-semihosting`, on the host console (a debugger, or QEMU, needed)
+//! - `panic-bkpt`, by a breakpoint (without a debugger, a HardFault)
+//!
+//! and ends by:
+//! - `panic-semihosting` on the `qemu` board, exiting QEMU (status 1)
+//! - `panic-reset`, a system reset
+//! - `panic-halt` (or no feature given), halting (in an endless loop)
+//!
+//! E.g., `--features "panic-log panic-reset"` logs the panic, then resets.
+//! An example links the handler by `use app as _;`.
+
+#[cfg(all(feature = "panic-halt", feature = "panic-reset"))]
+compile_error!("select at most one of the `panic-halt` and `panic-reset` features");
+
+#[cfg(feature = "panic-log")]
+use core::mem::MaybeUninit;
+use core::panic::PanicInfo;
+use core::slice;
+#[cfg(not(feature = "panic-reset"))]
+use core::sync::atomic::{self, Ordering};
+
+use cortex_m::{interrupt, register::msp};
+
+#[cfg(feature = "panic-log")]
+use crate::crashlog::CrashLog;
+use crate::crashlog::STACK_WORDS;
+
+// not initialized by the startup code
+#[cfg(feature = "panic-log")]
+#[link_section = ".noinit"]
+static mut LOG: MaybeUninit<CrashLog> = MaybeUninit::uninit();
+
+/// The crash log the panic is recorded in (`panic-log` feature)
+///
+/// # Safety
+///
+/// Not reentrant, it may be used by one context at a time (e.g., `main`, and
+/// the panic and `HardFault` handlers, which never return).
+#[cfg(feature = "panic-log")]
+pub unsafe fn log() -> &'static mut CrashLog {
+    &mut *LOG.as_mut_ptr()
+}
+
+/// The words on the (main) stack, from the stack pointer up (at most
+/// `STACK_WORDS`)
+pub fn stack() -> &'static [u32] {
+    extern "C" {
+        // top of the stack, see `cortex-m-rt` link.x
+        static _stack_start: u32;
+    }
+    let sp = msp::read() as usize;
+    let top = unsafe { &_stack_start as *const u32 as usize };
+    let words = (top.saturating_sub(sp) / 4).min(STACK_WORDS);
+    unsafe { slice::from_raw_parts(sp as *const u32, words) }
+}
+
+#[inline(never)]
+#[panic_handler]
+#[allow(unused_variables)]
+fn panic(info: &PanicInfo) -> ! {
+    interrupt::disable();
+
+    #[cfg(feature = "panic-log")]
+    unsafe { log() }.record_panic(info, stack());
+
+    #[cfg(feature = "panic-itm")]
+    {
+        let itm = unsafe { &mut *cortex_m::peripheral::ITM::ptr() };
+        cortex_m::iprintln!(&mut itm.stim[0], "{}", info);
+    }
+
+    #[cfg(feature = "panic-semihosting")]
+    {
+        use core::fmt::Write;
+        if let Ok(mut hstdout) = cortex_m_semihosting::hio::hstdout() {
+            writeln!(hstdout, "{}", info).ok();
+        }
+    }
+
+    // the run ends, instead of halting until the timeout (`qemu-tests`)
+    #[cfg(all(feature = "panic-semihosting", feature = "qemu"))]
+    cortex_m_semihosting::debug::exit(cortex_m_semihosting::debug::EXIT_FAILURE);
+
+    #[cfg(feature = "panic-bkpt")]
+    cortex_m::asm::bkpt();
+
+    #[cfg(feature = "panic-reset")]
+    cortex_m::peripheral::SCB::sys_reset();
+
+    #[cfg(not(feature = "panic-reset"))]
+    loop {
+        // a side effect, so the loop is not turned into a UDF instruction
+        atomic::compiler_fence(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,433 @@
+//! PWM output on the general purpose timers (TIM2..5), RM0368 13.3.10
+//!
+//! `bare4.rs` and `rtfm_blinky.rs` switch the LED (PA5) fully on or off by
+//! `BSRR`. PA5 is also channel 1 of TIM2 (alternate function 1), so the timer
+//! can drive it instead, at a frequency and a duty cycle (the brightness):
+//!
+//! ``` ignore
+//! let pwm = Pwm::new(bus, Timer::Tim2, Hertz(plan.timclk1()), Hertz::khz(1))?;
+//! pwm.output(PA5, AF_TIM1_2);
+//! pwm.enable(Channel::C1, Polarity::ActiveHigh);
+//! pwm.set_duty_percent(Channel::C1, 25)?;
+//! ```
+//!
+//! The timer counts (`CNT`) from 0 to `ARR` at the timer clock divided by the
+//! prescaler, with the output active while `CNT < CCRx` (PWM mode 1). The
+//! prescaler is the smallest one for which the period fits `ARR` (16 bit for
+//! TIM3/4, 32 bit for TIM2/5), giving the finest duty cycle steps.
+//!
+//! The four channels of a timer share the frequency. TIM2..5 have no
+//! complementary (`CHxN`) outputs, a complementary pair is two channels of the
+//! same duty, one of them `ActiveLow` (without dead time).
+//!
+//! `set_pulse` gives the duty as a pulse length, e.g., a servo at 50 Hz with
+//! pulses of 1 to 2 ms, and `breathe` the duty of a breathing (fading) LED.
+
+use core::mem::offset_of;
+
+use crate::bus::RegisterBus;
+use crate::exti::{Pin, Port};
+use crate::regs::{
+    Gpio, Rcc, Tim, APB1ENR_TIM2EN, APB1ENR_TIM3EN, APB1ENR_TIM4EN, APB1ENR_TIM5EN, CCER_CCE,
+    CCER_CCP, CCMR_OCM, CCMR_OCPE, CR1_ARPE, CR1_CEN, EGR_UG, MODER_ALTERNATE, OCM_PWM1, RCC, TIM2,
+    TIM3, TIM4, TIM5,
+};
+use crate::time::{Duration, Hertz};
+use crate::volatile::Field;
+
+#[rustfmt::skip]
+pub mod address {
+    use super::*;
+
+    pub const RCC_AHB1ENR: u32      = RCC.reg(offset_of!(Rcc, AHB1ENR));
+    pub const RCC_APB1ENR: u32      = RCC.reg(offset_of!(Rcc, APB1ENR));
+}
+
+use address::*;
+
+/// The user LED (LD2), TIM2_CH1 in alternate function 1
+pub const PA5: Pin = Pin::new(Port::A, 5);
+
+const PSC_MAX: u32 = 0x1_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// zero, above half the timer clock, or too low for the prescaler
+    Frequency,
+    /// above 100%
+    Duty,
+    /// longer than the period
+    Pulse,
+}
+
+/// A general purpose timer, on APB1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timer {
+    Tim2,
+    Tim3,
+    Tim4,
+    Tim5,
+}
+
+impl Timer {
+    /// Address of the register at `offset` of the timer
+    pub const fn reg(self, offset: usize) -> u32 {
+        match self {
+            Timer::Tim2 => TIM2.reg(offset),
+            Timer::Tim3 => TIM3.reg(offset),
+            Timer::Tim4 => TIM4.reg(offset),
+            Timer::Tim5 => TIM5.reg(offset),
+        }
+    }
+
+    /// Clock enable bit in `RCC_APB1ENR`
+    pub const fn enable(self) -> Field {
+        match self {
+            Timer::Tim2 => APB1ENR_TIM2EN,
+            Timer::Tim3 => APB1ENR_TIM3EN,
+            Timer::Tim4 => APB1ENR_TIM4EN,
+            Timer::Tim5 => APB1ENR_TIM5EN,
+        }
+    }
+
+    /// Largest counter value, TIM2 and TIM5 are 32 bit
+    pub const fn counter_max(self) -> u32 {
+        match self {
+            Timer::Tim2 | Timer::Tim5 => u32::MAX,
+            Timer::Tim3 | Timer::Tim4 => 0xFFFF,
+        }
+    }
+
+    /// Largest `ARR`, leaving room for a `CCR` of `ARR + 1` (always active)
+    pub const fn arr_max(self) -> u32 {
+        self.counter_max() - 1
+    }
+}
+
+/// A channel of a timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    C1 = 0,
+    C2 = 1,
+    C3 = 2,
+    C4 = 3,
+}
+
+/// Output level while the duty lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Prescaler (divisor, `PSC + 1`) and `ARR` for `hz`, from `timclk`
+pub fn timing(timclk: u32, hz: u32, arr_max: u32) -> Result<(u32, u32), Error> {
+    if hz == 0 || hz > timclk / 2 {
+        return Err(Error::Frequency);
+    }
+    let cycles = timclk / hz;
+    let psc = (cycles as u64).div_ceil(arr_max as u64 + 1) as u32;
+    if psc > PSC_MAX {
+        return Err(Error::Frequency);
+    }
+    Ok((psc, cycles / psc - 1))
+}
+
+/// Powers on the port of `pin`, and connects it to alternate function `af`
+pub fn alternate<B: RegisterBus>(bus: &B, pin: Pin, af: u32) {
+    bus.modify_u32(RCC_AHB1ENR, 0, 1 << pin.port.index());
+    let afr = if pin.pin < 8 {
+        offset_of!(Gpio, AFRL)
+    } else {
+        offset_of!(Gpio, AFRH)
+    };
+    let field = Gpio::afr(pin.pin);
+    bus.modify_u32(pin.port.reg(afr), field.mask(), field.val(af).bits());
+    let field = Gpio::moder(pin.pin);
+    bus.modify_u32(
+        pin.port.reg(offset_of!(Gpio, MODER)),
+        field.mask(),
+        field.val(MODER_ALTERNATE).bits(),
+    );
+}
+
+/// PWM output, over a `RegisterBus`
+pub struct Pwm<B: RegisterBus> {
+    bus: B,
+    timer: Timer,
+    timclk: u32,
+    // divisor, `PSC + 1`
+    psc: u32,
+    arr: u32,
+}
+
+impl<B: RegisterBus> Pwm<B> {
+    /// Powers on and starts `timer` at `hz`, clocked by `timclk` (twice PCLK1
+    /// if APB1 is divided, `Plan::timclk1`), all channels disabled
+    pub fn new(bus: B, timer: Timer, timclk: Hertz, hz: Hertz) -> Result<Self, Error> {
+        let (psc, arr) = timing(timclk.0, hz.0, timer.arr_max())?;
+        bus.modify_u32(RCC_APB1ENR, 0, timer.enable().mask());
+        let pwm = Pwm {
+            bus,
+            timer,
+            timclk: timclk.0,
+            psc,
+            arr,
+        };
+        pwm.write_timing();
+        // ARR preloaded, so a frequency change takes effect at the next period
+        pwm.bus.write_u32(
+            pwm.reg(offset_of!(Tim, CR1)),
+            CR1_ARPE.mask() | CR1_CEN.mask(),
+        );
+        Ok(pwm)
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    fn reg(&self, offset: usize) -> u32 {
+        self.timer.reg(offset)
+    }
+
+    fn write_timing(&self) {
+        self.bus
+            .write_u32(self.reg(offset_of!(Tim, PSC)), self.psc - 1);
+        self.bus.write_u32(self.reg(offset_of!(Tim, ARR)), self.arr);
+        // load the prescaler (only taken at an update event)
+        self.bus
+            .write_u32(self.reg(offset_of!(Tim, EGR)), EGR_UG.mask());
+    }
+
+    /// Configures `pin` as output of alternate function `af` (`AF_TIM1_2` for
+    /// TIM2, `AF_TIM3_5` for TIM3..5), e.g., `PA5` for TIM2_CH1
+    pub fn output(&self, pin: Pin, af: u32) {
+        alternate(&self.bus, pin, af);
+    }
+
+    /// Sets the frequency of all channels, keeping their duty cycles
+    pub fn set_frequency(&mut self, hz: Hertz) -> Result<(), Error> {
+        let (psc, arr) = timing(self.timclk, hz.0, self.timer.arr_max())?;
+        let old = self.max_duty() as u64;
+        let duties = [Channel::C1, Channel::C2, Channel::C3, Channel::C4].map(|c| self.duty(c));
+        self.psc = psc;
+        self.arr = arr;
+        self.write_timing();
+        for (channel, &duty) in duties.iter().enumerate() {
+            let duty = (duty as u64 * self.max_duty() as u64 / old) as u32;
+            let ccr = self.reg(Tim::ccr_offset(channel as u8));
+            self.bus.write_u32(ccr, duty);
+        }
+        Ok(())
+    }
+
+    /// The frequency, rounded down to whole Hz
+    pub fn frequency(&self) -> Hertz {
+        Hertz(self.timclk / self.psc / (self.arr + 1))
+    }
+
+    /// Starts PWM (mode 1) output on `channel`
+    pub fn enable(&self, channel: Channel, polarity: Polarity) {
+        let ch = channel as u8;
+        let field = Tim::ccmr(ch);
+        // output compare, CCR preloaded (duty changes at the next period)
+        let mode = CCMR_OCM.val(OCM_PWM1).bits() | CCMR_OCPE.mask();
+        let ccmr = self.reg(Tim::ccmr_offset(ch));
+        self.bus
+            .modify_u32(ccmr, field.mask(), mode << field.offset());
+
+        let field = Tim::ccer(ch);
+        let low = match polarity {
+            Polarity::ActiveHigh => 0,
+            Polarity::ActiveLow => CCER_CCP.mask(),
+        };
+        let ccer = self.reg(offset_of!(Tim, CCER));
+        let bits = (CCER_CCE.mask() | low) << field.offset();
+        self.bus.modify_u32(ccer, field.mask(), bits);
+    }
+
+    /// Stops the output of `channel` (the pin floats, unless pulled)
+    pub fn disable(&self, channel: Channel) {
+        let field = Tim::ccer(channel as u8);
+        let ccer = self.reg(offset_of!(Tim, CCER));
+        self.bus
+            .modify_u32(ccer, CCER_CCE.mask() << field.offset(), 0);
+    }
+
+    /// The duty of 100%
+    pub fn max_duty(&self) -> u32 {
+        self.arr + 1
+    }
+
+    /// Sets the duty, in timer ticks (clamped to `max_duty`)
+    pub fn set_duty(&self, channel: Channel, duty: u32) {
+        let ccr = self.reg(Tim::ccr_offset(channel as u8));
+        self.bus.write_u32(ccr, duty.min(self.max_duty()));
+    }
+
+    pub fn duty(&self, channel: Channel) -> u32 {
+        self.bus.read_u32(self.reg(Tim::ccr_offset(channel as u8)))
+    }
+
+    /// Sets the duty cycle, in percent
+    pub fn set_duty_percent(&self, channel: Channel, percent: u32) -> Result<(), Error> {
+        if percent > 100 {
+            return Err(Error::Duty);
+        }
+        let duty = self.max_duty() as u64 * percent as u64 / 100;
+        self.set_duty(channel, duty as u32);
+        Ok(())
+    }
+
+    /// Sets the duty as a pulse length, e.g., 1.5 ms to center a servo
+    pub fn set_pulse(&self, channel: Channel, pulse: Duration) -> Result<(), Error> {
+        let ticks = pulse.cycles_at(Hertz(self.timclk)) / self.psc as u64;
+        if ticks > self.max_duty() as u64 {
+            return Err(Error::Pulse);
+        }
+        self.set_duty(channel, ticks as u32);
+        Ok(())
+    }
+}
+
+/// The duty (of `max`) at `step` of a breath of `steps`, fading in and out
+///
+/// The brightness seen grows about as the square root of the duty, so the duty
+/// follows a square (of a triangle), for an even fade.
+pub fn breathe(step: u32, steps: u32, max: u32) -> u32 {
+    let half = steps / 2;
+    if half == 0 {
+        return max;
+    }
+    let step = step % steps;
+    let x = if step < half { step } else { steps - step }.min(half) as u64;
+    let half = half as u64;
+    (max as u64 * x * x / (half * half)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Access::*, MemoryBus};
+    use crate::regs::{AF_TIM1_2, GPIOA};
+
+    const TIMCLK: Hertz = Hertz::mhz(16);
+
+    fn tim2(offset: usize) -> u32 {
+        Timer::Tim2.reg(offset)
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(tim2(offset_of!(Tim, CCR)), 0x4000_0034);
+        assert_eq!(Timer::Tim5.reg(offset_of!(Tim, ARR)), 0x4000_0C2C);
+        assert_eq!(Timer::Tim4.reg(Tim::ccr_offset(3)), 0x4000_0840);
+    }
+
+    #[test]
+    fn timings() {
+        // (timer, timclk, Hz) -> (prescaler, ARR)
+        #[rustfmt::skip]
+        let table = [
+            (Timer::Tim2, 16_000_000, 1_000,    Ok((1, 15_999))),
+            (Timer::Tim3, 16_000_000, 1_000,    Ok((1, 15_999))),
+            // 16 bit, divided
+            (Timer::Tim3, 16_000_000, 50,       Ok((5, 63_999))),
+            (Timer::Tim4, 84_000_000, 1,        Ok((1282, 65_521))),
+            // ARR at the limit, then divided (no ARR of 0xFFFF, CCR = ARR + 1)
+            (Timer::Tim3, 65_535_000, 1_000,    Ok((1, 65_534))),
+            (Timer::Tim3, 65_536_000, 1_000,    Ok((2, 32_767))),
+            (Timer::Tim5, 84_000_000, 1,        Ok((1, 83_999_999))),
+            (Timer::Tim2, 16_000_000, 8_000_000, Ok((1, 1))),
+            (Timer::Tim2, 16_000_000, 0,        Err(Error::Frequency)),
+            (Timer::Tim2, 16_000_000, 9_000_000, Err(Error::Frequency)),
+        ];
+        for &(timer, timclk, hz, expected) in &table {
+            assert_eq!(
+                timing(timclk, hz, timer.arr_max()),
+                expected,
+                "{:?} {}",
+                timer,
+                hz
+            );
+        }
+        // beyond the prescaler, out of reach for 16 bit timers (below 1 Hz)
+        assert_eq!(timing(84_000_000, 1, 0xFF), Err(Error::Frequency));
+        assert_eq!(timing(16_777_216, 1, 0xFF), Ok((65_536, 255)));
+    }
+
+    #[test]
+    fn led() {
+        let pwm = Pwm::new(MemoryBus::new(), Timer::Tim2, TIMCLK, Hertz::khz(1)).unwrap();
+        pwm.output(PA5, AF_TIM1_2);
+        pwm.enable(Channel::C1, Polarity::ActiveHigh);
+        pwm.set_duty_percent(Channel::C1, 25).unwrap();
+
+        let bus = pwm.bus();
+        assert_eq!(bus.peek(RCC_APB1ENR), 1 << 0);
+        assert_eq!(bus.peek(tim2(offset_of!(Tim, PSC))), 0);
+        assert_eq!(bus.peek(tim2(offset_of!(Tim, ARR))), 15_999);
+        assert!(bus
+            .accesses()
+            .contains(&Write(tim2(offset_of!(Tim, EGR)), 1)));
+        assert_eq!(bus.peek(tim2(offset_of!(Tim, CR1))), 0x81);
+        // AF1, alternate mode
+        assert_eq!(bus.peek(GPIOA.reg(offset_of!(Gpio, AFRL))), 1 << 20);
+        assert_eq!(bus.peek(GPIOA.reg(offset_of!(Gpio, MODER))), 0b10 << 10);
+        // OC1M = PWM mode 1, OC1PE
+        assert_eq!(bus.peek(tim2(offset_of!(Tim, CCMR1))), 0x68);
+        assert_eq!(bus.peek(tim2(offset_of!(Tim, CCER))), 0x1);
+        assert_eq!(pwm.duty(Channel::C1), 4_000);
+        assert_eq!(pwm.frequency(), Hertz(1_000));
+        assert_eq!(pwm.set_duty_percent(Channel::C1, 101), Err(Error::Duty));
+    }
+
+    #[test]
+    fn channels() {
+        let pwm = Pwm::new(MemoryBus::new(), Timer::Tim3, TIMCLK, Hertz::khz(1)).unwrap();
+        // a complementary pair on 2 and 3
+        pwm.enable(Channel::C2, Polarity::ActiveHigh);
+        pwm.enable(Channel::C3, Polarity::ActiveLow);
+        pwm.enable(Channel::C4, Polarity::ActiveHigh);
+        pwm.disable(Channel::C4);
+
+        let bus = pwm.bus();
+        let tim3 = |offset| Timer::Tim3.reg(offset);
+        assert_eq!(bus.peek(tim3(offset_of!(Tim, CCMR1))), 0x6800);
+        assert_eq!(bus.peek(tim3(offset_of!(Tim, CCMR2))), 0x6868);
+        assert_eq!(bus.peek(tim3(offset_of!(Tim, CCER))), 0x0310);
+
+        pwm.set_duty(Channel::C2, 100_000);
+        assert_eq!(pwm.duty(Channel::C2), pwm.max_duty());
+    }
+
+    #[test]
+    fn frequency_change() {
+        let mut pwm = Pwm::new(MemoryBus::new(), Timer::Tim2, TIMCLK, Hertz::khz(1)).unwrap();
+        pwm.set_duty_percent(Channel::C2, 50).unwrap();
+        pwm.set_frequency(Hertz::khz(4)).unwrap();
+        assert_eq!(pwm.max_duty(), 4_000);
+        assert_eq!(pwm.duty(Channel::C2), 2_000);
+        assert_eq!(pwm.set_frequency(Hertz(0)), Err(Error::Frequency));
+    }
+
+    #[test]
+    fn servo() {
+        let pwm = Pwm::new(MemoryBus::new(), Timer::Tim3, TIMCLK, Hertz(50)).unwrap();
+        // prescaler 5, 3.2 MHz ticks
+        pwm.set_pulse(Channel::C1, Duration::from_micros(1_500))
+            .unwrap();
+        assert_eq!(pwm.duty(Channel::C1), 4_800);
+        let pulse = pwm.set_pulse(Channel::C1, Duration::from_millis(21));
+        assert_eq!(pulse, Err(Error::Pulse));
+    }
+
+    #[test]
+    fn breath() {
+        let levels: [u32; 8] = [0, 1, 2, 3, 4, 5, 6, 7].map(|step| breathe(step, 8, 16));
+        assert_eq!(levels, [0, 1, 4, 9, 16, 9, 4, 1]);
+        assert_eq!(breathe(8, 8, 16), 0);
+        assert_eq!(breathe(0, 1, 16), 16);
+    }
+}
@@ -0,0 +1,849 @@
+//! STM32F401 register blocks (RCC, PWR, FLASH, GPIO, USART, SYSCFG, EXTI, DMA, TIM,
+//! ADC, IWDG, WWDG)
+//!
+//! `repr(C)` structs of `VolatileCell<u32>` registers, in the style of the C
+//! `stm32f40x.h` header, but following the STM32F401 register maps of the
+//! Reference Manual RM0368. Each register offset is checked against the manual
+//! at compile time.
+//!
+//! ``` ignore
+//! let rcc = unsafe { RCC.get() };
+//! let gpioa = unsafe { GPIOA.get() };
+//!
+//! rcc.AHB1ENR.modify_value(AHB1ENR_GPIOAEN.val(1));
+//! gpioa.MODER.modify_field(Gpio::moder(5), MODER_OUTPUT);
+//! gpioa.BSRR.write(Gpio::bs(5));
+//! ```
+
+use core::marker::PhantomData;
+use core::mem::offset_of;
+
+use crate::volatile::{Field, VolatileCell};
+
+#[rustfmt::skip]
+pub mod address {
+    pub const PERIPH_BASE: u32      = 0x4000_0000;
+    pub const APB1PERIPH_BASE: u32  = PERIPH_BASE;
+    pub const APB2PERIPH_BASE: u32  = PERIPH_BASE + 0x0001_0000;
+    pub const AHB1PERIPH_BASE: u32  = PERIPH_BASE + 0x0002_0000;
+
+    pub const TIM2_BASE: u32        = APB1PERIPH_BASE;
+    pub const TIM3_BASE: u32        = APB1PERIPH_BASE + 0x0400;
+    pub const TIM4_BASE: u32        = APB1PERIPH_BASE + 0x0800;
+    pub const TIM5_BASE: u32        = APB1PERIPH_BASE + 0x0C00;
+    pub const WWDG_BASE: u32        = APB1PERIPH_BASE + 0x2C00;
+    pub const IWDG_BASE: u32        = APB1PERIPH_BASE + 0x3000;
+    pub const USART2_BASE: u32      = APB1PERIPH_BASE + 0x4400;
+    pub const PWR_BASE: u32         = APB1PERIPH_BASE + 0x7000;
+    pub const USART1_BASE: u32      = APB2PERIPH_BASE + 0x1000;
+    pub const ADC1_BASE: u32        = APB2PERIPH_BASE + 0x2000;
+    pub const ADC_COMMON_BASE: u32  = ADC1_BASE + 0x0300;
+    pub const USART6_BASE: u32      = APB2PERIPH_BASE + 0x1400;
+    pub const SYSCFG_BASE: u32      = APB2PERIPH_BASE + 0x3800;
+    pub const EXTI_BASE: u32        = APB2PERIPH_BASE + 0x3C00;
+    pub const GPIOA_BASE: u32       = AHB1PERIPH_BASE;
+    pub const GPIOB_BASE: u32       = AHB1PERIPH_BASE + 0x0400;
+    pub const GPIOC_BASE: u32       = AHB1PERIPH_BASE + 0x0800;
+    pub const GPIOD_BASE: u32       = AHB1PERIPH_BASE + 0x0C00;
+    pub const GPIOE_BASE: u32       = AHB1PERIPH_BASE + 0x1000;
+    pub const GPIOH_BASE: u32       = AHB1PERIPH_BASE + 0x1C00;
+    pub const RCC_BASE: u32         = AHB1PERIPH_BASE + 0x3800;
+    pub const FLASH_BASE: u32       = AHB1PERIPH_BASE + 0x3C00;
+    pub const DMA1_BASE: u32        = AHB1PERIPH_BASE + 0x6000;
+    pub const DMA2_BASE: u32        = AHB1PERIPH_BASE + 0x6400;
+}
+
+/// A register block instance, at a fixed address
+pub struct Instance<T> {
+    addr: u32,
+    _block: PhantomData<fn() -> T>,
+}
+
+impl<T> Instance<T> {
+    const fn new(addr: u32) -> Self {
+        Instance {
+            addr,
+            _block: PhantomData,
+        }
+    }
+
+    /// Base address of the block
+    pub const fn addr(&self) -> u32 {
+        self.addr
+    }
+
+    /// Address of a register, `offset` from the base address
+    pub const fn reg(&self, offset: usize) -> u32 {
+        self.addr + offset as u32
+    }
+
+    pub const fn ptr(&self) -> *const T {
+        self.addr as *const T
+    }
+
+    /// # Safety
+    ///
+    /// Only valid on the target. The caller must make sure that the block
+    /// is not concurrently accessed (e.g., by interrupt handlers).
+    pub unsafe fn get(&self) -> &'static T {
+        &*self.ptr()
+    }
+}
+
+#[rustfmt::skip]
+mod instances {
+    use super::*;
+    pub const RCC: Instance<Rcc>        = Instance::new(address::RCC_BASE);
+    pub const PWR: Instance<Pwr>        = Instance::new(address::PWR_BASE);
+    pub const FLASH: Instance<Flash>    = Instance::new(address::FLASH_BASE);
+    pub const GPIOA: Instance<Gpio>     = Instance::new(address::GPIOA_BASE);
+    pub const GPIOB: Instance<Gpio>     = Instance::new(address::GPIOB_BASE);
+    pub const GPIOC: Instance<Gpio>     = Instance::new(address::GPIOC_BASE);
+    pub const GPIOD: Instance<Gpio>     = Instance::new(address::GPIOD_BASE);
+    pub const GPIOE: Instance<Gpio>     = Instance::new(address::GPIOE_BASE);
+    pub const GPIOH: Instance<Gpio>     = Instance::new(address::GPIOH_BASE);
+    pub const USART1: Instance<Usart>   = Instance::new(address::USART1_BASE);
+    pub const USART2: Instance<Usart>   = Instance::new(address::USART2_BASE);
+    pub const USART6: Instance<Usart>   = Instance::new(address::USART6_BASE);
+    pub const SYSCFG: Instance<Syscfg>  = Instance::new(address::SYSCFG_BASE);
+    pub const EXTI: Instance<Exti>      = Instance::new(address::EXTI_BASE);
+    pub const DMA1: Instance<Dma>       = Instance::new(address::DMA1_BASE);
+    pub const DMA2: Instance<Dma>       = Instance::new(address::DMA2_BASE);
+    pub const TIM2: Instance<Tim>       = Instance::new(address::TIM2_BASE);
+    pub const TIM3: Instance<Tim>       = Instance::new(address::TIM3_BASE);
+    pub const TIM4: Instance<Tim>       = Instance::new(address::TIM4_BASE);
+    pub const TIM5: Instance<Tim>       = Instance::new(address::TIM5_BASE);
+    pub const ADC1: Instance<Adc>       = Instance::new(address::ADC1_BASE);
+    pub const ADC_COMMON: Instance<AdcCommon> = Instance::new(address::ADC_COMMON_BASE);
+    pub const IWDG: Instance<Iwdg>      = Instance::new(address::IWDG_BASE);
+    pub const WWDG: Instance<Wwdg>      = Instance::new(address::WWDG_BASE);
+}
+pub use instances::*;
+
+// checks `offset_of!` of each register against the reference manual
+macro_rules! assert_offsets {
+    ($block:ty { $($reg:ident: $offset:expr,)* }) => {
+        $(
+            const _: () = assert!(offset_of!($block, $reg) == $offset);
+        )*
+    };
+}
+
+/// Reset and clock control, RM0368 6.3
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Rcc {
+    pub CR:         VolatileCell<u32>,      // clock control
+    pub PLLCFGR:    VolatileCell<u32>,      // PLL configuration
+    pub CFGR:       VolatileCell<u32>,      // clock configuration
+    pub CIR:        VolatileCell<u32>,      // clock interrupt
+    pub AHB1RSTR:   VolatileCell<u32>,      // AHB1 peripheral reset
+    pub AHB2RSTR:   VolatileCell<u32>,      // AHB2 peripheral reset
+    _reserved0:     [u32; 2],
+    pub APB1RSTR:   VolatileCell<u32>,      // APB1 peripheral reset
+    pub APB2RSTR:   VolatileCell<u32>,      // APB2 peripheral reset
+    _reserved1:     [u32; 2],
+    pub AHB1ENR:    VolatileCell<u32>,      // AHB1 peripheral clock enable
+    pub AHB2ENR:    VolatileCell<u32>,      // AHB2 peripheral clock enable
+    _reserved2:     [u32; 2],
+    pub APB1ENR:    VolatileCell<u32>,      // APB1 peripheral clock enable
+    pub APB2ENR:    VolatileCell<u32>,      // APB2 peripheral clock enable
+    _reserved3:     [u32; 2],
+    pub AHB1LPENR:  VolatileCell<u32>,      // AHB1 peripheral clock enable, low power mode
+    pub AHB2LPENR:  VolatileCell<u32>,      // AHB2 peripheral clock enable, low power mode
+    _reserved4:     [u32; 2],
+    pub APB1LPENR:  VolatileCell<u32>,      // APB1 peripheral clock enable, low power mode
+    pub APB2LPENR:  VolatileCell<u32>,      // APB2 peripheral clock enable, low power mode
+    _reserved5:     [u32; 2],
+    pub BDCR:       VolatileCell<u32>,      // backup domain control
+    pub CSR:        VolatileCell<u32>,      // clock control & status
+    _reserved6:     [u32; 2],
+    pub SSCGR:      VolatileCell<u32>,      // spread spectrum clock generation
+    pub PLLI2SCFGR: VolatileCell<u32>,      // PLLI2S configuration
+    _reserved7:     u32,
+    pub DCKCFGR:    VolatileCell<u32>,      // dedicated clocks configuration
+}
+
+#[rustfmt::skip]
+assert_offsets!(Rcc {
+    CR:         0x00,
+    PLLCFGR:    0x04,
+    CFGR:       0x08,
+    CIR:        0x0C,
+    AHB1RSTR:   0x10,
+    AHB2RSTR:   0x14,
+    APB1RSTR:   0x20,
+    APB2RSTR:   0x24,
+    AHB1ENR:    0x30,
+    AHB2ENR:    0x34,
+    APB1ENR:    0x40,
+    APB2ENR:    0x44,
+    AHB1LPENR:  0x50,
+    AHB2LPENR:  0x54,
+    APB1LPENR:  0x60,
+    APB2LPENR:  0x64,
+    BDCR:       0x70,
+    CSR:        0x74,
+    SSCGR:      0x80,
+    PLLI2SCFGR: 0x84,
+    DCKCFGR:    0x8C,
+});
+
+// RCC_CR, RM0368 6.3.1
+pub const CR_HSION: Field = Field::bit(0);
+pub const CR_HSIRDY: Field = Field::bit(1);
+pub const CR_HSEON: Field = Field::bit(16);
+pub const CR_HSERDY: Field = Field::bit(17);
+pub const CR_HSEBYP: Field = Field::bit(18);
+pub const CR_PLLON: Field = Field::bit(24);
+pub const CR_PLLRDY: Field = Field::bit(25);
+// RCC_PLLCFGR, RM0368 6.3.2
+pub const PLLCFGR_PLLM: Field = Field::new(0, 6);
+pub const PLLCFGR_PLLN: Field = Field::new(6, 9);
+pub const PLLCFGR_PLLP: Field = Field::new(16, 2);
+pub const PLLCFGR_PLLSRC: Field = Field::bit(22);
+pub const PLLCFGR_PLLQ: Field = Field::new(24, 4);
+// RCC_CFGR, RM0368 6.3.3
+pub const CFGR_SW: Field = Field::new(0, 2);
+pub const CFGR_SWS: Field = Field::new(2, 2);
+pub const CFGR_HPRE: Field = Field::new(4, 4);
+pub const CFGR_PPRE1: Field = Field::new(10, 3);
+pub const CFGR_PPRE2: Field = Field::new(13, 3);
+pub const CFGR_MCO1: Field = Field::new(21, 2);
+pub const CFGR_MCO1PRE: Field = Field::new(24, 3);
+pub const CFGR_MCO2PRE: Field = Field::new(27, 3);
+pub const CFGR_MCO2: Field = Field::new(30, 2);
+// RCC_CFGR SW/SWS values
+pub const SW_HSI: u32 = 0b00;
+pub const SW_HSE: u32 = 0b01;
+pub const SW_PLL: u32 = 0b10;
+// RCC_AHB1ENR, RM0368 6.3.9
+pub const AHB1ENR_GPIOAEN: Field = Field::bit(0);
+pub const AHB1ENR_GPIOBEN: Field = Field::bit(1);
+pub const AHB1ENR_GPIOCEN: Field = Field::bit(2);
+pub const AHB1ENR_GPIODEN: Field = Field::bit(3);
+pub const AHB1ENR_GPIOEEN: Field = Field::bit(4);
+pub const AHB1ENR_GPIOHEN: Field = Field::bit(7);
+pub const AHB1ENR_DMA1EN: Field = Field::bit(21);
+pub const AHB1ENR_DMA2EN: Field = Field::bit(22);
+// RCC_APB1ENR, RM0368 6.3.11
+pub const APB1ENR_TIM2EN: Field = Field::bit(0);
+pub const APB1ENR_TIM3EN: Field = Field::bit(1);
+pub const APB1ENR_TIM4EN: Field = Field::bit(2);
+pub const APB1ENR_TIM5EN: Field = Field::bit(3);
+pub const APB1ENR_WWDGEN: Field = Field::bit(11);
+pub const APB1ENR_USART2EN: Field = Field::bit(17);
+pub const APB1ENR_PWREN: Field = Field::bit(28);
+// RCC_APB2ENR, RM0368 6.3.12
+pub const APB2ENR_USART1EN: Field = Field::bit(4);
+pub const APB2ENR_USART6EN: Field = Field::bit(5);
+pub const APB2ENR_ADC1EN: Field = Field::bit(8);
+pub const APB2ENR_SYSCFGEN: Field = Field::bit(14);
+
+// RCC_BDCR, RM0368 6.3.20
+pub const BDCR_LSEON: Field = Field::bit(0);
+pub const BDCR_LSERDY: Field = Field::bit(1);
+
+// RCC_CSR, RM0368 6.3.21
+pub const CSR_RMVF: Field = Field::bit(24);
+pub const CSR_BORRSTF: Field = Field::bit(25);
+pub const CSR_PINRSTF: Field = Field::bit(26);
+pub const CSR_PORRSTF: Field = Field::bit(27);
+pub const CSR_SFTRSTF: Field = Field::bit(28);
+pub const CSR_IWDGRSTF: Field = Field::bit(29);
+pub const CSR_WWDGRSTF: Field = Field::bit(30);
+pub const CSR_LPWRRSTF: Field = Field::bit(31);
+
+/// Power controller, RM0368 5.4
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Pwr {
+    pub CR:         VolatileCell<u32>,      // power control
+    pub CSR:        VolatileCell<u32>,      // power control/status
+}
+
+#[rustfmt::skip]
+assert_offsets!(Pwr {
+    CR:         0x00,
+    CSR:        0x04,
+});
+
+// PWR_CR, RM0368 5.4.1
+pub const PWR_CR_DBP: Field = Field::bit(8);
+
+/// Flash interface, RM0368 3.8
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Flash {
+    pub ACR:        VolatileCell<u32>,      // access control
+    pub KEYR:       VolatileCell<u32>,      // key
+    pub OPTKEYR:    VolatileCell<u32>,      // option key
+    pub SR:         VolatileCell<u32>,      // status
+    pub CR:         VolatileCell<u32>,      // control
+    pub OPTCR:      VolatileCell<u32>,      // option control
+}
+
+#[rustfmt::skip]
+assert_offsets!(Flash {
+    ACR:        0x00,
+    KEYR:       0x04,
+    OPTKEYR:    0x08,
+    SR:         0x0C,
+    CR:         0x10,
+    OPTCR:      0x14,
+});
+
+// FLASH_ACR, RM0368 3.8.1
+pub const ACR_LATENCY: Field = Field::new(0, 4);
+pub const ACR_PRFTEN: Field = Field::bit(8);
+pub const ACR_ICEN: Field = Field::bit(9);
+pub const ACR_DCEN: Field = Field::bit(10);
+
+/// General purpose IO, RM0368 8.4
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Gpio {
+    pub MODER:      VolatileCell<u32>,      // port mode
+    pub OTYPER:     VolatileCell<u32>,      // port output type
+    pub OSPEEDR:    VolatileCell<u32>,      // port output speed
+    pub PUPDR:      VolatileCell<u32>,      // port pull-up/pull-down
+    pub IDR:        VolatileCell<u32>,      // port input data
+    pub ODR:        VolatileCell<u32>,      // port output data
+    pub BSRR:       VolatileCell<u32>,      // port bit set/reset
+    pub LCKR:       VolatileCell<u32>,      // port configuration lock
+    pub AFRL:       VolatileCell<u32>,      // alternate function low (pins 0..7)
+    pub AFRH:       VolatileCell<u32>,      // alternate function high (pins 8..15)
+}
+
+#[rustfmt::skip]
+assert_offsets!(Gpio {
+    MODER:      0x00,
+    OTYPER:     0x04,
+    OSPEEDR:    0x08,
+    PUPDR:      0x0C,
+    IDR:        0x10,
+    ODR:        0x14,
+    BSRR:       0x18,
+    LCKR:       0x1C,
+    AFRL:       0x20,
+    AFRH:       0x24,
+});
+
+// GPIOx_OSPEEDR values
+pub const OSPEEDR_VERY_HIGH: u32 = 0b11;
+
+// GPIOx_AFRL/AFRH values, STM32F401xD/E datasheet Table 9
+pub const AF_TIM1_2: u32 = 1;
+pub const AF_TIM3_5: u32 = 2;
+
+// GPIOx_MODER values
+pub const MODER_INPUT: u32 = 0b00;
+pub const MODER_OUTPUT: u32 = 0b01;
+pub const MODER_ALTERNATE: u32 = 0b10;
+pub const MODER_ANALOG: u32 = 0b11;
+
+impl Gpio {
+    /// `MODER` (and `OSPEEDR`, `PUPDR`) field of `pin`
+    pub const fn moder(pin: u8) -> Field {
+        Field::new(pin * 2, 2)
+    }
+
+    /// `AFRL`/`AFRH` field of `pin` (use `AFRH` for pins 8..15)
+    pub const fn afr(pin: u8) -> Field {
+        Field::new((pin % 8) * 4, 4)
+    }
+
+    /// `BSRR` value setting `pin` high
+    pub const fn bs(pin: u8) -> u32 {
+        Field::bit(pin).mask()
+    }
+
+    /// `BSRR` value setting `pin` low
+    pub const fn br(pin: u8) -> u32 {
+        Field::bit(pin + 16).mask()
+    }
+}
+
+/// Universal synchronous asynchronous receiver transmitter, RM0368 19.6
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Usart {
+    pub SR:         VolatileCell<u32>,      // status
+    pub DR:         VolatileCell<u32>,      // data
+    pub BRR:        VolatileCell<u32>,      // baud rate
+    pub CR1:        VolatileCell<u32>,      // control 1
+    pub CR2:        VolatileCell<u32>,      // control 2
+    pub CR3:        VolatileCell<u32>,      // control 3
+    pub GTPR:       VolatileCell<u32>,      // guard time and prescaler
+}
+
+#[rustfmt::skip]
+assert_offsets!(Usart {
+    SR:         0x00,
+    DR:         0x04,
+    BRR:        0x08,
+    CR1:        0x0C,
+    CR2:        0x10,
+    CR3:        0x14,
+    GTPR:       0x18,
+});
+
+// USART_SR, RM0368 19.6.1
+pub const SR_PE: Field = Field::bit(0);
+pub const SR_FE: Field = Field::bit(1);
+pub const SR_NF: Field = Field::bit(2);
+pub const SR_ORE: Field = Field::bit(3);
+pub const SR_IDLE: Field = Field::bit(4);
+pub const SR_RXNE: Field = Field::bit(5);
+pub const SR_TC: Field = Field::bit(6);
+pub const SR_TXE: Field = Field::bit(7);
+// USART_CR1, RM0368 19.6.4
+pub const CR1_RE: Field = Field::bit(2);
+pub const CR1_TE: Field = Field::bit(3);
+pub const CR1_IDLEIE: Field = Field::bit(4);
+pub const CR1_RXNEIE: Field = Field::bit(5);
+pub const CR1_TCIE: Field = Field::bit(6);
+pub const CR1_TXEIE: Field = Field::bit(7);
+pub const CR1_UE: Field = Field::bit(13);
+// USART_CR3, RM0368 19.6.6
+pub const CR3_EIE: Field = Field::bit(0);
+pub const CR3_DMAR: Field = Field::bit(6);
+
+/// System configuration controller, RM0368 7.2
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Syscfg {
+    pub MEMRMP:     VolatileCell<u32>,      // memory remap
+    pub PMC:        VolatileCell<u32>,      // peripheral mode configuration
+    pub EXTICR:     [VolatileCell<u32>; 4], // external interrupt configuration 1..4
+    _reserved0:     [u32; 2],
+    pub CMPCR:      VolatileCell<u32>,      // compensation cell control
+}
+
+#[rustfmt::skip]
+assert_offsets!(Syscfg {
+    MEMRMP:     0x00,
+    PMC:        0x04,
+    EXTICR:     0x08,
+    CMPCR:      0x20,
+});
+
+impl Syscfg {
+    /// `EXTICR` field of `line` (in `EXTICR[line / 4]`)
+    pub const fn exticr(line: u8) -> Field {
+        Field::new((line % 4) * 4, 4)
+    }
+}
+
+/// External interrupt/event controller, RM0368 10.3
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Exti {
+    pub IMR:        VolatileCell<u32>,      // interrupt mask
+    pub EMR:        VolatileCell<u32>,      // event mask
+    pub RTSR:       VolatileCell<u32>,      // rising trigger selection
+    pub FTSR:       VolatileCell<u32>,      // falling trigger selection
+    pub SWIER:      VolatileCell<u32>,      // software interrupt event
+    pub PR:         VolatileCell<u32>,      // pending
+}
+
+#[rustfmt::skip]
+assert_offsets!(Exti {
+    IMR:        0x00,
+    EMR:        0x04,
+    RTSR:       0x08,
+    FTSR:       0x0C,
+    SWIER:      0x10,
+    PR:         0x14,
+});
+
+/// DMA controller, RM0368 9.5
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Dma {
+    pub LISR:       VolatileCell<u32>,      // low interrupt status (streams 0..3)
+    pub HISR:       VolatileCell<u32>,      // high interrupt status (streams 4..7)
+    pub LIFCR:      VolatileCell<u32>,      // low interrupt flag clear
+    pub HIFCR:      VolatileCell<u32>,      // high interrupt flag clear
+    pub S:          [DmaStream; 8],         // streams 0..7
+}
+
+#[rustfmt::skip]
+assert_offsets!(Dma {
+    LISR:       0x00,
+    HISR:       0x04,
+    LIFCR:      0x08,
+    HIFCR:      0x0C,
+    S:          0x10,
+});
+
+/// DMA stream, RM0368 9.5.5 - 9.5.10
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct DmaStream {
+    pub CR:         VolatileCell<u32>,      // configuration
+    pub NDTR:       VolatileCell<u32>,      // number of data
+    pub PAR:        VolatileCell<u32>,      // peripheral address
+    pub M0AR:       VolatileCell<u32>,      // memory 0 address
+    pub M1AR:       VolatileCell<u32>,      // memory 1 address
+    pub FCR:        VolatileCell<u32>,      // FIFO control
+}
+
+#[rustfmt::skip]
+assert_offsets!(DmaStream {
+    CR:         0x00,
+    NDTR:       0x04,
+    PAR:        0x08,
+    M0AR:       0x0C,
+    M1AR:       0x10,
+    FCR:        0x14,
+});
+
+impl Dma {
+    /// Offset of the `LISR`/`HISR` (and `LIFCR`/`HIFCR`) flags of `stream`
+    pub const fn flags_offset(stream: usize) -> u8 {
+        [0, 6, 16, 22][stream % 4]
+    }
+
+    /// Offset of the `LISR`/`HISR` (`LIFCR`/`HIFCR`) register of `stream`
+    pub const fn isr_offset(stream: usize) -> usize {
+        if stream < 4 {
+            offset_of!(Dma, LISR)
+        } else {
+            offset_of!(Dma, HISR)
+        }
+    }
+
+    /// Offset of the register at `offset` in the block of `stream`
+    pub const fn stream_offset(stream: usize, offset: usize) -> usize {
+        offset_of!(Dma, S) + stream * core::mem::size_of::<DmaStream>() + offset
+    }
+}
+
+// DMA_LISR/HISR (and LIFCR/HIFCR) flags, relative to `Dma::flags_offset`, RM0368 9.5.1
+pub const ISR_FEIF: u32 = 1 << 0;
+pub const ISR_DMEIF: u32 = 1 << 2;
+pub const ISR_TEIF: u32 = 1 << 3;
+pub const ISR_HTIF: u32 = 1 << 4;
+pub const ISR_TCIF: u32 = 1 << 5;
+pub const ISR_ALL: u32 = ISR_FEIF | ISR_DMEIF | ISR_TEIF | ISR_HTIF | ISR_TCIF;
+// DMA_SxCR, RM0368 9.5.5
+pub const SXCR_EN: Field = Field::bit(0);
+pub const SXCR_TEIE: Field = Field::bit(2);
+pub const SXCR_TCIE: Field = Field::bit(4);
+pub const SXCR_DIR: Field = Field::new(6, 2);
+pub const SXCR_CIRC: Field = Field::bit(8);
+pub const SXCR_MINC: Field = Field::bit(10);
+pub const SXCR_PSIZE: Field = Field::new(11, 2);
+pub const SXCR_MSIZE: Field = Field::new(13, 2);
+pub const SXCR_PL: Field = Field::new(16, 2);
+pub const SXCR_DBM: Field = Field::bit(18);
+pub const SXCR_CT: Field = Field::bit(19);
+pub const SXCR_CHSEL: Field = Field::new(25, 3);
+
+/// General purpose timer (TIM2..5), RM0368 13.4
+///
+/// TIM3 and TIM4 are 16 bit, only the low half of `CNT`, `ARR` and `CCR` is
+/// used.
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Tim {
+    pub CR1:        VolatileCell<u32>,      // control 1
+    pub CR2:        VolatileCell<u32>,      // control 2
+    pub SMCR:       VolatileCell<u32>,      // slave mode control
+    pub DIER:       VolatileCell<u32>,      // DMA/interrupt enable
+    pub SR:         VolatileCell<u32>,      // status
+    pub EGR:        VolatileCell<u32>,      // event generation
+    pub CCMR1:      VolatileCell<u32>,      // capture/compare mode 1 (channels 1, 2)
+    pub CCMR2:      VolatileCell<u32>,      // capture/compare mode 2 (channels 3, 4)
+    pub CCER:       VolatileCell<u32>,      // capture/compare enable
+    pub CNT:        VolatileCell<u32>,      // counter
+    pub PSC:        VolatileCell<u32>,      // prescaler
+    pub ARR:        VolatileCell<u32>,      // auto-reload
+    _reserved0:     u32,
+    pub CCR:        [VolatileCell<u32>; 4], // capture/compare 1..4
+    _reserved1:     u32,
+    pub DCR:        VolatileCell<u32>,      // DMA control
+    pub DMAR:       VolatileCell<u32>,      // DMA address for full transfer
+    pub OR:         VolatileCell<u32>,      // option (TIM2, TIM5)
+}
+
+#[rustfmt::skip]
+assert_offsets!(Tim {
+    CR1:        0x00,
+    CR2:        0x04,
+    SMCR:       0x08,
+    DIER:       0x0C,
+    SR:         0x10,
+    EGR:        0x14,
+    CCMR1:      0x18,
+    CCMR2:      0x1C,
+    CCER:       0x20,
+    CNT:        0x24,
+    PSC:        0x28,
+    ARR:        0x2C,
+    CCR:        0x34,
+    DCR:        0x48,
+    DMAR:       0x4C,
+    OR:         0x50,
+});
+
+impl Tim {
+    /// Offset of the `CCMR1`/`CCMR2` register of `channel` (0..3)
+    pub const fn ccmr_offset(channel: u8) -> usize {
+        if channel < 2 {
+            offset_of!(Tim, CCMR1)
+        } else {
+            offset_of!(Tim, CCMR2)
+        }
+    }
+
+    /// `CCMRx` field of `channel` (0..3), relative to `Tim::ccmr_offset`
+    pub const fn ccmr(channel: u8) -> Field {
+        Field::new((channel % 2) * 8, 8)
+    }
+
+    /// `CCER` field of `channel` (0..3): `CCxE`, `CCxP`, -, `CCxNP`
+    pub const fn ccer(channel: u8) -> Field {
+        Field::new(channel * 4, 4)
+    }
+
+    /// Offset of the `CCR` register of `channel` (0..3)
+    pub const fn ccr_offset(channel: u8) -> usize {
+        offset_of!(Tim, CCR) + channel as usize * 4
+    }
+
+    /// `SR` capture/compare flag of `channel` (0..3), `CCxIF`
+    pub const fn ccif(channel: u8) -> Field {
+        Field::bit(channel + 1)
+    }
+
+    /// `SR` overcapture flag of `channel` (0..3), `CCxOF`
+    pub const fn ccof(channel: u8) -> Field {
+        Field::bit(channel + 9)
+    }
+}
+
+// TIMx_CR1, RM0368 13.4.1
+pub const CR1_CEN: Field = Field::bit(0);
+pub const CR1_ARPE: Field = Field::bit(7);
+// TIMx_SMCR, RM0368 13.4.3
+pub const SMCR_SMS: Field = Field::new(0, 3);
+pub const SMCR_TS: Field = Field::new(4, 3);
+// TIMx_SMCR SMS values
+pub const SMS_RESET: u32 = 0b100;
+pub const SMS_EXTERNAL_CLOCK: u32 = 0b111;
+// TIMx_SMCR TS values
+pub const TS_TI1FP1: u32 = 0b101;
+// TIMx_SR, RM0368 13.4.5 (`CCxIF` at bit x, `CCxOF` at bit x + 8)
+pub const SR_UIF: Field = Field::bit(0);
+// TIMx_EGR, RM0368 13.4.6
+pub const EGR_UG: Field = Field::bit(0);
+// TIMx_CCMRx, relative to `Tim::ccmr`, RM0368 13.4.7
+pub const CCMR_CCS: Field = Field::new(0, 2);
+// TIMx_CCMRx CCxS values, the input of an input capture channel
+pub const CCS_OUTPUT: u32 = 0b00;
+pub const CCS_TI_SAME: u32 = 0b01;
+pub const CCS_TI_OTHER: u32 = 0b10;
+// TIMx_CCMRx input capture
+pub const CCMR_ICPSC: Field = Field::new(2, 2);
+pub const CCMR_ICF: Field = Field::new(4, 4);
+// TIMx_CCMRx output compare
+pub const CCMR_OCPE: Field = Field::bit(3);
+pub const CCMR_OCM: Field = Field::new(4, 3);
+// TIMx_CCMRx OCxM values
+pub const OCM_PWM1: u32 = 0b110;
+// TIMx_CCER, relative to `Tim::ccer`, RM0368 13.4.9
+pub const CCER_CCE: Field = Field::bit(0);
+pub const CCER_CCP: Field = Field::bit(1);
+pub const CCER_CCNP: Field = Field::bit(3);
+// TIM5_OR, RM0368 13.4.21
+pub const TIM5_OR_TI4_RMP: Field = Field::new(6, 2);
+// TIM5_OR TI4_RMP values, the input of TIM5 channel 4
+pub const TI4_RMP_LSI: u32 = 0b01;
+pub const TI4_RMP_LSE: u32 = 0b10;
+
+/// Analog to digital converter, RM0368 11.12
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Adc {
+    pub SR:         VolatileCell<u32>,      // status
+    pub CR1:        VolatileCell<u32>,      // control 1
+    pub CR2:        VolatileCell<u32>,      // control 2
+    pub SMPR1:      VolatileCell<u32>,      // sample time 1 (channels 10..18)
+    pub SMPR2:      VolatileCell<u32>,      // sample time 2 (channels 0..9)
+    pub JOFR:       [VolatileCell<u32>; 4], // injected channel data offset 1..4
+    pub HTR:        VolatileCell<u32>,      // watchdog higher threshold
+    pub LTR:        VolatileCell<u32>,      // watchdog lower threshold
+    pub SQR1:       VolatileCell<u32>,      // regular sequence 1 (ranks 13..16, length)
+    pub SQR2:       VolatileCell<u32>,      // regular sequence 2 (ranks 7..12)
+    pub SQR3:       VolatileCell<u32>,      // regular sequence 3 (ranks 1..6)
+    pub JSQR:       VolatileCell<u32>,      // injected sequence
+    pub JDR:        [VolatileCell<u32>; 4], // injected data 1..4
+    pub DR:         VolatileCell<u32>,      // regular data
+}
+
+#[rustfmt::skip]
+assert_offsets!(Adc {
+    SR:         0x00,
+    CR1:        0x04,
+    CR2:        0x08,
+    SMPR1:      0x0C,
+    SMPR2:      0x10,
+    JOFR:       0x14,
+    HTR:        0x24,
+    LTR:        0x28,
+    SQR1:       0x2C,
+    SQR2:       0x30,
+    SQR3:       0x34,
+    JSQR:       0x38,
+    JDR:        0x3C,
+    DR:         0x4C,
+});
+
+impl Adc {
+    /// Offset of the `SQRx` register of `rank` (0..15, the conversion order)
+    pub const fn sqr_offset(rank: usize) -> usize {
+        offset_of!(Adc, SQR3) - (rank / 6) * 4
+    }
+
+    /// `SQx` field of `rank` (0..15), relative to `Adc::sqr_offset`
+    pub const fn sq(rank: usize) -> Field {
+        Field::new((rank % 6) as u8 * 5, 5)
+    }
+
+    /// Offset of the `SMPRx` register of `channel` (0..18)
+    pub const fn smpr_offset(channel: u8) -> usize {
+        if channel < 10 {
+            offset_of!(Adc, SMPR2)
+        } else {
+            offset_of!(Adc, SMPR1)
+        }
+    }
+
+    /// `SMPx` field of `channel` (0..18), relative to `Adc::smpr_offset`
+    pub const fn smp(channel: u8) -> Field {
+        Field::new((channel % 10) * 3, 3)
+    }
+}
+
+// ADC_SR, RM0368 11.12.1
+pub const ADC_SR_EOC: Field = Field::bit(1);
+pub const ADC_SR_STRT: Field = Field::bit(4);
+pub const ADC_SR_OVR: Field = Field::bit(5);
+// ADC_CR1, RM0368 11.12.2
+pub const ADC_CR1_SCAN: Field = Field::bit(8);
+pub const ADC_CR1_RES: Field = Field::new(24, 2);
+pub const ADC_CR1_OVRIE: Field = Field::bit(26);
+// ADC_CR2, RM0368 11.12.3
+pub const ADC_CR2_ADON: Field = Field::bit(0);
+pub const ADC_CR2_CONT: Field = Field::bit(1);
+pub const ADC_CR2_DMA: Field = Field::bit(8);
+pub const ADC_CR2_DDS: Field = Field::bit(9);
+pub const ADC_CR2_EOCS: Field = Field::bit(10);
+pub const ADC_CR2_SWSTART: Field = Field::bit(30);
+// ADC_SQR1, RM0368 11.12.9
+pub const SQR1_L: Field = Field::new(20, 4);
+
+/// ADC common registers, RM0368 11.12.16
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct AdcCommon {
+    _reserved0:     u32,
+    pub CCR:        VolatileCell<u32>,      // common control
+}
+
+#[rustfmt::skip]
+assert_offsets!(AdcCommon {
+    CCR:        0x04,
+});
+
+// ADC_CCR, RM0368 11.12.16
+pub const ADC_CCR_ADCPRE: Field = Field::new(16, 2);
+pub const ADC_CCR_VBATE: Field = Field::bit(22);
+pub const ADC_CCR_TSVREFE: Field = Field::bit(23);
+
+/// Independent watchdog, RM0368 17.4
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Iwdg {
+    pub KR:         VolatileCell<u32>,      // key
+    pub PR:         VolatileCell<u32>,      // prescaler
+    pub RLR:        VolatileCell<u32>,      // reload
+    pub SR:         VolatileCell<u32>,      // status
+}
+
+#[rustfmt::skip]
+assert_offsets!(Iwdg {
+    KR:         0x00,
+    PR:         0x04,
+    RLR:        0x08,
+    SR:         0x0C,
+});
+
+// IWDG_KR keys, RM0368 17.4.1
+pub const KR_ENABLE_ACCESS: u32 = 0x5555;
+pub const KR_RELOAD: u32 = 0xAAAA;
+pub const KR_START: u32 = 0xCCCC;
+// IWDG_PR, RM0368 17.4.2
+pub const PR_PR: Field = Field::new(0, 3);
+// IWDG_RLR, RM0368 17.4.3
+pub const RLR_RL: Field = Field::new(0, 12);
+// IWDG_SR, RM0368 17.4.4
+pub const IWDG_SR_PVU: Field = Field::bit(0);
+pub const IWDG_SR_RVU: Field = Field::bit(1);
+
+/// Window watchdog, RM0368 18.6
+#[repr(C)]
+#[allow(non_snake_case)]
+#[rustfmt::skip]
+pub struct Wwdg {
+    pub CR:         VolatileCell<u32>,      // control
+    pub CFR:        VolatileCell<u32>,      // configuration
+    pub SR:         VolatileCell<u32>,      // status
+}
+
+#[rustfmt::skip]
+assert_offsets!(Wwdg {
+    CR:         0x00,
+    CFR:        0x04,
+    SR:         0x08,
+});
+
+// WWDG_CR, RM0368 18.6.1
+pub const WWDG_CR_T: Field = Field::new(0, 7);
+pub const WWDG_CR_WDGA: Field = Field::bit(7);
+// WWDG_CFR, RM0368 18.6.2
+pub const CFR_W: Field = Field::new(0, 7);
+pub const CFR_WDGTB: Field = Field::new(7, 2);
+pub const CFR_EWI: Field = Field::bit(9);
+// WWDG_SR, RM0368 18.6.3
+pub const WWDG_SR_EWIF: Field = Field::bit(0);
+
+const _: () = assert!(core::mem::size_of::<Rcc>() == 0x90);
+const _: () = assert!(core::mem::size_of::<Pwr>() == 0x08);
+const _: () = assert!(core::mem::size_of::<Flash>() == 0x18);
+const _: () = assert!(core::mem::size_of::<Gpio>() == 0x28);
+const _: () = assert!(core::mem::size_of::<Usart>() == 0x1C);
+const _: () = assert!(core::mem::size_of::<Syscfg>() == 0x24);
+const _: () = assert!(core::mem::size_of::<Exti>() == 0x18);
+const _: () = assert!(core::mem::size_of::<DmaStream>() == 0x18);
+const _: () = assert!(core::mem::size_of::<Dma>() == 0xD0);
+const _: () = assert!(core::mem::size_of::<Tim>() == 0x54);
+const _: () = assert!(core::mem::size_of::<Adc>() == 0x50);
+const _: () = assert!(core::mem::size_of::<AdcCommon>() == 0x08);
+const _: () = assert!(core::mem::size_of::<Iwdg>() == 0x10);
+const _: () = assert!(core::mem::size_of::<Wwdg>() == 0x0C);
@@ -0,0 +1,128 @@
+//! Reset cause, from the reset flags of `RCC_CSR` (RM0368 6.3.21)
+//!
+//! The flags are kept over a reset (but for a power-on reset), and accumulate
+//! until cleared by `RMVF`. Read (and clear) them early in `init`/`main`:
+//!
+//! ``` ignore
+//! let flags = reset::Flags::take(&unsafe { Mmio::new() });
+//! iprintln!(stim, "reset by {:?}", flags.cause());
+//! ```
+
+use core::mem::offset_of;
+
+use crate::bus::RegisterBus;
+use crate::regs::{
+    Rcc, CSR_BORRSTF, CSR_IWDGRSTF, CSR_LPWRRSTF, CSR_PINRSTF, CSR_PORRSTF, CSR_RMVF, CSR_SFTRSTF,
+    CSR_WWDGRSTF, RCC,
+};
+
+#[rustfmt::skip]
+pub mod address {
+    use super::*;
+
+    pub const RCC_CSR: u32          = RCC.reg(offset_of!(Rcc, CSR));
+}
+
+use address::*;
+
+/// Reset cause, the most specific of the flags set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    /// window watchdog (WWDG)
+    WindowWatchdog,
+    /// independent watchdog (IWDG)
+    IndependentWatchdog,
+    /// low-power management (entering Standby/Stop, if so configured)
+    LowPower,
+    /// software (`SCB::sys_reset`)
+    Software,
+    /// power-on (also sets `Pin` and `BrownOut`)
+    PowerOn,
+    /// supply below the brown-out threshold
+    BrownOut,
+    /// NRST pin (the reset button)
+    Pin,
+    /// no flag set (e.g., already cleared)
+    Unknown,
+}
+
+/// The reset flags of `RCC_CSR`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flags(pub u32);
+
+impl Flags {
+    pub fn read<B: RegisterBus>(bus: &B) -> Self {
+        Flags(bus.read_u32(RCC_CSR))
+    }
+
+    /// Clears the flags (`RMVF`), so the next reset is told apart
+    pub fn clear<B: RegisterBus>(bus: &B) {
+        bus.modify_u32(RCC_CSR, 0, CSR_RMVF.mask());
+    }
+
+    /// Reads, then clears, the flags
+    pub fn take<B: RegisterBus>(bus: &B) -> Self {
+        let flags = Self::read(bus);
+        Self::clear(bus);
+        flags
+    }
+
+    pub fn cause(self) -> Cause {
+        #[rustfmt::skip]
+        let causes = [
+            (CSR_WWDGRSTF,  Cause::WindowWatchdog),
+            (CSR_IWDGRSTF,  Cause::IndependentWatchdog),
+            (CSR_LPWRRSTF,  Cause::LowPower),
+            (CSR_SFTRSTF,   Cause::Software),
+            (CSR_PORRSTF,   Cause::PowerOn),
+            (CSR_BORRSTF,   Cause::BrownOut),
+            (CSR_PINRSTF,   Cause::Pin),
+        ];
+        causes
+            .iter()
+            .find(|(flag, _)| self.0 & flag.mask() != 0)
+            .map_or(Cause::Unknown, |(_, cause)| *cause)
+    }
+
+    /// Reset by a watchdog (IWDG or WWDG)
+    pub fn is_watchdog(self) -> bool {
+        self.0 & (CSR_IWDGRSTF.mask() | CSR_WWDGRSTF.mask()) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Access::*, MemoryBus};
+
+    #[test]
+    fn causes() {
+        // power-on sets POR, PIN and BOR
+        assert_eq!(Flags(0b1110 << 24).cause(), Cause::PowerOn);
+        assert_eq!(Flags(0b0110 << 24).cause(), Cause::BrownOut);
+        assert_eq!(Flags(0b0100 << 24).cause(), Cause::Pin);
+        // the reset pin is driven low by any internal reset
+        assert_eq!(Flags(1 << 28 | 1 << 26).cause(), Cause::Software);
+        assert_eq!(Flags(1 << 29 | 1 << 26).cause(), Cause::IndependentWatchdog);
+        assert!(Flags(1 << 30 | 1 << 26).is_watchdog());
+        assert_eq!(Flags(1).cause(), Cause::Unknown);
+    }
+
+    #[test]
+    fn take_clears() {
+        let bus = MemoryBus::new();
+        // LSI on, software reset
+        bus.preset(RCC_CSR, 1 << 28 | 1 << 26 | 1);
+
+        assert_eq!(Flags::take(&bus).cause(), Cause::Software);
+        assert_eq!(RCC_CSR, 0x4002_3874);
+        assert_eq!(
+            &bus.accesses()[..],
+            &[
+                Read(RCC_CSR, 0x1400_0001),
+                Read(RCC_CSR, 0x1400_0001),
+                Write(RCC_CSR, 0x1500_0001),
+            ]
+        );
+    }
+}
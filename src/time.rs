@@ -0,0 +1,363 @@
+//! Time units on top of the DWT cycle counter (CYCCNT)
+//!
+//! `bare2.rs` measures `end - start` in cycles, and `bare6.rs` waits a number
+//! of cycles, both only meaningful at a known core clock (8_000_000 cycles are
+//! 0.5 s at 16 MHz, but 125 ms at 64 MHz). Here the clock is kept along:
+//!
+//! - `Duration`, a span of time in microseconds, independent of the clock
+//! - `Instant`, a reading of CYCCNT, compared and subtracted across wraparound
+//! - `Hertz`, a frequency
+//!
+//! Cycles are converted at the current SYSCLK, 16 MHz (HSI) after reset. After
+//! changing the clock (e.g., by `Plan::apply` of `clocks.rs`, or `freeze` of
+//! the HAL), give the new one to `set_sysclk`.
+//!
+//! ``` ignore
+//! use app::time::{self, DurationExt as _, Instant};
+//!
+//! let start = Instant::now();
+//! time::delay(500.millis());
+//! iprintln!(stim, "{:?}", start.elapsed());
+//! ```
+//!
+//! CYCCNT wraps after 2^32 cycles (51 s at 84 MHz), instants are ordered
+//! correctly when less than 2^31 cycles apart.
+
+use core::cmp::Ordering;
+use core::ops::{Add, AddAssign, Mul, Sub, SubAssign};
+use core::sync::atomic::{AtomicU32, Ordering as MemOrdering};
+
+use cortex_m::peripheral::DWT;
+
+/// The core clock after reset (HSI)
+pub const RESET_SYSCLK: Hertz = Hertz::mhz(16);
+
+// cycles waited for at a time by `delay`, less than 2^31 so a late reading of
+// CYCCNT is still ordered after the end
+const MAX_WAIT: u32 = 1 << 30;
+
+static SYSCLK: AtomicU32 = AtomicU32::new(RESET_SYSCLK.0);
+
+/// A frequency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Hertz(pub u32);
+
+impl Hertz {
+    pub const fn khz(khz: u32) -> Self {
+        Hertz(khz * 1_000)
+    }
+
+    pub const fn mhz(mhz: u32) -> Self {
+        Hertz(mhz * 1_000_000)
+    }
+
+    /// The time of one period (rounded down to a microsecond)
+    pub fn period(self) -> Duration {
+        Duration(1_000_000 / self.0)
+    }
+}
+
+/// A span of time, in microseconds (at most 71 minutes)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Duration(u32);
+
+impl Duration {
+    pub const ZERO: Duration = Duration(0);
+
+    pub const fn from_micros(us: u32) -> Self {
+        Duration(us)
+    }
+
+    pub const fn from_millis(ms: u32) -> Self {
+        Duration(ms * 1_000)
+    }
+
+    pub const fn from_secs(s: u32) -> Self {
+        Duration(s * 1_000_000)
+    }
+
+    /// The time of `cycles` at a clock of `clock` (rounded down)
+    pub fn from_cycles(cycles: u32, clock: Hertz) -> Self {
+        Duration((u64::from(cycles) * 1_000_000 / u64::from(clock.0)) as u32)
+    }
+
+    pub const fn as_micros(self) -> u32 {
+        self.0
+    }
+
+    pub const fn as_millis(self) -> u32 {
+        self.0 / 1_000
+    }
+
+    /// The number of cycles at a clock of `clock` (rounded down)
+    pub fn cycles_at(self, clock: Hertz) -> u64 {
+        u64::from(self.0) * u64::from(clock.0) / 1_000_000
+    }
+
+    /// The number of cycles at the current SYSCLK, e.g., for scheduling RTFM
+    /// tasks (by `U32Ext::cycles` of `rtfm::cyccnt`)
+    ///
+    /// # Panics
+    ///
+    /// If the number does not fit CYCCNT (more than 51 s at 84 MHz).
+    pub fn cycles(self) -> u32 {
+        let cycles = self.cycles_at(sysclk());
+        assert!(cycles <= u64::from(u32::MAX), "duration exceeds CYCCNT");
+        cycles as u32
+    }
+
+    /// The frequency of which this is the period
+    pub fn frequency(self) -> Hertz {
+        Hertz(1_000_000 / self.0)
+    }
+
+    pub fn checked_sub(self, rhs: Duration) -> Option<Duration> {
+        self.0.checked_sub(rhs.0).map(Duration)
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Duration {
+        Duration(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Duration) -> Duration {
+        Duration(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, rhs: Duration) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Mul<u32> for Duration {
+    type Output = Duration;
+
+    fn mul(self, rhs: u32) -> Duration {
+        Duration(self.0 * rhs)
+    }
+}
+
+/// `500.millis()`, `10.micros()`, `2.secs()`
+pub trait DurationExt {
+    fn micros(self) -> Duration;
+    fn millis(self) -> Duration;
+    fn secs(self) -> Duration;
+}
+
+impl DurationExt for u32 {
+    fn micros(self) -> Duration {
+        Duration::from_micros(self)
+    }
+
+    fn millis(self) -> Duration {
+        Duration::from_millis(self)
+    }
+
+    fn secs(self) -> Duration {
+        Duration::from_secs(self)
+    }
+}
+
+/// A reading of the cycle counter (CYCCNT)
+///
+/// Ordered by the wrapping difference, so an instant just after the wrap is
+/// later than one just before. (The order is only transitive for instants
+/// less than 2^31 cycles apart.)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instant(u32);
+
+impl Instant {
+    /// Reads CYCCNT, enabled by `DWT::enable_cycle_counter`
+    pub fn now() -> Self {
+        Instant(DWT::get_cycle_count())
+    }
+
+    pub const fn from_cycles(cycles: u32) -> Self {
+        Instant(cycles)
+    }
+
+    /// The raw CYCCNT value
+    pub const fn cycles(self) -> u32 {
+        self.0
+    }
+
+    /// Cycles from `earlier` to `self` (wrapping)
+    pub fn cycles_since(self, earlier: Instant) -> u32 {
+        self.0.wrapping_sub(earlier.0)
+    }
+
+    /// Time from `earlier` to `self`, at the current SYSCLK
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration::from_cycles(self.cycles_since(earlier), sysclk())
+    }
+
+    /// Time since `self`, at the current SYSCLK
+    pub fn elapsed(self) -> Duration {
+        Instant::now().duration_since(self)
+    }
+}
+
+impl Ord for Instant {
+    fn cmp(&self, other: &Instant) -> Ordering {
+        (self.0.wrapping_sub(other.0) as i32).cmp(&0)
+    }
+}
+
+impl PartialOrd for Instant {
+    fn partial_cmp(&self, other: &Instant) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `Instant + Duration`, at the current SYSCLK
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0.wrapping_add(rhs.cycles()))
+    }
+}
+
+/// `Instant - Instant`, at the current SYSCLK
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// Records the core clock, after changing it
+pub fn set_sysclk(clock: Hertz) {
+    SYSCLK.store(clock.0, MemOrdering::Relaxed);
+}
+
+/// The core clock (CYCCNT runs at SYSCLK)
+pub fn sysclk() -> Hertz {
+    Hertz(SYSCLK.load(MemOrdering::Relaxed))
+}
+
+/// Busy waits for (at least) `d`, at the current SYSCLK
+///
+/// Needs CYCCNT enabled (`DWT::enable_cycle_counter`), else it never returns.
+/// The cycles are computed when called, so a clock change during the wait is
+/// not accounted for.
+pub fn delay(d: Duration) {
+    wait(d.cycles_at(sysclk()), DWT::get_cycle_count);
+}
+
+// waits for `cycles` of the counter read by `now`, in steps of at most
+// `MAX_WAIT`, so any number of cycles is waited for across the wraparound
+fn wait(mut cycles: u64, mut now: impl FnMut() -> u32) {
+    let mut start = now();
+    while cycles > 0 {
+        let step = cycles.min(u64::from(MAX_WAIT)) as u32;
+        let end = start.wrapping_add(step);
+        while (now().wrapping_sub(end) as i32) < 0 {}
+        start = end;
+        cycles -= u64::from(step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(500.millis(), Duration::from_micros(500_000));
+        assert_eq!(2.secs().as_millis(), 2_000);
+        // `bare6.rs`, 8_000_000 cycles
+        assert_eq!(500.millis().cycles_at(Hertz::mhz(16)), 8_000_000);
+        assert_eq!(500.millis().cycles_at(Hertz::mhz(64)), 4 * 8_000_000);
+        assert_eq!(
+            Duration::from_cycles(8_000_000, Hertz::mhz(64)),
+            125.millis()
+        );
+        assert_eq!(Hertz(1_000).period(), 1.millis());
+        assert_eq!(250.millis().frequency(), Hertz(4));
+        // 1 cycle at 84 MHz rounds down
+        assert_eq!(Duration::from_cycles(83, Hertz::mhz(84)), Duration::ZERO);
+        // no overflow of the intermediate product
+        assert_eq!(60.secs().cycles_at(Hertz::mhz(84)), 5_040_000_000);
+    }
+
+    #[test]
+    fn sysclk_changes() {
+        // the only test touching `SYSCLK`
+        assert_eq!(sysclk(), RESET_SYSCLK);
+        assert_eq!(500.millis().cycles(), 8_000_000);
+        assert_eq!(Instant(10) + 1.millis(), Instant(16_010));
+
+        set_sysclk(Hertz::mhz(84));
+        assert_eq!(500.millis().cycles(), 42_000_000);
+        assert_eq!(Instant(84_000) - Instant(0), 1.millis());
+        set_sysclk(RESET_SYSCLK);
+    }
+
+    #[test]
+    fn wraparound() {
+        let before = Instant(u32::MAX - 10);
+        let after = Instant(5);
+        assert!(after > before);
+        assert!(before < after);
+        assert_eq!(after.cycles_since(before), 16);
+        // half the counter apart, the order flips
+        assert!(Instant(1 << 31) < Instant(0));
+    }
+
+    // a counter advancing `step` cycles per reading
+    fn counter(start: u32, step: u32) -> (Cell<u32>, impl Fn(&Cell<u32>) -> u32) {
+        (Cell::new(start), move |c: &Cell<u32>| {
+            let now = c.get();
+            c.set(now.wrapping_add(step));
+            now
+        })
+    }
+
+    #[test]
+    fn wait_across_wrap() {
+        let start = u32::MAX - 100;
+        let (c, read) = counter(start, 7);
+        wait(1_000, || read(&c));
+        let waited = c.get().wrapping_sub(start);
+        assert!((1_000..1_000 + 3 * 7).contains(&waited));
+
+        let (c, read) = counter(start, 1);
+        wait(0, || read(&c));
+        assert_eq!(c.get().wrapping_sub(start), 1);
+    }
+
+    #[test]
+    fn wait_longer_than_counter() {
+        // 3 wraps of CYCCNT
+        let cycles = 3 << 32;
+        let step = 1 << 20;
+        let (c, read) = counter(0, step);
+        let mut total = 0u64;
+        let mut last = 0;
+        wait(cycles, || {
+            let now = read(&c);
+            total += u64::from(now.wrapping_sub(last));
+            last = now;
+            now
+        });
+        assert!((cycles..=cycles + 2 * u64::from(step)).contains(&total));
+    }
+}
@@ -0,0 +1,191 @@
+//! Deferred formatting trace
+//!
+//! `iprintln!` formats on the target, which is slow enough to drop USART bytes
+//! at 16 MHz (`bare8.rs`, `bare10.rs`). With the `trace-deferred` feature,
+//! `trace!` instead writes the id of an interned format string, followed by the
+//! raw arguments, on stimulus port `PORT`. The format string is formatted on
+//! the host by `itm-trace` (see `itm-tools`), using the symbol table of the elf.
+//! Without the feature, `trace!` is `iprintln!` on stimulus port 0.
+//!
+//! ``` ignore
+//! app::trace!(itm, "data {} ({} cycles)", byte, cost);
+//! ```
+//!
+//! Each format string is a (1 byte) static in the `.trace` section, which is
+//! placed at address 1 and not loaded (see `build.rs`). Its address is the id
+//! (from 1, `&FMT` must not be null), and its symbol name is the format string
+//! followed by `@file:line:column`.
+//!
+//! A trace event is a frame of 32 bit words:
+//!
+//! ``` text
+//! header: [15:0] id, [19:16] number of arguments, [31:20] argument types (3 bits each)
+//! 0..=4 arguments, one word each
+//! ```
+
+use cortex_m::{interrupt, peripheral::itm::Stim};
+
+#[doc(hidden)]
+pub use cortex_m::iprintln;
+
+/// The stimulus port used by deferred traces (enabled in `openocd.gdb`)
+pub const PORT: usize = 1;
+
+/// Arguments per trace event
+pub const MAX_ARGS: usize = 4;
+
+/// How the host should interpret an argument word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Type {
+    Unsigned = 0,
+    Signed = 1,
+    Bool = 2,
+    Char = 3,
+    Float = 4,
+}
+
+/// A raw argument word and its type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arg {
+    pub ty: Type,
+    pub bits: u32,
+}
+
+macro_rules! arg {
+    ($ty:ident: $($t:ty),*) => {
+        $(impl From<$t> for Arg {
+            fn from(v: $t) -> Self {
+                Arg { ty: Type::$ty, bits: v as u32 }
+            }
+        })*
+    };
+}
+
+arg!(Unsigned: u8, u16, u32, usize);
+arg!(Signed: i8, i16, i32, isize);
+
+impl From<bool> for Arg {
+    fn from(v: bool) -> Self {
+        Arg {
+            ty: Type::Bool,
+            bits: v as u32,
+        }
+    }
+}
+
+impl From<char> for Arg {
+    fn from(v: char) -> Self {
+        Arg {
+            ty: Type::Char,
+            bits: v as u32,
+        }
+    }
+}
+
+impl From<f32> for Arg {
+    fn from(v: f32) -> Self {
+        Arg {
+            ty: Type::Float,
+            bits: v.to_bits(),
+        }
+    }
+}
+
+/// The header word of a frame, panics on more than `MAX_ARGS` arguments
+pub fn header(id: u16, args: &[Arg]) -> u32 {
+    assert!(
+        args.len() <= MAX_ARGS,
+        "at most {} trace arguments",
+        MAX_ARGS
+    );
+    args.iter()
+        .enumerate()
+        .fold(id as u32 | (args.len() as u32) << 16, |h, (i, a)| {
+            h | (a.ty as u32) << (20 + 3 * i)
+        })
+}
+
+/// The id of an interned format string (its address in `.trace`)
+pub fn id(fmt: &'static u8) -> u16 {
+    fmt as *const u8 as usize as u16
+}
+
+/// Writes a frame, the frame is not interleaved with traces from other tasks
+pub fn write(stim: &mut Stim, fmt: &'static u8, args: &[Arg]) {
+    let header = header(id(fmt), args);
+    interrupt::free(|_| {
+        write_u32(stim, header);
+        for arg in args {
+            write_u32(stim, arg.bits);
+        }
+    });
+}
+
+fn write_u32(stim: &mut Stim, word: u32) {
+    while !stim.is_fifo_ready() {}
+    stim.write_u32(word);
+}
+
+/// Traces on the `ITM` (`&mut ITM`), formatted on the host
+#[cfg(feature = "trace-deferred")]
+#[macro_export]
+macro_rules! trace {
+    ($itm:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        #[link_section = ".trace"]
+        #[export_name = concat!($fmt, "@", file!(), ":", line!(), ":", column!())]
+        static FMT: u8 = 0;
+        $crate::trace::write(
+            &mut $itm.stim[$crate::trace::PORT],
+            &FMT,
+            &[$($crate::trace::Arg::from($arg)),*],
+        );
+    }};
+}
+
+/// Traces on the `ITM` (`&mut ITM`), formatted on the target
+#[cfg(not(feature = "trace-deferred"))]
+#[macro_export]
+macro_rules! trace {
+    ($itm:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {
+        $crate::trace::iprintln!(&mut $itm.stim[0], $fmt $(, $arg)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args() {
+        assert_eq!(
+            Arg::from(200u8),
+            Arg {
+                ty: Type::Unsigned,
+                bits: 200
+            }
+        );
+        assert_eq!(Arg::from(-1i8).bits, 0xffff_ffff);
+        assert_eq!(Arg::from(-1i32).ty, Type::Signed);
+        assert_eq!(Arg::from(true).bits, 1);
+        assert_eq!(Arg::from('a').bits, 97);
+        assert_eq!(Arg::from(1.0f32).bits, 0x3f80_0000);
+    }
+
+    #[test]
+    fn headers() {
+        assert_eq!(header(7, &[]), 7);
+        assert_eq!(
+            header(0x1234, &[Arg::from(1u8), Arg::from(-1i32)]),
+            0x1234 | 2 << 16 | 1 << 23
+        );
+        let all = [Arg::from(1.0f32); 4];
+        assert_eq!(header(0, &all) >> 20, 0b100_100_100_100);
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_many_args() {
+        header(0, &[Arg::from(0u8); 5]);
+    }
+}
@@ -0,0 +1,354 @@
+//! Interrupt driven, buffered USART driver
+//!
+//! In place of the 3 byte `Queue` (and `asm::bkpt` on overflow) of `bare9.rs`
+//! and the blocking `block!(tx.write(byte))` of `bare10.rs`:
+//! - received bytes are put in an RX ring by the USART interrupt
+//! - bytes written are put in a TX ring, drained by the TXE interrupt
+//! - receive errors and lost bytes are counted (`Counters`), not trapped
+//!
+//! The rings hold `RxN` and `TxN` bytes. The driver implements the
+//! `embedded-hal` serial traits (never blocking, `WouldBlock` on an empty RX
+//! ring or a full TX ring), and `core::fmt::Write`.
+//!
+//! Registers are accessed over a `RegisterBus`. In an RTFM app the driver is a
+//! resource of the task bound to the USART interrupt (calling `on_interrupt`),
+//! shared by `lock` with the tasks reading and writing:
+//!
+//! ``` ignore
+//! #[task(binds = USART2, priority = 2, resources = [SERIAL])]
+//! fn usart2(cx: usart2::Context) {
+//!     cx.resources.SERIAL.on_interrupt();
+//! }
+//! ```
+
+use core::convert::Infallible;
+use core::fmt;
+use core::mem::offset_of;
+
+use embedded_hal::serial;
+use heapless::spsc::Queue;
+use heapless::ArrayLength;
+
+use crate::bus::RegisterBus;
+use crate::regs::{
+    Instance, Usart, CR1_RE, CR1_RXNEIE, CR1_TE, CR1_TXEIE, CR1_UE, CR3_EIE, SR_FE, SR_NF, SR_ORE,
+    SR_PE, SR_RXNE, SR_TC, SR_TXE,
+};
+
+/// Receive errors and lost bytes, since `new` (wrapping)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    /// a byte arrived before the previous one was read, and was lost
+    pub overrun: u32,
+    /// no stop bit, the byte is dropped
+    pub framing: u32,
+    /// noise on the line, the byte is kept
+    pub noise: u32,
+    /// parity mismatch, the byte is dropped
+    pub parity: u32,
+    /// received bytes lost to a full RX ring
+    pub rx_dropped: u32,
+    /// bytes lost to a full TX ring (by `fmt::Write`)
+    pub tx_dropped: u32,
+}
+
+/// `BRR` value for `baud` from a peripheral clock of `pclk` Hz (oversampling
+/// by 16), RM0368 19.3.4
+pub const fn brr(pclk: u32, baud: u32) -> u32 {
+    (pclk + baud / 2) / baud
+}
+
+pub struct BufferedSerial<B, RxN, TxN>
+where
+    RxN: ArrayLength<u8>,
+    TxN: ArrayLength<u8>,
+{
+    bus: B,
+    sr: u32,
+    dr: u32,
+    brr: u32,
+    cr1: u32,
+    cr3: u32,
+    rx: Queue<u8, RxN>,
+    tx: Queue<u8, TxN>,
+    // TXEIE is set, the TX ring is being drained
+    draining: bool,
+    counters: Counters,
+}
+
+impl<B, RxN, TxN> BufferedSerial<B, RxN, TxN>
+where
+    B: RegisterBus,
+    RxN: ArrayLength<u8>,
+    TxN: ArrayLength<u8>,
+{
+    /// A driver for `usart`, which must be clocked (`RCC_APBxENR`) and have
+    /// its pins in alternate function mode, configured by `init`
+    pub fn new(bus: B, usart: &Instance<Usart>) -> Self {
+        BufferedSerial {
+            bus,
+            sr: usart.reg(offset_of!(Usart, SR)),
+            dr: usart.reg(offset_of!(Usart, DR)),
+            brr: usart.reg(offset_of!(Usart, BRR)),
+            cr1: usart.reg(offset_of!(Usart, CR1)),
+            cr3: usart.reg(offset_of!(Usart, CR3)),
+            rx: Queue::new(),
+            tx: Queue::new(),
+            draining: false,
+            counters: Counters::default(),
+        }
+    }
+
+    /// 8N1 at `baud`, from a peripheral clock of `pclk` Hz, with interrupts
+    /// on RXNE and receive errors
+    pub fn init(&mut self, pclk: u32, baud: u32) {
+        self.bus.write_u32(self.brr, brr(pclk, baud));
+        self.bus.write_u32(self.cr3, CR3_EIE.mask());
+        self.bus.write_u32(
+            self.cr1,
+            CR1_UE.mask() | CR1_TE.mask() | CR1_RE.mask() | CR1_RXNEIE.mask(),
+        );
+        self.draining = false;
+    }
+
+    /// To be called from the USART interrupt handler
+    pub fn on_interrupt(&mut self) {
+        let sr = self.bus.read_u32(self.sr);
+        let errors = SR_ORE.mask() | SR_NF.mask() | SR_FE.mask() | SR_PE.mask();
+
+        if sr & (SR_RXNE.mask() | errors) != 0 {
+            // reading DR (after SR) clears RXNE and the error flags
+            let byte = self.bus.read_u32(self.dr) as u8;
+            let c = &mut self.counters;
+            if sr & SR_ORE.mask() != 0 {
+                c.overrun = c.overrun.wrapping_add(1);
+            }
+            if sr & SR_NF.mask() != 0 {
+                c.noise = c.noise.wrapping_add(1);
+            }
+            if sr & SR_FE.mask() != 0 {
+                c.framing = c.framing.wrapping_add(1);
+            }
+            if sr & SR_PE.mask() != 0 {
+                c.parity = c.parity.wrapping_add(1);
+            }
+
+            let corrupt = sr & (SR_FE.mask() | SR_PE.mask()) != 0;
+            if sr & SR_RXNE.mask() != 0 && !corrupt && self.rx.enqueue(byte).is_err() {
+                c.rx_dropped = c.rx_dropped.wrapping_add(1);
+            }
+        }
+
+        if self.draining && sr & SR_TXE.mask() != 0 {
+            match self.tx.dequeue() {
+                Some(byte) => self.bus.write_u32(self.dr, byte as u32),
+                None => {
+                    self.bus.modify_u32(self.cr1, CR1_TXEIE.mask(), 0);
+                    self.draining = false;
+                }
+            }
+        }
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    /// Number of received bytes, not yet read
+    pub fn received(&self) -> usize {
+        self.rx.len()
+    }
+
+    /// Number of bytes written, not yet sent
+    pub fn pending(&self) -> usize {
+        self.tx.len()
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    fn enqueue(&mut self, byte: u8) -> Result<(), u8> {
+        self.tx.enqueue(byte)?;
+        if !self.draining {
+            // TXE is set while idle, so the interrupt fires right away
+            self.bus.modify_u32(self.cr1, 0, CR1_TXEIE.mask());
+            self.draining = true;
+        }
+        Ok(())
+    }
+}
+
+impl<B, RxN, TxN> serial::Read<u8> for BufferedSerial<B, RxN, TxN>
+where
+    B: RegisterBus,
+    RxN: ArrayLength<u8>,
+    TxN: ArrayLength<u8>,
+{
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        self.rx.dequeue().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<B, RxN, TxN> serial::Write<u8> for BufferedSerial<B, RxN, TxN>
+where
+    B: RegisterBus,
+    RxN: ArrayLength<u8>,
+    TxN: ArrayLength<u8>,
+{
+    type Error = Infallible;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+        self.enqueue(byte).map_err(|_| nb::Error::WouldBlock)
+    }
+
+    /// Done when the TX ring is empty and the last byte is sent (TC)
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        if self.tx.is_empty() && self.bus.read_u32(self.sr) & SR_TC.mask() != 0 {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+/// Writes what fits the TX ring, `fmt::Error` if bytes were dropped
+impl<B, RxN, TxN> fmt::Write for BufferedSerial<B, RxN, TxN>
+where
+    B: RegisterBus,
+    RxN: ArrayLength<u8>,
+    TxN: ArrayLength<u8>,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut dropped = 0;
+        for &byte in s.as_bytes() {
+            if self.enqueue(byte).is_err() {
+                dropped += 1;
+            }
+        }
+        if dropped == 0 {
+            Ok(())
+        } else {
+            let c = &mut self.counters;
+            c.tx_dropped = c.tx_dropped.wrapping_add(dropped);
+            Err(fmt::Error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemoryBus;
+    use crate::regs::USART2;
+    use core::fmt::Write as _;
+    use embedded_hal::serial::{Read as _, Write as _};
+    use heapless::consts::*;
+
+    const SR: u32 = 0x4000_4400;
+    const DR: u32 = 0x4000_4404;
+    const BRR: u32 = 0x4000_4408;
+    const CR1: u32 = 0x4000_440C;
+    const CR3: u32 = 0x4000_4414;
+
+    fn serial() -> BufferedSerial<MemoryBus, U2, U2> {
+        let mut s = BufferedSerial::new(MemoryBus::new(), &USART2);
+        s.init(16_000_000, 115_200);
+        s
+    }
+
+    // a byte received, with the status flags `sr`
+    fn receive(s: &mut BufferedSerial<MemoryBus, U2, U2>, sr: u32, byte: u8) {
+        s.bus().preset(SR, sr);
+        s.bus().preset(DR, byte as u32);
+        s.on_interrupt();
+    }
+
+    #[test]
+    fn baud_rate() {
+        // 8.6875, mantissa 8 and fraction 11
+        assert_eq!(brr(16_000_000, 115_200), 0x8B);
+        assert_eq!(brr(42_000_000, 9_600), 4375);
+    }
+
+    #[test]
+    fn init_registers() {
+        let s = serial();
+        assert_eq!(s.bus().peek(BRR), 0x8B);
+        assert_eq!(s.bus().peek(CR3), 1);
+        assert_eq!(s.bus().peek(CR1), 1 << 13 | 1 << 5 | 1 << 3 | 1 << 2);
+    }
+
+    #[test]
+    fn receive_bytes() {
+        let mut s = serial();
+        assert_eq!(s.read(), Err(nb::Error::WouldBlock));
+
+        receive(&mut s, SR_RXNE.mask(), b'a');
+        receive(&mut s, SR_RXNE.mask(), b'b');
+        // the RX ring is full
+        receive(&mut s, SR_RXNE.mask(), b'c');
+
+        assert_eq!(s.received(), 2);
+        assert_eq!(s.read(), Ok(b'a'));
+        assert_eq!(s.read(), Ok(b'b'));
+        assert_eq!(s.read(), Err(nb::Error::WouldBlock));
+        assert_eq!(s.counters().rx_dropped, 1);
+    }
+
+    #[test]
+    fn receive_errors() {
+        let mut s = serial();
+        receive(&mut s, SR_RXNE.mask() | SR_ORE.mask(), b'a');
+        receive(&mut s, SR_RXNE.mask() | SR_NF.mask(), b'b');
+        receive(&mut s, SR_RXNE.mask() | SR_FE.mask(), b'c');
+        receive(&mut s, SR_RXNE.mask() | SR_PE.mask(), b'd');
+
+        // framing and parity errors drop the byte
+        assert_eq!(s.read(), Ok(b'a'));
+        assert_eq!(s.read(), Ok(b'b'));
+        assert_eq!(s.read(), Err(nb::Error::WouldBlock));
+        assert_eq!(
+            s.counters(),
+            Counters {
+                overrun: 1,
+                framing: 1,
+                noise: 1,
+                parity: 1,
+                ..Counters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn transmit_drains_ring() {
+        let mut s = serial();
+        s.write(b'h').unwrap();
+        s.write(b'i').unwrap();
+        assert_eq!(s.write(b'!'), Err(nb::Error::WouldBlock));
+        assert_ne!(s.bus().peek(CR1) & CR1_TXEIE.mask(), 0);
+
+        s.bus().preset(SR, SR_TXE.mask());
+        s.on_interrupt();
+        assert_eq!(s.bus().peek(DR), b'h' as u32);
+        s.on_interrupt();
+        assert_eq!(s.bus().peek(DR), b'i' as u32);
+        assert_eq!(s.flush(), Err(nb::Error::WouldBlock));
+
+        // ring empty, TXE interrupt off
+        s.on_interrupt();
+        assert_eq!(s.bus().peek(CR1) & CR1_TXEIE.mask(), 0);
+        s.bus().preset(SR, SR_TXE.mask() | SR_TC.mask());
+        assert_eq!(s.flush(), Ok(()));
+    }
+
+    #[test]
+    fn fmt_write_counts_dropped() {
+        let mut s = serial();
+        assert!(write!(s, "{}", 1234).is_err());
+        assert_eq!(s.pending(), 2);
+        assert_eq!(s.counters().tx_dropped, 2);
+    }
+}
@@ -0,0 +1,334 @@
+//! USART reception by DMA, in frames ended by an idle line
+//!
+//! With `bare9.rs`/`bare10.rs` (and `usart.rs`) each received byte takes an
+//! interrupt. Here a DMA stream moves the received bytes to a buffer, and an
+//! interrupt is taken only when the line goes idle (IDLE, a frame is complete)
+//! or the buffer is full (DMA transfer complete).
+//!
+//! There are two buffers, one receiving (owned by the DMA) and one spare. At
+//! the end of a frame the two are swapped, and the received buffer is handed
+//! out as an owned `Frame`, to be passed along to a task as a message (in the
+//! style of `rtfm_blinky_msg3.rs`). Once done, the task gives it back by
+//! `release`. A frame ending while the spare is still out is dropped (the
+//! buffer is reused), and counted.
+//!
+//! ``` ignore
+//! #[task(binds = USART2, priority = 2, resources = [RX], spawn = [frame])]
+//! fn usart2(cx: usart2::Context) {
+//!     if let Some(frame) = cx.resources.RX.on_usart_interrupt() {
+//!         cx.spawn.frame(frame).ok();
+//!     }
+//! }
+//! ```
+
+use core::mem::{self, offset_of};
+use core::ops::Deref;
+
+use crate::bus::RegisterBus;
+use crate::regs::{
+    Dma, DmaStream, Instance, Usart, CR1_IDLEIE, CR1_RE, CR1_UE, CR3_DMAR, ISR_ALL, ISR_TCIF,
+    ISR_TEIF, SR_IDLE, SR_ORE, SXCR_CHSEL, SXCR_EN, SXCR_MINC, SXCR_PL, SXCR_TCIE, SXCR_TEIE,
+};
+use crate::usart::brr;
+
+/// Max number of bytes of a frame
+pub const BUFFER_SIZE: usize = 64;
+
+pub type Buffer = [u8; BUFFER_SIZE];
+
+/// The DMA stream and channel of a peripheral request, RM0368 9.3.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request {
+    pub stream: usize,
+    pub channel: u8,
+}
+
+/// `USART2_RX`, DMA1 stream 5, channel 4
+pub const USART2_RX: Request = Request {
+    stream: 5,
+    channel: 4,
+};
+
+/// Frames and errors, since `new` (wrapping)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    /// frames handed out
+    pub frames: u32,
+    /// frames dropped, the spare buffer was not released in time
+    pub dropped: u32,
+    /// a byte arrived before the DMA read the previous one
+    pub overrun: u32,
+    /// DMA transfer errors
+    pub transfer: u32,
+}
+
+/// A received frame, owning its buffer
+pub struct Frame {
+    buf: &'static mut Buffer,
+    len: usize,
+}
+
+impl Frame {
+    /// The bytes received
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Deref for Frame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+pub struct DmaRx<B> {
+    bus: B,
+    // USART registers
+    sr: u32,
+    dr: u32,
+    brr: u32,
+    cr1: u32,
+    cr3: u32,
+    // DMA registers
+    isr: u32,
+    ifcr: u32,
+    flags: u8,
+    cr: u32,
+    ndtr: u32,
+    par: u32,
+    m0ar: u32,
+    channel: u8,
+    // owned by the DMA while the stream is enabled
+    active: &'static mut Buffer,
+    spare: Option<&'static mut Buffer>,
+    counters: Counters,
+}
+
+impl<B: RegisterBus> DmaRx<B> {
+    /// Reception of `usart` by `dma` (`request`), into two `buffers`
+    ///
+    /// The USART and DMA must be clocked (`RCC_APBxENR`, `RCC_AHB1ENR`), and
+    /// the RX pin in alternate function mode, configured by `init`.
+    pub fn new(
+        bus: B,
+        usart: &Instance<Usart>,
+        dma: &Instance<Dma>,
+        request: Request,
+        buffers: (&'static mut Buffer, &'static mut Buffer),
+    ) -> Self {
+        let stream = |offset| dma.reg(Dma::stream_offset(request.stream, offset));
+        let isr = dma.reg(Dma::isr_offset(request.stream));
+        DmaRx {
+            bus,
+            sr: usart.reg(offset_of!(Usart, SR)),
+            dr: usart.reg(offset_of!(Usart, DR)),
+            brr: usart.reg(offset_of!(Usart, BRR)),
+            cr1: usart.reg(offset_of!(Usart, CR1)),
+            cr3: usart.reg(offset_of!(Usart, CR3)),
+            isr,
+            // LIFCR/HIFCR are 8 bytes after LISR/HISR
+            ifcr: isr + (offset_of!(Dma, LIFCR) - offset_of!(Dma, LISR)) as u32,
+            flags: Dma::flags_offset(request.stream),
+            cr: stream(offset_of!(DmaStream, CR)),
+            ndtr: stream(offset_of!(DmaStream, NDTR)),
+            par: stream(offset_of!(DmaStream, PAR)),
+            m0ar: stream(offset_of!(DmaStream, M0AR)),
+            channel: request.channel,
+            active: buffers.0,
+            spare: Some(buffers.1),
+            counters: Counters::default(),
+        }
+    }
+
+    /// 8N1 reception at `baud`, from a peripheral clock of `pclk` Hz, with
+    /// an interrupt on IDLE, and the DMA stream started
+    pub fn init(&mut self, pclk: u32, baud: u32) {
+        self.bus.write_u32(self.brr, brr(pclk, baud));
+        self.bus.write_u32(self.cr3, CR3_DMAR.mask());
+        self.bus
+            .write_u32(self.cr1, CR1_UE.mask() | CR1_RE.mask() | CR1_IDLEIE.mask());
+        self.bus.write_u32(self.par, self.dr);
+        self.start();
+    }
+
+    /// To be called from the USART interrupt handler, a frame if the line
+    /// went idle
+    pub fn on_usart_interrupt(&mut self) -> Option<Frame> {
+        let sr = self.bus.read_u32(self.sr);
+        if sr & (SR_IDLE.mask() | SR_ORE.mask()) == 0 {
+            return None;
+        }
+        // reading DR (after SR) clears IDLE and ORE
+        self.bus.read_u32(self.dr);
+        if sr & SR_ORE.mask() != 0 {
+            self.counters.overrun = self.counters.overrun.wrapping_add(1);
+        }
+        if sr & SR_IDLE.mask() != 0 {
+            self.complete()
+        } else {
+            None
+        }
+    }
+
+    /// To be called from the DMA stream interrupt handler, a frame if the
+    /// buffer is full
+    pub fn on_dma_interrupt(&mut self) -> Option<Frame> {
+        let flags = self.bus.read_u32(self.isr) >> self.flags;
+        if flags & ISR_TEIF != 0 {
+            self.counters.transfer = self.counters.transfer.wrapping_add(1);
+        }
+        if flags & (ISR_TCIF | ISR_TEIF) != 0 {
+            self.complete()
+        } else {
+            None
+        }
+    }
+
+    /// Gives back the buffer of a handled frame
+    pub fn release(&mut self, frame: Frame) {
+        self.spare = Some(frame.buf);
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    // (re)starts the stream into the active buffer
+    fn start(&mut self) {
+        self.bus.write_u32(self.ifcr, ISR_ALL << self.flags);
+        self.bus
+            .write_u32(self.m0ar, self.active.as_mut_ptr() as usize as u32);
+        self.bus.write_u32(self.ndtr, BUFFER_SIZE as u32);
+        // peripheral to memory (DIR 0), bytes (PSIZE/MSIZE 0), high priority
+        self.bus.write_u32(
+            self.cr,
+            SXCR_CHSEL.val(self.channel as u32).bits()
+                | SXCR_PL.val(0b10).bits()
+                | SXCR_MINC.mask()
+                | SXCR_TCIE.mask()
+                | SXCR_TEIE.mask(),
+        );
+        self.bus.modify_u32(self.cr, 0, SXCR_EN.mask());
+    }
+
+    // stops the stream, and hands out the received bytes (if any)
+    fn complete(&mut self) -> Option<Frame> {
+        self.bus.modify_u32(self.cr, SXCR_EN.mask(), 0);
+        // the current transfer is finished before EN reads 0, RM0368 9.3.17
+        while self.bus.read_u32(self.cr) & SXCR_EN.mask() != 0 {}
+
+        let len = BUFFER_SIZE - self.bus.read_u32(self.ndtr) as usize;
+        let frame = if len == 0 {
+            None
+        } else if let Some(spare) = self.spare.take() {
+            let buf = mem::replace(&mut self.active, spare);
+            self.counters.frames = self.counters.frames.wrapping_add(1);
+            Some(Frame { buf, len })
+        } else {
+            self.counters.dropped = self.counters.dropped.wrapping_add(1);
+            None
+        };
+
+        self.start();
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemoryBus;
+    use crate::regs::{DMA1, USART2};
+
+    const USART2_SR: u32 = 0x4000_4400;
+    const USART2_DR: u32 = 0x4000_4404;
+    const USART2_CR3: u32 = 0x4000_4414;
+    const DMA1_HISR: u32 = 0x4002_6004;
+    const DMA1_HIFCR: u32 = 0x4002_600C;
+    const DMA1_S5CR: u32 = 0x4002_6088;
+    const DMA1_S5NDTR: u32 = 0x4002_608C;
+    const DMA1_S5PAR: u32 = 0x4002_6090;
+    const DMA1_S5M0AR: u32 = 0x4002_6094;
+
+    fn buffer() -> &'static mut Buffer {
+        Box::leak(Box::new([0; BUFFER_SIZE]))
+    }
+
+    fn rx() -> DmaRx<MemoryBus> {
+        let mut rx = DmaRx::new(
+            MemoryBus::new(),
+            &USART2,
+            &DMA1,
+            USART2_RX,
+            (buffer(), buffer()),
+        );
+        rx.init(16_000_000, 115_200);
+        rx
+    }
+
+    // `data` received by the DMA, then the line goes idle
+    fn receive(rx: &mut DmaRx<MemoryBus>, data: &[u8]) -> Option<Frame> {
+        let addr = rx.bus().peek(DMA1_S5M0AR);
+        assert_eq!(addr, rx.active.as_ptr() as usize as u32);
+        rx.active[..data.len()].copy_from_slice(data);
+        rx.bus()
+            .preset(DMA1_S5NDTR, (BUFFER_SIZE - data.len()) as u32);
+        rx.bus().preset(USART2_SR, SR_IDLE.mask());
+        rx.on_usart_interrupt()
+    }
+
+    #[test]
+    fn stream_setup() {
+        let rx = rx();
+        let bus = rx.bus();
+        assert_eq!(bus.peek(USART2_CR3), 1 << 6);
+        assert_eq!(bus.peek(DMA1_S5PAR), USART2_DR);
+        assert_eq!(bus.peek(DMA1_S5NDTR), BUFFER_SIZE as u32);
+        // stream 5 flags at bit 6 of HIFCR
+        assert_eq!(bus.peek(DMA1_HIFCR), 0b111101 << 6);
+        assert_eq!(
+            bus.peek(DMA1_S5CR),
+            4 << 25 | 0b10 << 16 | 1 << 10 | 1 << 4 | 1 << 2 | 1
+        );
+    }
+
+    #[test]
+    fn frames_swap_buffers() {
+        let mut rx = rx();
+        let frame = receive(&mut rx, b"abcd").unwrap();
+        assert_eq!(&*frame, b"abcd");
+
+        // the spare is out, the next frame is dropped
+        assert!(receive(&mut rx, b"ef").is_none());
+        assert_eq!(rx.counters().dropped, 1);
+
+        rx.release(frame);
+        assert_eq!(receive(&mut rx, b"gh").unwrap().as_slice(), b"gh");
+        assert_eq!(rx.counters().frames, 2);
+    }
+
+    #[test]
+    fn idle_without_data() {
+        let mut rx = rx();
+        assert!(receive(&mut rx, b"").is_none());
+        assert_eq!(rx.counters(), Counters::default());
+    }
+
+    #[test]
+    fn buffer_full() {
+        let mut rx = rx();
+        rx.bus().preset(DMA1_S5NDTR, 0);
+        rx.bus().preset(DMA1_HISR, ISR_TCIF << 6);
+        let frame = rx.on_dma_interrupt().unwrap();
+        assert_eq!(frame.len(), BUFFER_SIZE);
+        // restarted
+        assert_eq!(rx.bus().peek(DMA1_S5NDTR), BUFFER_SIZE as u32);
+        assert_eq!(rx.bus().peek(DMA1_S5CR) & 1, 1);
+    }
+}
@@ -0,0 +1,113 @@
+//! A vector table in RAM, for the interrupts missing from the device crate
+//!
+//! `stm32f4` 0.8 (the device crate of `stm32f4xx-hal` 0.6) has no DMA
+//! interrupts for the STM32F401, their vectors are reserved (0), so no RTFM
+//! task can be bound to them. Instead, the task is bound to a free interrupt
+//! (one that is never pended otherwise, e.g., `EXTI2`), and `route` switches
+//! to a copy of the vector table in RAM (`VTOR`), where the DMA stream has the
+//! handler of the free interrupt. The stream gets the priority of the free
+//! interrupt (set by RTFM before `init`), so the resources of the task are
+//! locked as usual (`rtfm_serial_dma.rs`):
+//!
+//! ``` ignore
+//! #[init]
+//! fn init(cx: init::Context) -> init::LateResources {
+//!     static mut VECTORS: Table = Table::new();
+//!     vectors::route(&unsafe { Mmio::new() }, VECTORS, irq::DMA1_STREAM5, irq::EXTI2);
+//!     ..
+//! }
+//!
+//! #[task(binds = EXTI2, priority = 2, resources = [RX], spawn = [received])]
+//! fn dma1_stream5(cx: dma1_stream5::Context) { .. }
+//! ```
+
+use core::sync::atomic::{self, Ordering};
+
+use crate::bus::RegisterBus;
+
+/// Vectors of the STM32F401, 16 exceptions and 85 interrupts (RM0368 10.2)
+pub const VECTORS: usize = 16 + 85;
+
+/// Interrupt positions (IRQ numbers), RM0368 Table 38
+pub mod irq {
+    pub const EXTI2: u8 = 8;
+    pub const EXTI3: u8 = 9;
+    pub const DMA1_STREAM5: u8 = 16;
+    pub const DMA2_STREAM0: u8 = 56;
+}
+
+#[rustfmt::skip]
+pub mod address {
+    pub const NVIC_ISER: u32    = 0xE000_E100;
+    pub const NVIC_IPR: u32     = 0xE000_E400;
+    pub const VTOR: u32         = 0xE000_ED08;
+    /// the vector table while `VTOR` is 0 (the flash is aliased at 0)
+    pub const FLASH: u32        = 0x0800_0000;
+}
+
+use address::*;
+
+/// A vector table, aligned for `VTOR` (to its size, rounded up to a power of
+/// two)
+#[repr(C, align(512))]
+pub struct Table([u32; VECTORS]);
+
+impl Table {
+    pub const fn new() -> Self {
+        Table([0; VECTORS])
+    }
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Switches to `table`, a copy of the vector table in use where interrupt
+/// `from` has the handler of `to`, and enables `from` at the priority of `to`
+pub fn route<B: RegisterBus>(bus: &B, table: &'static mut Table, from: u8, to: u8) {
+    let base = match bus.read_u32(VTOR) {
+        0 => FLASH,
+        vtor => vtor,
+    };
+    for (i, vector) in table.0.iter_mut().enumerate() {
+        *vector = bus.read_u32(base + 4 * i as u32);
+    }
+    table.0[16 + from as usize] = table.0[16 + to as usize];
+    // the table is written before the core fetches from it
+    atomic::fence(Ordering::SeqCst);
+    bus.write_u32(VTOR, table.0.as_ptr() as usize as u32);
+
+    // 8 bits of priority per interrupt, 4 per register
+    let ipr = |irq: u8| (NVIC_IPR + (irq as u32 & !3), 8 * (irq as u32 % 4));
+    let (reg, shift) = ipr(to);
+    let priority = (bus.read_u32(reg) >> shift) & 0xFF;
+    let (reg, shift) = ipr(from);
+    bus.modify_u32(reg, 0xFF << shift, priority << shift);
+    bus.write_u32(NVIC_ISER + 4 * (from as u32 / 32), 1 << (from % 32));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemoryBus;
+
+    #[test]
+    fn route_dma() {
+        let bus = MemoryBus::new();
+        // the handler of EXTI3, at RTFM priority 2 (of 16)
+        bus.preset(FLASH + 4 * (16 + 9), 0x0800_0401);
+        bus.preset(NVIC_IPR + 8, 0xE0 << 8);
+        let table = Box::leak(Box::new(Table::new()));
+        let at = table as *const Table;
+
+        route(&bus, table, irq::DMA2_STREAM0, irq::EXTI3);
+        assert_eq!(bus.peek(VTOR), at as usize as u32);
+        let table = unsafe { &*at };
+        assert_eq!(table.0[16 + 56], 0x0800_0401);
+        assert_eq!(table.0[16 + 9], 0x0800_0401);
+        assert_eq!(bus.peek(NVIC_IPR + 56), 0xE0);
+        assert_eq!(bus.peek(NVIC_ISER + 4), 1 << 24);
+    }
+}
@@ -0,0 +1,297 @@
+//! Volatile cells with a bitfield API
+//!
+//! The `VolatileCell` of `bare5.rs`, extended with `Field` descriptors for
+//! reading and modifying register fields. A `Field` is checked on creation
+//! (`offset + width <= 32`), in a `const` this check is done at compile time:
+//!
+//! ``` ignore
+//! const MODER5: Field = Field::new(5 * 2, 2);
+//! const OUTPUT: FieldValue = MODER5.val(0b01); // 0b101 would not compile
+//!
+//! gpioa.MODER.modify_value(OUTPUT);
+//! gpioa.MODER.modify_field(MODER5, 0b01);
+//! ```
+
+use core::{cell, ptr};
+
+/// A bitfield of a 32 bit register, `width` bits starting at bit `offset`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    offset: u8,
+    width: u8,
+}
+
+impl Field {
+    /// Panics if the field is empty or does not fit in 32 bits
+    pub const fn new(offset: u8, width: u8) -> Self {
+        match Field::checked(offset, width) {
+            Some(field) => field,
+            None => panic!("field out of range"),
+        }
+    }
+
+    /// `None` if the field is empty or does not fit in 32 bits
+    pub const fn checked(offset: u8, width: u8) -> Option<Self> {
+        if width == 0 || offset as u32 + width as u32 > 32 {
+            None
+        } else {
+            Some(Field { offset, width })
+        }
+    }
+
+    /// A single bit field
+    pub const fn bit(offset: u8) -> Self {
+        Field::new(offset, 1)
+    }
+
+    pub const fn offset(self) -> u8 {
+        self.offset
+    }
+
+    pub const fn width(self) -> u8 {
+        self.width
+    }
+
+    /// The largest value the field can hold
+    pub const fn max(self) -> u32 {
+        u32::MAX >> (32 - self.width as u32)
+    }
+
+    /// The bits of the register covered by the field
+    pub const fn mask(self) -> u32 {
+        self.max() << self.offset
+    }
+
+    /// A value for the field, panics if `value` does not fit
+    pub const fn val(self, value: u32) -> FieldValue {
+        if value > self.max() {
+            panic!("value does not fit in field");
+        }
+        FieldValue { field: self, value }
+    }
+}
+
+/// A field together with a value known to fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldValue {
+    field: Field,
+    value: u32,
+}
+
+impl FieldValue {
+    pub const fn field(self) -> Field {
+        self.field
+    }
+
+    pub const fn value(self) -> u32 {
+        self.value
+    }
+
+    /// The value in place, shifted to the field offset
+    pub const fn bits(self) -> u32 {
+        self.value << self.field.offset
+    }
+}
+
+/// The value given does not fit in the field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow {
+    pub field: Field,
+    pub value: u32,
+}
+
+#[repr(transparent)]
+pub struct VolatileCell<T> {
+    value: cell::UnsafeCell<T>,
+}
+
+impl<T> VolatileCell<T> {
+    pub const fn new(value: T) -> Self {
+        VolatileCell {
+            value: cell::UnsafeCell::new(value),
+        }
+    }
+
+    #[inline(always)]
+    pub fn read(&self) -> T
+    where
+        T: Copy,
+    {
+        unsafe { ptr::read_volatile(self.value.get()) }
+    }
+
+    #[inline(always)]
+    pub fn write(&self, value: T)
+    where
+        T: Copy,
+    {
+        unsafe { ptr::write_volatile(self.value.get(), value) }
+    }
+}
+
+impl VolatileCell<u32> {
+    /// Read the value of `field`
+    #[inline(always)]
+    pub fn read_field(&self, field: Field) -> u32 {
+        (self.read() >> field.offset) & field.max()
+    }
+
+    /// Read, set `field` to `value` and write back, other bits are kept
+    ///
+    /// Bits of `value` not fitting in the field are dropped,
+    /// use `try_modify_field` or `modify_value` to catch that.
+    #[inline(always)]
+    pub fn modify_field(&self, field: Field, value: u32) {
+        let r = self.read() & !field.mask(); // read and mask initial value
+        self.write(r | (value & field.max()) << field.offset); // write modified value
+    }
+
+    /// As `modify_field`, but leaves the cell untouched if `value` does not fit
+    #[inline(always)]
+    pub fn try_modify_field(&self, field: Field, value: u32) -> Result<(), Overflow> {
+        if value > field.max() {
+            return Err(Overflow { field, value });
+        }
+        self.modify_field(field, value);
+        Ok(())
+    }
+
+    /// Read, set the field to the (checked) value and write back
+    #[inline(always)]
+    pub fn modify_value(&self, value: FieldValue) {
+        self.modify_field(value.field, value.value);
+    }
+
+    /// The `bare5.rs` modify, `field` given as bit offset and width
+    ///
+    /// Panics (in both dev and release) if the field does not fit in 32 bits.
+    #[inline(always)]
+    pub fn modify(&self, offset: u8, width: u8, value: u32) {
+        self.modify_field(Field::new(offset, width), value);
+    }
+
+    /// Set the `mask` bits (read, modify, write)
+    #[inline(always)]
+    pub fn set_bits(&self, mask: u32) {
+        self.write(self.read() | mask);
+    }
+
+    /// Clear the `mask` bits (read, modify, write)
+    #[inline(always)]
+    pub fn clear_bits(&self, mask: u32) {
+        self.write(self.read() & !mask);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the `test` of `bare5.rs`
+    #[test]
+    fn bare5_test() {
+        let t: VolatileCell<u32> = VolatileCell::new(0);
+        t.write(0);
+        assert!(t.read() == 0);
+        t.modify(3, 3, 0b10101);
+        //
+        //     10101
+        //    ..0111000
+        //    ---------
+        //    000101000
+        assert!(t.read() == 0b101 << 3);
+        t.modify(4, 3, 0b10001);
+        //    000101000
+        //      111
+        //      001
+        //    000011000
+        assert!(t.read() == 0b011 << 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bare5_test_overshift() {
+        let t: VolatileCell<u32> = VolatileCell::new(0);
+        t.modify(32, 3, 1);
+    }
+
+    #[test]
+    fn field_bounds() {
+        assert_eq!(Field::checked(0, 0), None);
+        assert_eq!(Field::checked(30, 3), None);
+        assert_eq!(Field::checked(32, 1), None);
+        assert!(Field::checked(29, 3).is_some());
+        assert_eq!(Field::new(0, 32).mask(), u32::MAX);
+        assert_eq!(Field::new(31, 1).mask(), 1 << 31);
+        assert_eq!(Field::new(10, 2).mask(), 0b11 << 10);
+        assert_eq!(Field::new(10, 2).max(), 0b11);
+    }
+
+    #[test]
+    fn read_and_modify_field() {
+        const MODER5: Field = Field::new(5 * 2, 2);
+        let t = VolatileCell::new(0xA800_0000);
+
+        t.modify_field(MODER5, 0b01);
+        assert_eq!(t.read(), 0xA800_0000 | 0b01 << 10);
+        assert_eq!(t.read_field(MODER5), 0b01);
+
+        t.modify_field(MODER5, 0b10);
+        assert_eq!(t.read(), 0xA800_0000 | 0b10 << 10);
+        assert_eq!(t.read_field(Field::new(26, 6)), 0b10_1010);
+    }
+
+    #[test]
+    fn full_width_field() {
+        let t = VolatileCell::new(0);
+        t.modify_field(Field::new(0, 32), 0xDEAD_BEEF);
+        assert_eq!(t.read(), 0xDEAD_BEEF);
+        assert_eq!(t.read_field(Field::new(0, 32)), 0xDEAD_BEEF);
+    }
+
+    #[test]
+    fn value_overflow() {
+        let f = Field::new(4, 3);
+        let t = VolatileCell::new(0xFFFF_FFFF);
+
+        assert_eq!(
+            t.try_modify_field(f, 0b1000),
+            Err(Overflow {
+                field: f,
+                value: 0b1000
+            })
+        );
+        assert_eq!(t.read(), 0xFFFF_FFFF);
+
+        assert_eq!(t.try_modify_field(f, 0b010), Ok(()));
+        assert_eq!(t.read(), !(0b101 << 4));
+    }
+
+    #[test]
+    fn const_field_value() {
+        const ODR5: Field = Field::bit(5);
+        const HIGH: FieldValue = ODR5.val(1);
+        let t = VolatileCell::new(0);
+
+        t.modify_value(HIGH);
+        assert_eq!(t.read(), 1 << 5);
+        t.modify_value(ODR5.val(0));
+        assert_eq!(t.read(), 0);
+        assert_eq!(Field::new(25, 3).val(4).bits(), 4 << 25);
+    }
+
+    #[test]
+    #[should_panic]
+    fn field_value_overflow() {
+        Field::new(0, 2).val(0b100);
+    }
+
+    #[test]
+    fn set_and_clear_bits() {
+        let t = VolatileCell::new(0b1001);
+        t.set_bits(0b0110);
+        assert_eq!(t.read(), 0b1111);
+        t.clear_bits(0b0101);
+        assert_eq!(t.read(), 0b1010);
+    }
+}
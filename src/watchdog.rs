@@ -0,0 +1,532 @@
+//! Watchdogs (IWDG and WWDG), and a task liveness monitor feeding them
+//!
+//! - `Iwdg`, the independent watchdog (RM0368 17), clocked by the LSI
+//!   (32 kHz), resets the MCU unless fed within its timeout. Once started it
+//!   cannot be stopped, but by a reset.
+//! - `Wwdg`, the window watchdog (RM0368 18), clocked by PCLK1, also resets
+//!   the MCU if fed too early (before the window opens).
+//!
+//! Both are configured from a timeout in milliseconds (`IwdgConfig`,
+//! `WwdgConfig`).
+//!
+//! Feeding the dog from a periodic task only tells that this task runs. The
+//! `Monitor` feeds the dog only when each registered task has checked in
+//! since the last feed, so a task hung (e.g., the `echo` of `bare10.rs`,
+//! spinning in `block!(tx.write(byte))`), or starved by higher priority tasks,
+//! lets the dog reset the MCU:
+//!
+//! ``` ignore
+//! // init
+//! let echo = monitor.register("echo").unwrap();
+//!
+//! // in `echo`
+//! cx.resources.MONITOR.lock(|monitor| monitor.check_in(echo));
+//!
+//! // in a periodic supervisor task
+//! cx.resources.MONITOR.feed(cx.resources.DOG);
+//! ```
+//!
+//! On the next boot, the reset cause tells a watchdog reset (see `reset.rs`).
+
+use core::mem::offset_of;
+
+use crate::bus::RegisterBus;
+use crate::regs::{
+    Iwdg as IwdgRegs, Rcc, Wwdg as WwdgRegs, APB1ENR_WWDGEN, CFR_W, CFR_WDGTB, IWDG,
+    KR_ENABLE_ACCESS, KR_RELOAD, KR_START, PR_PR, RCC, RLR_RL, WWDG, WWDG_CR_T, WWDG_CR_WDGA,
+};
+
+#[rustfmt::skip]
+pub mod address {
+    use super::*;
+
+    pub const IWDG_KR: u32          = IWDG.reg(offset_of!(IwdgRegs, KR));
+    pub const IWDG_PR: u32          = IWDG.reg(offset_of!(IwdgRegs, PR));
+    pub const IWDG_RLR: u32         = IWDG.reg(offset_of!(IwdgRegs, RLR));
+    pub const IWDG_SR: u32          = IWDG.reg(offset_of!(IwdgRegs, SR));
+    pub const WWDG_CR: u32          = WWDG.reg(offset_of!(WwdgRegs, CR));
+    pub const WWDG_CFR: u32         = WWDG.reg(offset_of!(WwdgRegs, CFR));
+    pub const RCC_APB1ENR: u32      = RCC.reg(offset_of!(Rcc, APB1ENR));
+}
+
+use address::*;
+
+/// LSI frequency (typical, it varies from 17 to 47 kHz, RM0368 / datasheet)
+pub const LSI_HZ: u32 = 32_000;
+
+/// Max number of tasks registered with a `Monitor`
+pub const MAX_TASKS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// timeout out of the range of the watchdog
+    Timeout,
+    /// window not shorter than the timeout
+    Window,
+    /// `MAX_TASKS` already registered
+    Full,
+}
+
+/// A watchdog, to be fed before it resets the MCU
+pub trait Feed {
+    /// Reloads the watchdog, if it can be right now. Returns whether reloaded.
+    fn feed(&mut self) -> bool;
+}
+
+/// Prescaler and reload of the IWDG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IwdgConfig {
+    /// `PR`, dividing the LSI by `4 << prescaler`
+    pub prescaler: u8,
+    /// `RLR`, the counter counts down from `reload` to 0
+    pub reload: u16,
+}
+
+impl IwdgConfig {
+    /// The finest resolution giving a timeout of `ms` (from 1 ms to 32.768 s)
+    pub fn from_millis(ms: u32) -> Result<Self, Error> {
+        let ticks = ms as u64 * LSI_HZ as u64 / 1000;
+        let prescaler = (0..=6)
+            .find(|&prescaler| ticks <= (RLR_RL.max() as u64 + 1) * (4 << prescaler))
+            .ok_or(Error::Timeout)?;
+        let count = ticks / (4 << prescaler);
+        if count == 0 {
+            return Err(Error::Timeout);
+        }
+        Ok(IwdgConfig {
+            prescaler,
+            reload: count as u16 - 1,
+        })
+    }
+
+    /// Timeout in microseconds (at `LSI_HZ`)
+    pub fn timeout_us(self) -> u32 {
+        let ticks = (4u64 << self.prescaler) * (self.reload as u64 + 1);
+        (ticks * 1_000_000 / LSI_HZ as u64) as u32
+    }
+}
+
+/// Time base, counter and window of the WWDG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WwdgConfig {
+    /// `WDGTB`, the counter is clocked by PCLK1 / 4096 / `1 << timebase`
+    pub timebase: u8,
+    /// `T`, loaded on a feed, resets when counting down from 0x40 to 0x3F
+    pub counter: u8,
+    /// `W`, a feed while the counter is above `window` resets
+    pub window: u8,
+}
+
+impl WwdgConfig {
+    /// The finest resolution giving a timeout of `timeout_ms`, fed no earlier
+    /// than `window_ms` after the last feed (0 for no window), from a PCLK1 of
+    /// `pclk1` Hz
+    pub fn from_millis(pclk1: u32, timeout_ms: u32, window_ms: u32) -> Result<Self, Error> {
+        // PCLK1 cycles, a tick of the counter is `4096 << timebase` cycles
+        let cycles = |ms: u32| ms as u64 * pclk1 as u64 / 1000;
+        let timebase = (0..=3)
+            .find(|&timebase| cycles(timeout_ms) <= 64 * (4096 << timebase))
+            .ok_or(Error::Timeout)?;
+        let tick = 4096u64 << timebase;
+        let count = cycles(timeout_ms) / tick;
+        if count == 0 {
+            return Err(Error::Timeout);
+        }
+        // the window opens once the counter is down `window_ms`, rounded up
+        let closed = cycles(window_ms).div_ceil(tick);
+        if closed >= count {
+            return Err(Error::Window);
+        }
+        let counter = 0x3F + count as u8;
+        Ok(WwdgConfig {
+            timebase,
+            counter,
+            window: counter - closed as u8,
+        })
+    }
+
+    /// Timeout in microseconds, from a PCLK1 of `pclk1` Hz
+    pub fn timeout_us(self, pclk1: u32) -> u32 {
+        self.ticks_us(pclk1, self.counter - 0x3F)
+    }
+
+    /// Time from a feed until the window opens, in microseconds
+    pub fn window_us(self, pclk1: u32) -> u32 {
+        self.ticks_us(pclk1, self.counter - self.window)
+    }
+
+    fn ticks_us(self, pclk1: u32, ticks: u8) -> u32 {
+        ((4096u64 << self.timebase) * ticks as u64 * 1_000_000 / pclk1 as u64) as u32
+    }
+}
+
+/// The independent watchdog
+pub struct Iwdg<B> {
+    bus: B,
+}
+
+impl<B: RegisterBus> Iwdg<B> {
+    pub fn new(bus: B) -> Self {
+        Iwdg { bus }
+    }
+
+    /// Starts the watchdog (and the LSI), and loads the counter
+    pub fn start(&mut self, config: IwdgConfig) {
+        // the LSI must run to update the prescaler and reload
+        self.bus.write_u32(IWDG_KR, KR_START);
+        self.bus.write_u32(IWDG_KR, KR_ENABLE_ACCESS);
+        self.bus
+            .write_u32(IWDG_PR, PR_PR.val(config.prescaler as u32).bits());
+        self.bus
+            .write_u32(IWDG_RLR, RLR_RL.val(config.reload as u32).bits());
+        // PVU and RVU, the update is in progress
+        while self.bus.read_u32(IWDG_SR) != 0 {}
+        self.feed();
+    }
+
+    /// Reloads the counter
+    pub fn feed(&mut self) {
+        self.bus.write_u32(IWDG_KR, KR_RELOAD);
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+}
+
+impl<B: RegisterBus> Feed for Iwdg<B> {
+    fn feed(&mut self) -> bool {
+        Iwdg::feed(self);
+        true
+    }
+}
+
+/// The window watchdog
+pub struct Wwdg<B> {
+    bus: B,
+    config: WwdgConfig,
+}
+
+impl<B: RegisterBus> Wwdg<B> {
+    pub fn new(bus: B, config: WwdgConfig) -> Self {
+        Wwdg { bus, config }
+    }
+
+    /// Clocks and starts the watchdog
+    pub fn start(&mut self) {
+        self.bus.modify_u32(RCC_APB1ENR, 0, APB1ENR_WWDGEN.mask());
+        let cfr = CFR_W.val(self.config.window as u32).bits()
+            | CFR_WDGTB.val(self.config.timebase as u32).bits();
+        self.bus.write_u32(WWDG_CFR, cfr);
+        self.feed();
+    }
+
+    /// Current value of the down counter
+    pub fn counter(&self) -> u8 {
+        (self.bus.read_u32(WWDG_CR) & WWDG_CR_T.mask()) as u8
+    }
+
+    /// The window is open, a feed does not reset
+    pub fn is_open(&self) -> bool {
+        self.counter() <= self.config.window
+    }
+
+    /// Reloads the counter (resets the MCU if the window is not yet open)
+    pub fn feed(&mut self) {
+        let cr = WWDG_CR_WDGA.mask() | WWDG_CR_T.val(self.config.counter as u32).bits();
+        self.bus.write_u32(WWDG_CR, cr);
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+}
+
+impl<B: RegisterBus> Feed for Wwdg<B> {
+    /// Feeds only once the window is open, else waits for the next call
+    fn feed(&mut self) -> bool {
+        if !self.is_open() {
+            return false;
+        }
+        Wwdg::feed(self);
+        true
+    }
+}
+
+/// A task registered with a `Monitor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Task(u8);
+
+/// Feeds a watchdog once each registered task has checked in
+pub struct Monitor {
+    names: [&'static str; MAX_TASKS],
+    registered: u8,
+    // a bit per task, set by `check_in`, cleared on a feed
+    alive: u32,
+}
+
+impl Monitor {
+    pub const fn new() -> Self {
+        Monitor {
+            names: [""; MAX_TASKS],
+            registered: 0,
+            alive: 0,
+        }
+    }
+
+    /// Registers a task (by its name, for `missing`)
+    pub fn register(&mut self, name: &'static str) -> Result<Task, Error> {
+        let n = self.registered as usize;
+        if n == MAX_TASKS {
+            return Err(Error::Full);
+        }
+        self.names[n] = name;
+        self.registered += 1;
+        Ok(Task(n as u8))
+    }
+
+    /// The task is alive, to be called on each run of the task
+    pub fn check_in(&mut self, task: Task) {
+        self.alive |= 1 << task.0;
+    }
+
+    /// Each task checked in since the last feed
+    pub fn all_alive(&self) -> bool {
+        self.alive == self.all()
+    }
+
+    /// The tasks that have not checked in since the last feed
+    pub fn missing(&self) -> impl Iterator<Item = &'static str> + '_ {
+        let alive = self.alive;
+        self.names[..self.registered as usize]
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| alive & 1 << i == 0)
+            .map(|(_, name)| *name)
+    }
+
+    /// Feeds `dog` if each task checked in, then waits for them to check in
+    /// again. Returns whether fed (the check-ins are kept while the dog
+    /// refuses, e.g., a closed `Wwdg` window).
+    pub fn feed(&mut self, dog: &mut impl Feed) -> bool {
+        if !self.all_alive() || !dog.feed() {
+            return false;
+        }
+        self.alive = 0;
+        true
+    }
+
+    fn all(&self) -> u32 {
+        match self.registered {
+            32 => !0,
+            n => (1 << n) - 1,
+        }
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Access::*, MemoryBus};
+
+    #[test]
+    fn iwdg_config() {
+        // 1 ms, 32 ticks of the LSI, 8 counts by 4
+        let c = IwdgConfig::from_millis(1).unwrap();
+        assert_eq!(
+            c,
+            IwdgConfig {
+                prescaler: 0,
+                reload: 7
+            }
+        );
+        assert_eq!(c.timeout_us(), 1000);
+
+        // 500 ms, 16000 ticks, 4000 counts by 4 (fits the 12 bit reload)
+        let c = IwdgConfig::from_millis(500).unwrap();
+        assert_eq!(
+            c,
+            IwdgConfig {
+                prescaler: 0,
+                reload: 3999
+            }
+        );
+
+        // 1 s, 32000 ticks, 4000 counts by 8
+        assert_eq!(
+            IwdgConfig::from_millis(1000),
+            Ok(IwdgConfig {
+                prescaler: 1,
+                reload: 3999
+            })
+        );
+
+        let max = IwdgConfig::from_millis(32_768).unwrap();
+        assert_eq!(
+            max,
+            IwdgConfig {
+                prescaler: 6,
+                reload: 4095
+            }
+        );
+        assert_eq!(max.timeout_us(), 32_768_000);
+
+        assert_eq!(IwdgConfig::from_millis(0), Err(Error::Timeout));
+        assert_eq!(IwdgConfig::from_millis(32_769), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn wwdg_config() {
+        // 16 MHz, a tick of 256 us (by 4096)
+        let c = WwdgConfig::from_millis(16_000_000, 10, 0).unwrap();
+        assert_eq!(
+            c,
+            WwdgConfig {
+                timebase: 0,
+                counter: 0x3F + 39,
+                window: 0x3F + 39,
+            }
+        );
+        assert_eq!(c.timeout_us(16_000_000), 9984);
+        assert_eq!(c.window_us(16_000_000), 0);
+
+        // a tick of 2048 us (by 4096 * 8), the window opens after 20 ms
+        let c = WwdgConfig::from_millis(16_000_000, 100, 20).unwrap();
+        assert_eq!(c.timebase, 3);
+        assert_eq!(c.counter, 0x3F + 48);
+        assert_eq!(c.window, c.counter - 10);
+        assert!(c.window_us(16_000_000) >= 20_000);
+
+        assert_eq!(
+            WwdgConfig::from_millis(16_000_000, 200, 0),
+            Err(Error::Timeout)
+        );
+        assert_eq!(
+            WwdgConfig::from_millis(16_000_000, 10, 10),
+            Err(Error::Window)
+        );
+        // 99 ms rounds up to 49 ticks, above the 48 of the timeout
+        assert_eq!(
+            WwdgConfig::from_millis(16_000_000, 100, 99),
+            Err(Error::Window)
+        );
+    }
+
+    #[test]
+    fn iwdg_start() {
+        let mut dog = Iwdg::new(MemoryBus::new());
+        dog.start(IwdgConfig::from_millis(1000).unwrap());
+        dog.feed();
+
+        assert_eq!(IWDG_KR, 0x4000_3000);
+        assert_eq!(
+            &dog.bus().accesses()[..],
+            &[
+                Write(IWDG_KR, 0xCCCC),
+                Write(IWDG_KR, 0x5555),
+                Write(IWDG_PR, 1),
+                Write(IWDG_RLR, 3999),
+                Read(IWDG_SR, 0),
+                Write(IWDG_KR, 0xAAAA),
+                Write(IWDG_KR, 0xAAAA),
+            ]
+        );
+    }
+
+    #[test]
+    fn wwdg_window() {
+        let config = WwdgConfig::from_millis(16_000_000, 100, 20).unwrap();
+        let mut dog = Wwdg::new(MemoryBus::new(), config);
+        dog.start();
+
+        assert_eq!(dog.bus().peek(RCC_APB1ENR), 1 << 11);
+        assert_eq!(dog.bus().peek(WWDG_CFR), 3 << 7 | config.window as u32);
+        assert_eq!(dog.bus().peek(WWDG_CR), 0x80 | config.counter as u32);
+
+        // right after the feed, the window is closed
+        assert!(!dog.is_open());
+        let start = dog.bus().accesses().len();
+        assert!(!Feed::feed(&mut dog));
+        assert_eq!(
+            &dog.bus().accesses()[start..],
+            &[Read(WWDG_CR, 0x80 | 0x6F)]
+        );
+
+        // counted down into the window
+        dog.bus().preset(WWDG_CR, 0x80 | config.window as u32);
+        assert!(dog.is_open());
+        assert!(Feed::feed(&mut dog));
+        assert_eq!(dog.counter(), config.counter);
+    }
+
+    // a dog counting its feeds
+    struct Dog(u32);
+
+    impl Feed for Dog {
+        fn feed(&mut self) -> bool {
+            self.0 += 1;
+            true
+        }
+    }
+
+    #[test]
+    fn monitor() {
+        let mut monitor = Monitor::new();
+        let blink = monitor.register("blink").unwrap();
+        let echo = monitor.register("echo").unwrap();
+        let mut dog = Dog(0);
+
+        monitor.check_in(blink);
+        assert!(!monitor.feed(&mut dog));
+        assert_eq!(monitor.missing().collect::<Vec<_>>(), ["echo"]);
+
+        monitor.check_in(echo);
+        monitor.check_in(echo);
+        assert!(monitor.feed(&mut dog));
+        assert_eq!(dog.0, 1);
+
+        // a new round, each task checks in again
+        assert_eq!(monitor.missing().collect::<Vec<_>>(), ["blink", "echo"]);
+        monitor.check_in(echo);
+        assert!(!monitor.feed(&mut dog));
+        assert_eq!(dog.0, 1);
+    }
+
+    #[test]
+    fn monitor_closed_window() {
+        let config = WwdgConfig::from_millis(16_000_000, 100, 20).unwrap();
+        let mut dog = Wwdg::new(MemoryBus::new(), config);
+        dog.start();
+        let mut monitor = Monitor::new();
+        let task = monitor.register("task").unwrap();
+        monitor.check_in(task);
+
+        // not fed while the window is closed, the check-in is kept
+        assert!(!monitor.feed(&mut dog));
+        assert!(monitor.all_alive());
+        assert_eq!(monitor.missing().count(), 0);
+
+        dog.bus().preset(WWDG_CR, 0x80 | config.window as u32);
+        assert!(monitor.feed(&mut dog));
+        assert_eq!(dog.counter(), config.counter);
+        assert!(!monitor.all_alive());
+    }
+
+    #[test]
+    fn monitor_full() {
+        let mut monitor = Monitor::new();
+        for _ in 0..MAX_TASKS {
+            let task = monitor.register("task").unwrap();
+            monitor.check_in(task);
+        }
+        assert_eq!(monitor.register("one too many"), Err(Error::Full));
+        assert!(monitor.all_alive());
+        assert!(monitor.feed(&mut Dog(0)));
+    }
+}